@@ -0,0 +1,5 @@
+//! Print the canonicalization test vectors as JSON (`docs/api/test-vectors.json`).
+
+fn main() {
+    println!("{}", serde_json::to_string_pretty(&taric_core::vectors::test_vectors()).expect("vectors serialize"));
+}
@@ -0,0 +1,70 @@
+//! Content-addressed attachments (wire version 10).
+//!
+//! Blobs too large for `payload` (images, firmware dumps) travel separately from their entry.
+//! The entry lists each blob's SHA-256 and size in `attachments`; both are covered by the entry
+//! hash and signature, so a blob fetched from any store can be checked against the signed entry.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::errors::VerifyError;
+use crate::types::LogEntry;
+
+/// Most attachments one entry may list.
+pub const MAX_ATTACHMENTS: usize = 64;
+
+/// A blob an entry refers to by content.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Attachment {
+    /// Lowercase hex SHA-256 of the blob.
+    pub sha256: String,
+    /// Blob size in bytes.
+    pub size: u64,
+}
+
+/// Lowercase hex SHA-256 of `blob`, its content address.
+pub fn blob_hash(blob: &[u8]) -> String { hex::encode(Sha256::digest(blob)) }
+
+/// Whether `s` is a content address: 64 lowercase hex digits.
+pub fn is_blob_hash(s: &str) -> bool { s.len() == 64 && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) }
+
+impl Attachment {
+    /// The attachment describing `blob`.
+    pub fn of(blob: &[u8]) -> Self { Attachment { sha256: blob_hash(blob), size: blob.len() as u64 } }
+
+    /// Check `blob` is the attached one.
+    pub fn check_blob(&self, blob: &[u8]) -> Result<(), VerifyError> {
+        if blob.len() as u64 != self.size {
+            return Err(VerifyError::AttachmentMismatch(format!("{}: {} bytes, expected {}", self.sha256, blob.len(), self.size)));
+        }
+        if blob_hash(blob) != self.sha256 {
+            return Err(VerifyError::AttachmentMismatch(format!("{}: content hash differs", self.sha256)));
+        }
+        Ok(())
+    }
+}
+
+impl LogEntry {
+    /// The attachments; none before version 10.
+    pub fn attachments(&self) -> &[Attachment] { self.attachments.as_deref().unwrap_or_default() }
+
+    /// Check the attachment list: at most `MAX_ATTACHMENTS`, well-formed hashes, no duplicates.
+    pub fn check_attachments(&self) -> Result<(), VerifyError> {
+        let attachments = self.attachments();
+        if attachments.len() > MAX_ATTACHMENTS {
+            return Err(VerifyError::Malformed(format!("more than {MAX_ATTACHMENTS} attachments")));
+        }
+        let mut seen = HashSet::new();
+        for a in attachments {
+            if !is_blob_hash(&a.sha256) {
+                return Err(VerifyError::Malformed(format!("attachment sha256 {:?} is not 64 lowercase hex digits", a.sha256)));
+            }
+            if !seen.insert(a.sha256.as_str()) {
+                return Err(VerifyError::Malformed(format!("duplicate attachment {}", a.sha256)));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+//! Deterministic CBOR encoding (RFC 8949 section 4.2.1), used for every canonical form that is
+//! hashed or signed, and a decoder for the same data model (the binary envelope, see `envelope`,
+//! and `cbor` payloads, see `payload`).
+//!
+//! Only the data model the canonical forms need is supported: integers, byte and text strings,
+//! arrays, maps, booleans and null; no floats, tags or indefinite lengths. Deterministic
+//! encoding means every integer and length argument takes its shortest form, all lengths are
+//! definite and map entries are sorted by the bytewise order of their encoded keys. Clients can
+//! use any CBOR library with a deterministic mode (e.g. `cbor2` with `canonical=True`), or the
+//! vectors in `docs/api/test-vectors.json`.
+
+/// A CBOR data item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// Major type 0.
+    Unsigned(u64),
+    /// Major type 1: the integer `-1 - n`.
+    Negative(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    /// Entries are sorted when encoded; keys must be distinct.
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self { Value::Unsigned(n) }
+}
+
+impl From<u8> for Value {
+    fn from(n: u8) -> Self { Value::Unsigned(n.into()) }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        match u64::try_from(n) {
+            Ok(n) => Value::Unsigned(n),
+            // -1 - n for negative n, without overflow at i64::MIN
+            Err(_) => Value::Negative(!(n as u64)),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self { Value::Text(s.to_string()) }
+}
+
+impl From<&String> for Value {
+    fn from(s: &String) -> Self { Value::Text(s.clone()) }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self { v.map_or(Value::Null, Into::into) }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self { Value::Array(items.into_iter().map(Into::into).collect()) }
+}
+
+/// Head of a data item: major type and its shortest-form argument.
+fn head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend([major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((arg as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(arg.to_be_bytes());
+        }
+    }
+}
+
+fn encode_into(out: &mut Vec<u8>, v: &Value) {
+    match v {
+        Value::Unsigned(n) => head(out, 0, *n),
+        Value::Negative(n) => head(out, 1, *n),
+        Value::Bytes(b) => {
+            head(out, 2, b.len() as u64);
+            out.extend(b);
+        }
+        Value::Text(s) => {
+            head(out, 3, s.len() as u64);
+            out.extend(s.as_bytes());
+        }
+        Value::Array(items) => {
+            head(out, 4, items.len() as u64);
+            for item in items { encode_into(out, item); }
+        }
+        Value::Map(entries) => {
+            let mut encoded: Vec<(Vec<u8>, Vec<u8>)> = entries.iter().map(|(k, v)| (encode(k), encode(v))).collect();
+            encoded.sort();
+            debug_assert!(encoded.windows(2).all(|w| w[0].0 != w[1].0), "duplicate CBOR map key");
+            head(out, 5, encoded.len() as u64);
+            for (k, v) in encoded {
+                out.extend(k);
+                out.extend(v);
+            }
+        }
+        Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        Value::Null => out.push(0xf6),
+    }
+}
+
+/// The deterministic encoding of `v`.
+pub fn encode(v: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(&mut out, v);
+    out
+}
+
+/// Deepest nesting of arrays and maps `decode` accepts.
+pub const MAX_DEPTH: usize = 16;
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.input.len()).ok_or("truncated input")?;
+        let bytes = &self.input[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Major type and argument of the next item; `info` 31 (indefinite length) is refused.
+    fn head(&mut self) -> Result<(u8, u64), String> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let arg = match info {
+            0..=23 => info.into(),
+            24 => self.take(1)?[0].into(),
+            25 => u16::from_be_bytes(self.take(2)?.try_into().expect("2 bytes")).into(),
+            26 => u32::from_be_bytes(self.take(4)?.try_into().expect("4 bytes")).into(),
+            27 => u64::from_be_bytes(self.take(8)?.try_into().expect("8 bytes")),
+            _ => return Err(format!("unsupported initial byte 0x{initial:02x}")),
+        };
+        Ok((major, arg))
+    }
+
+    fn len(&mut self, arg: u64) -> Result<usize, String> {
+        // Every item takes at least one byte, so longer lengths cannot be satisfied.
+        usize::try_from(arg).ok().filter(|&n| n <= self.input.len() - self.pos).ok_or_else(|| "truncated input".to_string())
+    }
+
+    fn item(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH { return Err("nested too deeply".into()); }
+        let start = self.pos;
+        let (major, arg) = self.head()?;
+        Ok(match major {
+            0 => Value::Unsigned(arg),
+            1 => Value::Negative(arg),
+            2 => {
+                let n = self.len(arg)?;
+                Value::Bytes(self.take(n)?.to_vec())
+            }
+            3 => {
+                let n = self.len(arg)?;
+                Value::Text(String::from_utf8(self.take(n)?.to_vec()).map_err(|_| "text string is not UTF-8")?)
+            }
+            4 => {
+                let n = self.len(arg)?;
+                Value::Array((0..n).map(|_| self.item(depth + 1)).collect::<Result<_, _>>()?)
+            }
+            5 => {
+                let n = self.len(arg)?;
+                Value::Map((0..n).map(|_| Ok((self.item(depth + 1)?, self.item(depth + 1)?))).collect::<Result<_, String>>()?)
+            }
+            // Floats and two-byte simple values have a multi-byte head
+            7 if self.pos - start > 1 => return Err(format!("unsupported simple value or float 0x{:02x}", self.input[start])),
+            7 => match arg {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 => Value::Null,
+                _ => return Err(format!("unsupported simple value or float {arg}")),
+            },
+            _ => return Err("tags are not supported".into()),
+        })
+    }
+}
+
+/// Decode exactly one data item of the supported data model. Definite lengths only; floats,
+/// tags and undefined are refused.
+pub fn decode(input: &[u8]) -> Result<Value, String> {
+    let mut d = Decoder { input, pos: 0 };
+    let v = d.item(0)?;
+    if d.pos != input.len() { return Err("trailing bytes after the data item".into()); }
+    Ok(v)
+}
@@ -0,0 +1,113 @@
+//! Signed tree heads ("checkpoints") of the global Merkle log.
+//!
+//! A checkpoint commits the server to the log having exactly `tree_size` accepted entries
+//! with root `root_hash` at `timestamp`. It is signed with the ACK signing key.
+//!
+//! Independent witnesses co-sign checkpoints they have checked to be consistent with every
+//! checkpoint they saw before. A server showing different logs to different parties cannot
+//! collect co-signatures from witnesses that saw the other view.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use crate::cbor::{self, Value};
+use crate::errors::VerifyError;
+use crate::merkle::Hash;
+use crate::traits::AckSigner;
+use crate::types::VerifyingKey;
+use crate::verifier::verify_signature;
+
+/// Domain separation label, first element of the signed tuple.
+pub const CHECKPOINT_LABEL: &str = "taric-checkpoint-v1";
+/// Domain separation label of witness co-signatures.
+pub const COSIGNATURE_LABEL: &str = "taric-cosignature-v1";
+
+/// Signed tree head.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Checkpoint {
+    pub tree_size: u64,
+    /// Hex-encoded RFC 6962 root hash.
+    pub root_hash: String,
+    pub timestamp: i64,
+    pub server_signer_id: String,
+    pub server_signature: String,
+    /// Witness co-signatures attached by the server; not covered by `server_signature`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cosignatures: Vec<Cosignature>,
+}
+
+/// A witness's signature over a checkpoint it verified.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Cosignature {
+    pub witness_id: String,
+    /// When the witness signed (Unix epoch seconds).
+    pub timestamp: i64,
+    pub signature: String,
+}
+
+/// Canonicalize a checkpoint for signing (exclude server_signature).
+pub fn cbor_for_checkpoint_sign(c: &Checkpoint) -> Vec<u8> {
+    cbor::encode(&Value::Array(vec![
+        CHECKPOINT_LABEL.into(),
+        c.tree_size.into(),
+        (&c.root_hash).into(),
+        c.timestamp.into(),
+        (&c.server_signer_id).into(),
+    ]))
+}
+
+/// Canonicalize a checkpoint for a witness co-signature made by `witness_id` at `timestamp`.
+pub fn cbor_for_cosign(c: &Checkpoint, witness_id: &str, timestamp: i64) -> Vec<u8> {
+    cbor::encode(&Value::Array(vec![
+        COSIGNATURE_LABEL.into(),
+        c.tree_size.into(),
+        (&c.root_hash).into(),
+        c.timestamp.into(),
+        (&c.server_signer_id).into(),
+        witness_id.into(),
+        timestamp.into(),
+    ]))
+}
+
+impl Checkpoint {
+    /// Build and sign a checkpoint for a tree of `tree_size` leaves with root `root`.
+    pub fn sign(signer: &dyn AckSigner, tree_size: u64, root: &Hash, timestamp: i64) -> Self {
+        let mut c = Checkpoint {
+            tree_size,
+            root_hash: hex::encode(root),
+            timestamp,
+            server_signer_id: signer.signer_id().into(),
+            server_signature: String::new(),
+            cosignatures: Vec::new(),
+        };
+        c.server_signature = B64.encode(signer.sign(&cbor_for_checkpoint_sign(&c)));
+        c
+    }
+
+    /// Verify the server signature with the server's public key.
+    pub fn verify(&self, server_key: &VerifyingKey) -> Result<(), VerifyError> {
+        verify_signature(server_key, &server_key.algo, &cbor_for_checkpoint_sign(self), &self.server_signature)
+    }
+
+    /// Decoded root hash.
+    pub fn root(&self) -> Result<Hash, VerifyError> {
+        let bytes = hex::decode(&self.root_hash).map_err(|_| VerifyError::Malformed("root_hash hex".into()))?;
+        bytes.try_into().map_err(|_| VerifyError::Malformed("root_hash length".into()))
+    }
+}
+
+impl Cosignature {
+    /// Co-sign `checkpoint` as the witness identified by `witness.signer_id()`.
+    pub fn sign(witness: &dyn AckSigner, checkpoint: &Checkpoint, timestamp: i64) -> Self {
+        let witness_id = witness.signer_id().to_string();
+        let signature = B64.encode(witness.sign(&cbor_for_cosign(checkpoint, &witness_id, timestamp)));
+        Self { witness_id, timestamp, signature }
+    }
+
+    /// Verify this co-signature over `checkpoint` with the witness's public key.
+    pub fn verify(&self, checkpoint: &Checkpoint, witness_key: &VerifyingKey) -> Result<(), VerifyError> {
+        let msg = cbor_for_cosign(checkpoint, &self.witness_id, self.timestamp);
+        verify_signature(witness_key, &witness_key.algo, &msg, &self.signature)
+    }
+}
@@ -0,0 +1,203 @@
+//! COSE_Sign1 (RFC 9052) form of entries and ACKs, so standard COSE tooling can inspect and
+//! verify them.
+//!
+//! An entry or ACK whose `signature_format` is `cose_sign1` is signed over the COSE
+//! `Sig_structure` of its COSE_Sign1 message instead of its native canonical form:
+//! - protected header: `alg` (label 1) EdDSA (-8), and `kid` (label 4) the UTF-8 `key_id` of an
+//!   entry or `server_signer_id` of an ACK;
+//! - payload: the binary envelope (see `envelope`) without the fields the header carries, the
+//!   signature and `signature_format`. From version 8 the signed `payload_commitment` stands for
+//!   `payload` and `payload_salt`, which travel in the unprotected header (labels `"payload"`
+//!   and `"payload_salt"`) so the payload can still be erased;
+//! - signature: the raw signature bytes; no external AAD.
+//!
+//! The entry hash does not change: it is computed over the canonical form in `types` whatever
+//! the signature format.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+
+use crate::cbor::{self, Value};
+use crate::envelope::{ACK_KEYS, ENTRY_KEYS};
+use crate::errors::VerifyError;
+use crate::types::{Ack, LogEntry};
+
+/// `signature_format` of entries and ACKs signed as COSE_Sign1.
+pub const COSE_SIGN1: &str = "cose_sign1";
+
+/// Tag 18 (COSE_Sign1), major type 6.
+const TAG: u8 = 0xd2;
+const ALG: u64 = 1;
+const KID: u64 = 4;
+/// COSE algorithm identifier of EdDSA.
+const EDDSA: i64 = -8;
+
+/// Envelope keys the header or signature carry: algo, key_id, signature, signature_format.
+const ENTRY_HEADER_KEYS: [u64; 4] = [7, 8, 10, 21];
+/// Envelope keys covered by `payload_commitment` from version 8: payload, payload_salt.
+const COMMITTED_KEYS: [u64; 2] = [9, 17];
+/// Envelope keys the header or signature carry: server_signer_id, server_signature, signature_format.
+const ACK_HEADER_KEYS: [u64; 3] = [5, 6, 10];
+
+fn malformed(msg: impl std::fmt::Display) -> VerifyError { VerifyError::Malformed(format!("cose: {msg}")) }
+
+/// The fields of a COSE_Sign1 message but the signature.
+struct Parts {
+    protected: Vec<u8>,
+    unprotected: Vec<(Value, Value)>,
+    payload: Vec<u8>,
+}
+
+fn protected(alg: i64, kid: Option<&str>) -> Vec<u8> {
+    let mut header = vec![(ALG.into(), alg.into())];
+    if let Some(kid) = kid { header.push((KID.into(), Value::Bytes(kid.as_bytes().to_vec()))); }
+    cbor::encode(&Value::Map(header))
+}
+
+/// Split an envelope map: `dropped` keys are left out, `moved` ones go to the unprotected
+/// header under their field names, the rest is the payload.
+fn split(map: Vec<(Value, Value)>, names: &[(u64, &'static str)], dropped: &[u64], moved: &[u64]) -> (Vec<(Value, Value)>, Vec<u8>) {
+    let mut unprotected = Vec::new();
+    let mut payload = Vec::new();
+    for (k, v) in map {
+        let Value::Unsigned(key) = k else { continue };
+        if dropped.contains(&key) { continue; }
+        match names.iter().find(|(n, _)| moved.contains(&key) && *n == key) {
+            Some((_, name)) => unprotected.push(((*name).into(), v)),
+            None => payload.push((k, v)),
+        }
+    }
+    (unprotected, cbor::encode(&Value::Map(payload)))
+}
+
+fn entry_parts(e: &LogEntry) -> Result<Parts, VerifyError> {
+    let alg = match e.algo.as_str() {
+        "ed25519" => EDDSA,
+        other => return Err(VerifyError::UnsupportedAlgo(other.to_string())),
+    };
+    let moved: &[u64] = if e.version >= 8 { &COMMITTED_KEYS } else { &[] };
+    let (unprotected, payload) = split(e.envelope_map(), &ENTRY_KEYS, &ENTRY_HEADER_KEYS, moved);
+    Ok(Parts { protected: protected(alg, e.key_id.as_deref()), unprotected, payload })
+}
+
+fn ack_parts(a: &Ack) -> Parts {
+    let (unprotected, payload) = split(a.envelope_map(), &ACK_KEYS, &ACK_HEADER_KEYS, &[]);
+    Parts { protected: protected(EDDSA, Some(&a.server_signer_id)), unprotected, payload }
+}
+
+fn sig_structure(p: &Parts) -> Vec<u8> {
+    cbor::encode(&Value::Array(vec![
+        "Signature1".into(),
+        Value::Bytes(p.protected.clone()),
+        Value::Bytes(Vec::new()),
+        Value::Bytes(p.payload.clone()),
+    ]))
+}
+
+/// The COSE `Sig_structure` an entry with `signature_format` `cose_sign1` is signed over.
+pub fn cose_for_sign(e: &LogEntry) -> Result<Vec<u8>, VerifyError> {
+    Ok(sig_structure(&entry_parts(e)?))
+}
+
+/// The COSE `Sig_structure` an ACK with `signature_format` `cose_sign1` is signed over.
+pub fn cose_for_ack_sign(a: &Ack) -> Vec<u8> {
+    sig_structure(&ack_parts(a))
+}
+
+/// The tagged COSE_Sign1 message of `parts` and a base64 signature.
+fn message(parts: Parts, signature: &str) -> Result<Vec<u8>, VerifyError> {
+    let signature = B64.decode(signature).map_err(|_| malformed("signature base64"))?;
+    let mut out = vec![TAG];
+    out.extend(cbor::encode(&Value::Array(vec![
+        Value::Bytes(parts.protected),
+        Value::Map(parts.unprotected),
+        Value::Bytes(parts.payload),
+        Value::Bytes(signature),
+    ])));
+    Ok(out)
+}
+
+/// A parsed COSE_Sign1 message, as an envelope map with the header and signature fields
+/// put back under their envelope keys.
+struct Parsed {
+    alg: i64,
+    kid: Option<String>,
+    map: Vec<(Value, Value)>,
+}
+
+/// Parse a COSE_Sign1 message, tagged or not. `signature_key` is the envelope key of the
+/// signature, `names` maps unprotected header labels back to envelope keys.
+fn parse(bytes: &[u8], signature_key: u64, names: &[(u64, &'static str)]) -> Result<Parsed, VerifyError> {
+    let body = bytes.strip_prefix(&[TAG]).unwrap_or(bytes);
+    let Value::Array(items) = cbor::decode(body).map_err(malformed)? else { return Err(malformed("expected a COSE_Sign1 array")) };
+    let Ok([Value::Bytes(protected), Value::Map(unprotected), Value::Bytes(payload), Value::Bytes(signature)]) = <[Value; 4]>::try_from(items) else {
+        return Err(malformed("expected [protected, unprotected, payload, signature]"));
+    };
+    let Value::Map(header) = cbor::decode(&protected).map_err(malformed)? else { return Err(malformed("protected header is not a map")) };
+    let (mut alg, mut kid) = (None, None);
+    for (label, v) in header {
+        match (label, v) {
+            (Value::Unsigned(ALG), Value::Negative(n)) => alg = i64::try_from(n).ok().map(|n| -1 - n),
+            (Value::Unsigned(KID), Value::Bytes(b)) => kid = Some(String::from_utf8(b).map_err(|_| malformed("kid is not UTF-8"))?),
+            (label, _) => return Err(malformed(format!("unsupported protected header {label:?}"))),
+        }
+    }
+    let Value::Map(mut map) = cbor::decode(&payload).map_err(malformed)? else { return Err(malformed("payload is not a map")) };
+    for (label, v) in unprotected {
+        let key = match &label {
+            Value::Text(l) => names.iter().find(|(_, n)| n == l).map(|(k, _)| *k),
+            _ => None,
+        };
+        map.push((key.ok_or_else(|| malformed(format!("unsupported unprotected header {label:?}")))?.into(), v));
+    }
+    map.push((signature_key.into(), Value::Bytes(signature)));
+    Ok(Parsed { alg: alg.ok_or_else(|| malformed("missing alg"))?, kid, map })
+}
+
+/// Check `bytes` is exactly the message `encoded` (tagged or not), so what was parsed
+/// verifies as sent.
+fn check_deterministic(bytes: &[u8], encoded: &[u8]) -> Result<(), VerifyError> {
+    if bytes == encoded || bytes == &encoded[1..] { Ok(()) } else { Err(malformed("not the deterministic encoding of its content")) }
+}
+
+impl LogEntry {
+    /// The tagged COSE_Sign1 message of an entry signed as one.
+    pub fn to_cose_sign1(&self) -> Result<Vec<u8>, VerifyError> {
+        if self.signature_format.as_deref() != Some(COSE_SIGN1) { return Err(malformed("entry is not signed as COSE_Sign1")); }
+        message(entry_parts(self)?, &self.signature)
+    }
+
+    /// Parse a COSE_Sign1 message (tagged or not) into the entry it carries, with
+    /// `signature_format` `cose_sign1`. The signature is checked by the `Verifier`, like a
+    /// native one; a message other than the one `to_cose_sign1` gives for the entry is refused.
+    pub fn from_cose_sign1(bytes: &[u8]) -> Result<LogEntry, VerifyError> {
+        let Parsed { alg, kid, mut map } = parse(bytes, 10, &ENTRY_KEYS)?;
+        if alg != EDDSA { return Err(VerifyError::UnsupportedAlgo(format!("COSE alg {alg}"))); }
+        map.push((7u64.into(), "ed25519".into()));
+        if let Some(kid) = kid { map.push((8u64.into(), Value::Text(kid))); }
+        map.push((21u64.into(), COSE_SIGN1.into()));
+        let e = LogEntry::from_map(map)?;
+        check_deterministic(bytes, &e.to_cose_sign1()?)?;
+        Ok(e)
+    }
+}
+
+impl Ack {
+    /// The tagged COSE_Sign1 message of an ACK signed as one.
+    pub fn to_cose_sign1(&self) -> Result<Vec<u8>, VerifyError> {
+        if self.signature_format.as_deref() != Some(COSE_SIGN1) { return Err(malformed("ACK is not signed as COSE_Sign1")); }
+        message(ack_parts(self), &self.server_signature)
+    }
+
+    /// Parse a COSE_Sign1 message (tagged or not) into the ACK it carries, with
+    /// `signature_format` `cose_sign1`. The signature is not checked.
+    pub fn from_cose_sign1(bytes: &[u8]) -> Result<Ack, VerifyError> {
+        let Parsed { alg, kid, mut map } = parse(bytes, 6, &[])?;
+        if alg != EDDSA { return Err(VerifyError::UnsupportedAlgo(format!("COSE alg {alg}"))); }
+        map.push((5u64.into(), Value::Text(kid.ok_or_else(|| malformed("missing kid"))?)));
+        map.push((10u64.into(), COSE_SIGN1.into()));
+        let a = Ack::from_map(map)?;
+        check_deterministic(bytes, &a.to_cose_sign1()?)?;
+        Ok(a)
+    }
+}
@@ -0,0 +1,145 @@
+//! Selective disclosure of payload fields (wire version 9, `payload_commitment_scheme` `fields`).
+//!
+//! The payload is a JSON object; its `payload_commitment` is the hex root of an RFC 6962 Merkle
+//! tree (see `merkle`) with one leaf per top-level field, in field name order (bytewise). The leaf
+//! data of field `name` with value `value` is the CBOR array `[salt, name, json]`, where `json` is
+//! the value's RFC 8785 canonical JSON text (see `jcs`) and `salt` is `HMAC-SHA256(payload_salt,
+//! name)`. Integers in the payload must be I-JSON safe, so every implementation reads them alike.
+//! Revealing one field, its salt and its inclusion proof tells nothing about the others: their
+//! salts cannot be derived from it, and the proof holds only hashes.
+
+use std::collections::BTreeMap;
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::cbor;
+use crate::erasure::CommitmentScheme;
+use crate::errors::VerifyError;
+use crate::jcs;
+use crate::merkle::{leaf_hash, Hash, InclusionProof, MerkleTree};
+use crate::types::{compute_entry_hash, LogEntry, VerifyingKey};
+use crate::verifier::verify_signature;
+
+/// One revealed field with the proof that it belongs to the entry's payload commitment.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DisclosedField {
+    pub name: String,
+    pub value: Value,
+    /// Base64 of the field's 32-byte salt.
+    pub salt: String,
+    pub proof: InclusionProof,
+}
+
+/// An entry with its payload withheld and some of its fields revealed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Disclosure {
+    /// The signed entry without `payload` and `payload_salt`.
+    pub entry: LogEntry,
+    pub fields: Vec<DisclosedField>,
+}
+
+fn malformed(msg: impl std::fmt::Display) -> VerifyError { VerifyError::Malformed(format!("fields commitment: {msg}")) }
+
+/// The field salt of `name`, derived from the entry's payload salt.
+pub fn field_salt(payload_salt: &[u8], name: &str) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(payload_salt).expect("HMAC accepts any key length");
+    mac.update(name.as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+/// Leaf data of a field: the CBOR array `[salt, name, canonical JSON of value]`.
+pub fn field_leaf_data(salt: &[u8], name: &str, value: &Value) -> Vec<u8> {
+    cbor::encode(&cbor::Value::Array(vec![
+        cbor::Value::Bytes(salt.to_vec()),
+        name.into(),
+        cbor::Value::Text(String::from_utf8(jcs::encode(value)).expect("JCS output is UTF-8")),
+    ]))
+}
+
+/// Leaf hash of a field.
+pub fn field_leaf(salt: &[u8], name: &str, value: &Value) -> Hash {
+    leaf_hash(&field_leaf_data(salt, name, value))
+}
+
+/// The payload's top-level fields, in name order.
+fn fields(payload: &str) -> Result<BTreeMap<String, Value>, VerifyError> {
+    let fields = match serde_json::from_str(payload) {
+        Ok(Value::Object(map)) => map.into_iter().collect::<BTreeMap<_, _>>(),
+        Ok(_) => return Err(malformed("payload is not a JSON object")),
+        Err(e) => return Err(malformed(format!("payload is not JSON: {e}"))),
+    };
+    fields.values().try_for_each(jcs::check_numbers).map_err(malformed)?;
+    Ok(fields)
+}
+
+/// The field tree of a payload.
+fn field_tree(payload_salt: &[u8], fields: &BTreeMap<String, Value>) -> MerkleTree {
+    let mut tree = MerkleTree::new();
+    for (name, value) in fields {
+        tree.push_leaf_hash(field_leaf(&field_salt(payload_salt, name), name, value));
+    }
+    tree
+}
+
+/// Hex root of the field tree of `payload`.
+pub fn fields_commitment(payload_salt: &[u8], payload: &str) -> Result<String, VerifyError> {
+    Ok(hex::encode(field_tree(payload_salt, &fields(payload)?).root()))
+}
+
+fn root_of(entry: &LogEntry) -> Result<Hash, VerifyError> {
+    let commitment = entry.payload_commitment.as_deref().ok_or_else(|| malformed("no payload_commitment"))?;
+    hex::decode(commitment).ok().and_then(|b| b.try_into().ok()).ok_or_else(|| malformed("payload_commitment is not a hex SHA-256"))
+}
+
+impl LogEntry {
+    /// Reveal the payload fields `names` of a `fields`-committed entry.
+    pub fn disclose(&self, names: &[&str]) -> Result<Disclosure, VerifyError> {
+        if self.is_erased() { return Err(VerifyError::Malformed("payload erased".into())); }
+        if self.commitment_scheme()? != CommitmentScheme::Fields {
+            return Err(malformed("entry does not use the fields commitment scheme"));
+        }
+        let salt = B64.decode(self.payload_salt.as_deref().unwrap_or_default()).map_err(|_| VerifyError::Malformed("payload_salt is not base64".into()))?;
+        let all = fields(&self.payload)?;
+        let tree = field_tree(&salt, &all);
+        let mut disclosed = Vec::with_capacity(names.len());
+        for name in names {
+            let (index, (name, value)) = all.iter().enumerate().find(|(_, (n, _))| n == name).ok_or_else(|| malformed(format!("no field {name:?}")))?;
+            let path = tree.inclusion_proof(index as u64, tree.len()).expect("index within tree");
+            disclosed.push(DisclosedField {
+                name: name.clone(),
+                value: value.clone(),
+                salt: B64.encode(field_salt(&salt, name)),
+                proof: InclusionProof::new(index as u64, tree.len(), &path),
+            });
+        }
+        let entry = self.redacted().ok_or_else(|| malformed("requires version 9"))?;
+        Ok(Disclosure { entry, fields: disclosed })
+    }
+}
+
+impl Disclosure {
+    /// Check the entry's hash and its signature by `device_key`, and that every revealed field
+    /// is in the entry's payload commitment.
+    pub fn verify(&self, device_key: &VerifyingKey) -> Result<(), VerifyError> {
+        let e = &self.entry;
+        if compute_entry_hash(e) != e.entry_hash { return Err(VerifyError::HashMismatch); }
+        verify_signature(device_key, &e.algo, &e.signing_input()?, &e.signature)?;
+        if e.commitment_scheme()? != CommitmentScheme::Fields {
+            return Err(malformed("entry does not use the fields commitment scheme"));
+        }
+        let root = root_of(e)?;
+        for f in &self.fields {
+            let salt = B64.decode(&f.salt).map_err(|_| malformed(format!("salt of {:?} is not base64", f.name)))?;
+            f.proof.verify_leaf(&field_leaf(&salt, &f.name, &f.value), &root).map_err(|err| match err {
+                VerifyError::InvalidProof(why) => VerifyError::InvalidProof(format!("field {:?}: {why}", f.name)),
+                other => other,
+            })?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+//! End-to-end encrypted payloads (`payload_encoding` `hpke`, wire version 7).
+//!
+//! The device encrypts the payload to a recipient's X25519 public key with HPKE (RFC 9180,
+//! base mode, DHKEM(X25519, HKDF-SHA256), HKDF-SHA256, ChaCha20-Poly1305) and signs the entry
+//! as usual, so the signature covers the ciphertext. The server checks the envelope's shape,
+//! hash, signature and chain without being able to read the plaintext; only the holder of the
+//! recipient's private key can open it.
+//!
+//! The payload is the JSON text of an `EncryptedPayload`. The HPKE `info` is `HPKE_INFO` and the
+//! AAD is the entry's `device_id`, so a ciphertext cannot be replayed under another device.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::errors::VerifyError;
+use crate::payload::PayloadEncoding;
+
+/// The only supported HPKE suite.
+pub const HPKE_SUITE: &str = "x25519-hkdf-sha256-chacha20poly1305";
+/// HPKE `info` for entry payloads.
+pub const HPKE_INFO: &[u8] = b"taric-payload-v1";
+
+const KEM_ID: u16 = 0x0020;
+const KDF_ID: u16 = 0x0001;
+const AEAD_ID: u16 = 0x0003;
+
+/// Envelope of an encrypted payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedPayload {
+    /// Identifier of the recipient key, so the reader knows which private key opens it.
+    pub recipient: String,
+    /// `HPKE_SUITE`.
+    pub suite: String,
+    /// Base64 of the 32-byte encapsulated key (the ephemeral X25519 public key).
+    pub enc: String,
+    /// Base64 of the ChaCha20-Poly1305 ciphertext and tag.
+    pub ciphertext: String,
+    /// Encoding the plaintext would have as an unencrypted payload: `utf8`, `base64` (raw
+    /// bytes) or `cbor`. The plaintext is the raw bytes, not their base64.
+    pub plaintext_encoding: String,
+}
+
+fn malformed(msg: &str) -> VerifyError { VerifyError::Malformed(format!("encrypted payload: {msg}")) }
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &str, ikm: &[u8]) -> Vec<u8> {
+    let labeled = [b"HPKE-v1".as_slice(), suite_id, label.as_bytes(), ikm].concat();
+    Hkdf::<Sha256>::extract(Some(salt), &labeled).0.to_vec()
+}
+
+fn labeled_expand(suite_id: &[u8], prk: &[u8], label: &str, info: &[u8], len: usize) -> Vec<u8> {
+    let labeled = [&(len as u16).to_be_bytes(), b"HPKE-v1".as_slice(), suite_id, label.as_bytes(), info].concat();
+    let mut out = vec![0u8; len];
+    Hkdf::<Sha256>::from_prk(prk).expect("PRK has hash length").expand(&labeled, &mut out).expect("valid HKDF length");
+    out
+}
+
+/// DHKEM shared secret from the DH output and `enc || pkR`.
+fn kem_shared_secret(dh: &[u8], kem_context: &[u8]) -> Vec<u8> {
+    let suite_id = [b"KEM".as_slice(), &KEM_ID.to_be_bytes()].concat();
+    let eae_prk = labeled_extract(&suite_id, b"", "eae_prk", dh);
+    labeled_expand(&suite_id, &eae_prk, "shared_secret", kem_context, 32)
+}
+
+/// Base mode key schedule: the AEAD key and nonce (sequence number 0, single-shot).
+fn key_schedule(shared_secret: &[u8], info: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let suite_id = [b"HPKE".as_slice(), &KEM_ID.to_be_bytes(), &KDF_ID.to_be_bytes(), &AEAD_ID.to_be_bytes()].concat();
+    let psk_id_hash = labeled_extract(&suite_id, b"", "psk_id_hash", b"");
+    let info_hash = labeled_extract(&suite_id, b"", "info_hash", info);
+    let context = [&[0u8][..], &psk_id_hash, &info_hash].concat();
+    let secret = labeled_extract(&suite_id, shared_secret, "secret", b"");
+    (labeled_expand(&suite_id, &secret, "key", &context, 32), labeled_expand(&suite_id, &secret, "base_nonce", &context, 12))
+}
+
+/// A new recipient key pair: `(secret, public)`.
+pub fn generate_recipient_key() -> ([u8; 32], [u8; 32]) {
+    let secret = StaticSecret::random_from_rng(OsRng);
+    (secret.to_bytes(), PublicKey::from(&secret).to_bytes())
+}
+
+/// The public key of a recipient secret key.
+pub fn recipient_public_key(secret: &[u8; 32]) -> [u8; 32] { PublicKey::from(&StaticSecret::from(*secret)).to_bytes() }
+
+/// HPKE base mode single-shot seal with a given ephemeral secret; returns `(enc, ciphertext)`.
+/// A low-order recipient key, whose shared secret is all zero, is refused (RFC 9180 section 7.1.4).
+pub fn seal_with_ephemeral(ephemeral: &StaticSecret, recipient: &PublicKey, info: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<([u8; 32], Vec<u8>), VerifyError> {
+    let enc = PublicKey::from(ephemeral).to_bytes();
+    let dh = ephemeral.diffie_hellman(recipient);
+    if !dh.was_contributory() { return Err(malformed("recipient key is a low-order point")); }
+    let shared = kem_shared_secret(dh.as_bytes(), &[enc, recipient.to_bytes()].concat());
+    let (key, nonce) = key_schedule(&shared, info);
+    let ct = ChaCha20Poly1305::new_from_slice(&key)
+        .expect("32-byte key")
+        .encrypt(nonce.as_slice().into(), Payload { msg: plaintext, aad })
+        .expect("encryption does not fail");
+    Ok((enc, ct))
+}
+
+/// HPKE base mode single-shot open. A low-order `enc`, whose shared secret is all zero, is refused.
+pub fn open(recipient: &StaticSecret, enc: &[u8; 32], info: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, VerifyError> {
+    let dh = recipient.diffie_hellman(&PublicKey::from(*enc));
+    if !dh.was_contributory() { return Err(malformed("enc is a low-order point")); }
+    let shared = kem_shared_secret(dh.as_bytes(), &[*enc, PublicKey::from(recipient).to_bytes()].concat());
+    let (key, nonce) = key_schedule(&shared, info);
+    ChaCha20Poly1305::new_from_slice(&key)
+        .expect("32-byte key")
+        .decrypt(nonce.as_slice().into(), Payload { msg: ciphertext, aad })
+        .map_err(|_| malformed("decryption failed (wrong key, device or corrupted ciphertext)"))
+}
+
+impl EncryptedPayload {
+    /// Encrypt `plaintext` for the recipient key `recipient_pk` (named `recipient_id`), for an
+    /// entry of `device_id`. Set the result's JSON as the payload, with `payload_encoding` `hpke`.
+    pub fn seal(recipient_id: &str, recipient_pk: &[u8; 32], device_id: &str, plaintext: &[u8], plaintext_encoding: PayloadEncoding) -> Result<Self, VerifyError> {
+        let ephemeral = StaticSecret::random_from_rng(OsRng);
+        let (enc, ct) = seal_with_ephemeral(&ephemeral, &PublicKey::from(*recipient_pk), HPKE_INFO, device_id.as_bytes(), plaintext)?;
+        Ok(EncryptedPayload {
+            recipient: recipient_id.into(),
+            suite: HPKE_SUITE.into(),
+            enc: B64.encode(enc),
+            ciphertext: B64.encode(ct),
+            plaintext_encoding: plaintext_encoding.as_str().into(),
+        })
+    }
+
+    /// Parse and check the envelope of a `hpke` payload, without decrypting it.
+    pub fn parse(payload: &str) -> Result<Self, VerifyError> {
+        let p: EncryptedPayload = serde_json::from_str(payload).map_err(|e| malformed(&e.to_string()))?;
+        if p.suite != HPKE_SUITE { return Err(malformed(&format!("unsupported suite {:?}", p.suite))); }
+        p.enc_bytes()?;
+        B64.decode(&p.ciphertext).map_err(|_| malformed("ciphertext is not base64"))?;
+        match p.plaintext_encoding() {
+            Some(PayloadEncoding::Hpke) | None => Err(malformed(&format!("invalid plaintext_encoding {:?}", p.plaintext_encoding))),
+            Some(_) => Ok(p),
+        }
+    }
+
+    fn enc_bytes(&self) -> Result<[u8; 32], VerifyError> {
+        B64.decode(&self.enc).ok().and_then(|b| b.try_into().ok()).ok_or_else(|| malformed("enc is not a base64 X25519 key"))
+    }
+
+    pub fn plaintext_encoding(&self) -> Option<PayloadEncoding> { PayloadEncoding::parse(&self.plaintext_encoding) }
+
+    /// Decrypt with the recipient's private key; `device_id` is the entry's.
+    pub fn open(&self, recipient_sk: &[u8; 32], device_id: &str) -> Result<Vec<u8>, VerifyError> {
+        let ct = B64.decode(&self.ciphertext).map_err(|_| malformed("ciphertext is not base64"))?;
+        open(&StaticSecret::from(*recipient_sk), &self.enc_bytes()?, HPKE_INFO, device_id.as_bytes(), &ct)
+    }
+}
@@ -0,0 +1,319 @@
+//! Binary CBOR envelope of `LogEntry` and `Ack`, an alternative to JSON on the wire
+//! (`Content-Type: application/cbor`).
+//!
+//! The envelope is a CBOR map with small integer keys (`ENTRY_KEYS`, `ACK_KEYS`); absent
+//! optional fields are left out. Fields the JSON form carries as hex (hashes, commitments) or
+//! base64 (signatures, salts, `base64` and `cbor` payloads) travel as byte strings, and UUIDs as
+//! their 16 bytes. A value that would not come back identical from its bytes (e.g. uppercase
+//! hex, non-canonical base64, a non-UUID device ID) travels as text instead, so the conversion
+//! is lossless both ways: `from_cbor(to_cbor(x)) == x` for every entry and ACK.
+//!
+//! The envelope is only a transport encoding: hashes and signatures are still computed over the
+//! canonical forms in `types`.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+
+use crate::attachments::Attachment;
+use crate::cbor::{self, Value};
+use crate::errors::VerifyError;
+use crate::types::{Ack, LogEntry};
+
+/// Map keys of the `LogEntry` envelope, in key order.
+pub const ENTRY_KEYS: [(u64, &str); 22] = [
+    (1, "version"),
+    (2, "entry_hash"),
+    (3, "device_id"),
+    (4, "timestamp"),
+    (5, "session_id"),
+    (6, "nonce"),
+    (7, "algo"),
+    (8, "key_id"),
+    (9, "payload"),
+    (10, "signature"),
+    (11, "previous_entry_hash"),
+    (12, "boot_counter"),
+    (13, "entry_counter"),
+    (14, "stream_id"),
+    (15, "kind"),
+    (16, "payload_encoding"),
+    (17, "payload_salt"),
+    (18, "payload_commitment"),
+    (19, "payload_commitment_scheme"),
+    (20, "attachments"),
+    (21, "signature_format"),
+    (22, "canon"),
+];
+
+/// Map keys of the `Ack` envelope, in key order.
+pub const ACK_KEYS: [(u64, &str); 10] = [
+    (1, "entry_id"),
+    (2, "new_entry_hash"),
+    (3, "status"),
+    (4, "timestamp"),
+    (5, "server_signer_id"),
+    (6, "server_signature"),
+    (7, "log_index"),
+    (8, "device_seq"),
+    (9, "warnings"),
+    (10, "signature_format"),
+];
+
+/// How a text field is packed into bytes when that is lossless.
+#[derive(Clone, Copy)]
+enum Packing { Hex, Base64, Uuid }
+
+fn pack(packing: Packing, s: &str) -> Value {
+    let bytes = match packing {
+        Packing::Hex => hex::decode(s).ok().filter(|b| hex::encode(b) == s),
+        Packing::Base64 => B64.decode(s).ok().filter(|b| B64.encode(b) == s),
+        Packing::Uuid => uuid_bytes(s),
+    };
+    bytes.map_or_else(|| s.into(), Value::Bytes)
+}
+
+fn unpack(packing: Packing, v: Value, field: &str) -> Result<String, VerifyError> {
+    match (v, packing) {
+        (Value::Text(s), _) => Ok(s),
+        (Value::Bytes(b), Packing::Hex) => Ok(hex::encode(b)),
+        (Value::Bytes(b), Packing::Base64) => Ok(B64.encode(b)),
+        (Value::Bytes(b), Packing::Uuid) if b.len() == 16 => Ok(uuid_text(&b)),
+        _ => Err(malformed(format!("{field}: expected text or bytes"))),
+    }
+}
+
+/// The 16 bytes of a lowercase hyphenated UUID.
+fn uuid_bytes(s: &str) -> Option<Vec<u8>> {
+    let hyphens = [8, 13, 18, 23];
+    if s.len() != 36 || hyphens.iter().any(|&i| s.as_bytes()[i] != b'-') { return None; }
+    let digits: String = s.chars().filter(|&c| c != '-').collect();
+    hex::decode(&digits).ok().filter(|b| uuid_text(b) == s)
+}
+
+fn uuid_text(b: &[u8]) -> String {
+    let h = hex::encode(b);
+    format!("{}-{}-{}-{}-{}", &h[..8], &h[8..12], &h[12..16], &h[16..20], &h[20..])
+}
+
+fn malformed(msg: impl std::fmt::Display) -> VerifyError { VerifyError::Malformed(format!("cbor envelope: {msg}")) }
+
+/// Whether the JSON form of this payload is the base64 of its bytes.
+fn binary_payload(encoding: Option<&str>) -> bool { matches!(encoding, Some("base64" | "cbor")) }
+
+/// Fields of a decoded envelope map, taken out by key.
+struct Fields {
+    entries: Vec<(u64, Value)>,
+    names: &'static [(u64, &'static str)],
+}
+
+impl Fields {
+    fn new(map: Vec<(Value, Value)>, names: &'static [(u64, &'static str)]) -> Result<Self, VerifyError> {
+        let mut entries = Vec::with_capacity(map.len());
+        for (k, v) in map {
+            let key = match k {
+                Value::Unsigned(k) if names.iter().any(|(n, _)| *n == k) => k,
+                other => return Err(malformed(format!("unknown key {other:?}"))),
+            };
+            if entries.iter().any(|(k, _)| *k == key) { return Err(malformed(format!("duplicate key {key}"))); }
+            entries.push((key, v));
+        }
+        Ok(Fields { entries, names })
+    }
+
+    fn name(&self, key: u64) -> &'static str { self.names.iter().find(|(k, _)| *k == key).map_or("?", |(_, n)| n) }
+
+    fn take(&mut self, key: u64) -> Option<Value> {
+        let i = self.entries.iter().position(|(k, _)| *k == key)?;
+        Some(self.entries.swap_remove(i).1)
+    }
+
+    fn required(&mut self, key: u64) -> Result<Value, VerifyError> {
+        self.take(key).ok_or_else(|| malformed(format!("missing {}", self.name(key))))
+    }
+
+    fn text(&mut self, key: u64) -> Result<Option<String>, VerifyError> {
+        let name = self.name(key);
+        self.take(key).map(|v| match v {
+            Value::Text(s) => Ok(s),
+            _ => Err(malformed(format!("{name}: expected text"))),
+        }).transpose()
+    }
+
+    fn packed(&mut self, key: u64, packing: Packing) -> Result<Option<String>, VerifyError> {
+        let name = self.name(key);
+        self.take(key).map(|v| unpack(packing, v, name)).transpose()
+    }
+
+    fn uint(&mut self, key: u64) -> Result<Option<u64>, VerifyError> {
+        let name = self.name(key);
+        self.take(key).map(|v| match v {
+            Value::Unsigned(n) => Ok(n),
+            _ => Err(malformed(format!("{name}: expected an unsigned integer"))),
+        }).transpose()
+    }
+
+    fn int(&mut self, key: u64) -> Result<i64, VerifyError> {
+        let name = self.name(key);
+        match self.required(key)? {
+            Value::Unsigned(n) => i64::try_from(n).ok(),
+            Value::Negative(n) => i64::try_from(n).ok().map(|n| -1 - n),
+            _ => None,
+        }
+        .ok_or_else(|| malformed(format!("{name}: expected a 64-bit integer")))
+    }
+
+    fn required_text(&mut self, key: u64) -> Result<String, VerifyError> {
+        let name = self.name(key);
+        self.text(key)?.ok_or_else(|| malformed(format!("missing {name}")))
+    }
+
+    fn required_packed(&mut self, key: u64, packing: Packing) -> Result<String, VerifyError> {
+        let name = self.name(key);
+        self.packed(key, packing)?.ok_or_else(|| malformed(format!("missing {name}")))
+    }
+}
+
+fn decode_map(bytes: &[u8]) -> Result<Vec<(Value, Value)>, VerifyError> {
+    match cbor::decode(bytes).map_err(malformed)? {
+        Value::Map(map) => Ok(map),
+        _ => Err(malformed("not a map")),
+    }
+}
+
+/// Push `(key, value)` unless the value is absent.
+fn put(map: &mut Vec<(Value, Value)>, key: u64, value: Option<Value>) {
+    if let Some(v) = value { map.push((key.into(), v)); }
+}
+
+impl LogEntry {
+    /// The binary envelope of this entry.
+    pub fn to_cbor(&self) -> Vec<u8> { cbor::encode(&Value::Map(self.envelope_map())) }
+
+    /// Parse a binary envelope. Unknown keys are refused.
+    pub fn from_cbor(bytes: &[u8]) -> Result<LogEntry, VerifyError> { Self::from_map(decode_map(bytes)?) }
+
+    /// Envelope map, unsorted.
+    pub(crate) fn envelope_map(&self) -> Vec<(Value, Value)> {
+        let text = |s: &Option<String>| s.as_deref().map(Value::from);
+        let packed = |p: Packing, s: &Option<String>| s.as_deref().map(|s| pack(p, s));
+        let payload = if binary_payload(self.payload_encoding.as_deref()) { pack(Packing::Base64, &self.payload) } else { (&self.payload).into() };
+        let mut map = Vec::new();
+        put(&mut map, 1, Some(self.version.into()));
+        put(&mut map, 2, Some(pack(Packing::Hex, &self.entry_hash)));
+        put(&mut map, 3, Some(pack(Packing::Uuid, &self.device_id)));
+        put(&mut map, 4, Some(self.timestamp.into()));
+        put(&mut map, 5, Some(pack(Packing::Uuid, &self.session_id)));
+        put(&mut map, 6, Some(self.nonce.into()));
+        put(&mut map, 7, Some((&self.algo).into()));
+        put(&mut map, 8, text(&self.key_id));
+        put(&mut map, 9, Some(payload));
+        put(&mut map, 10, Some(pack(Packing::Base64, &self.signature)));
+        put(&mut map, 11, packed(Packing::Hex, &self.previous_entry_hash));
+        put(&mut map, 12, self.boot_counter.map(Value::from));
+        put(&mut map, 13, self.entry_counter.map(Value::from));
+        put(&mut map, 14, text(&self.stream_id));
+        put(&mut map, 15, text(&self.kind));
+        put(&mut map, 16, text(&self.payload_encoding));
+        put(&mut map, 17, packed(Packing::Base64, &self.payload_salt));
+        put(&mut map, 18, packed(Packing::Hex, &self.payload_commitment));
+        put(&mut map, 19, text(&self.payload_commitment_scheme));
+        put(&mut map, 20, self.attachments.as_ref().map(|list| {
+            Value::Array(list.iter().map(|a| Value::Array(vec![pack(Packing::Hex, &a.sha256), a.size.into()])).collect())
+        }));
+        put(&mut map, 21, text(&self.signature_format));
+        put(&mut map, 22, text(&self.canon));
+        map
+    }
+
+    /// Parse an envelope map. Unknown keys are refused.
+    pub(crate) fn from_map(map: Vec<(Value, Value)>) -> Result<LogEntry, VerifyError> {
+        let mut f = Fields::new(map, &ENTRY_KEYS)?;
+        let version = f.uint(1)?.ok_or_else(|| malformed("missing version"))?;
+        let payload_encoding = f.text(16)?;
+        let payload = if binary_payload(payload_encoding.as_deref()) { f.required_packed(9, Packing::Base64)? } else { f.required_text(9)? };
+        let attachments = match f.take(20) {
+            None => None,
+            Some(Value::Array(items)) => Some(items.into_iter().map(|item| match item {
+                Value::Array(pair) => match <[Value; 2]>::try_from(pair) {
+                    Ok([hash, Value::Unsigned(size)]) => Ok(Attachment { sha256: unpack(Packing::Hex, hash, "attachments")?, size }),
+                    _ => Err(malformed("attachments: expected [sha256, size] pairs")),
+                },
+                _ => Err(malformed("attachments: expected [sha256, size] pairs")),
+            }).collect::<Result<_, _>>()?),
+            Some(_) => return Err(malformed("attachments: expected an array")),
+        };
+        Ok(LogEntry {
+            version: u8::try_from(version).map_err(|_| malformed("version out of range"))?,
+            entry_hash: f.required_packed(2, Packing::Hex)?,
+            device_id: f.required_packed(3, Packing::Uuid)?,
+            timestamp: f.int(4)?,
+            session_id: f.required_packed(5, Packing::Uuid)?,
+            nonce: f.uint(6)?.ok_or_else(|| malformed("missing nonce"))?,
+            algo: f.required_text(7)?,
+            key_id: f.text(8)?,
+            payload,
+            signature: f.required_packed(10, Packing::Base64)?,
+            previous_entry_hash: f.packed(11, Packing::Hex)?,
+            boot_counter: f.uint(12)?,
+            entry_counter: f.uint(13)?,
+            stream_id: f.text(14)?,
+            kind: f.text(15)?,
+            payload_encoding,
+            payload_salt: f.packed(17, Packing::Base64)?,
+            payload_commitment: f.packed(18, Packing::Hex)?,
+            payload_commitment_scheme: f.text(19)?,
+            attachments,
+            canon: f.text(22)?,
+            signature_format: f.text(21)?,
+        })
+    }
+}
+
+impl Ack {
+    /// The binary envelope of this ACK.
+    pub fn to_cbor(&self) -> Vec<u8> { cbor::encode(&Value::Map(self.envelope_map())) }
+
+    /// Parse a binary envelope. Unknown keys are refused.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Ack, VerifyError> { Self::from_map(decode_map(bytes)?) }
+
+    /// Envelope map, unsorted.
+    pub(crate) fn envelope_map(&self) -> Vec<(Value, Value)> {
+        let mut map = Vec::new();
+        put(&mut map, 1, Some(pack(Packing::Hex, &self.entry_id)));
+        put(&mut map, 2, Some(pack(Packing::Hex, &self.new_entry_hash)));
+        put(&mut map, 3, Some((&self.status).into()));
+        put(&mut map, 4, Some(self.timestamp.into()));
+        put(&mut map, 5, Some((&self.server_signer_id).into()));
+        put(&mut map, 6, Some(pack(Packing::Base64, &self.server_signature)));
+        put(&mut map, 7, self.log_index.map(Value::from));
+        put(&mut map, 8, self.device_seq.map(Value::from));
+        put(&mut map, 9, (!self.warnings.is_empty()).then(|| Value::Array(self.warnings.iter().map(Value::from).collect())));
+        put(&mut map, 10, self.signature_format.as_deref().map(Value::from));
+        map
+    }
+
+    /// Parse an envelope map. Unknown keys are refused.
+    pub(crate) fn from_map(map: Vec<(Value, Value)>) -> Result<Ack, VerifyError> {
+        let mut f = Fields::new(map, &ACK_KEYS)?;
+        let warnings = match f.take(9) {
+            None => Vec::new(),
+            Some(Value::Array(items)) => items.into_iter().map(|w| match w {
+                Value::Text(s) => Ok(s),
+                _ => Err(malformed("warnings: expected text")),
+            }).collect::<Result<_, _>>()?,
+            Some(_) => return Err(malformed("warnings: expected an array")),
+        };
+        Ok(Ack {
+            entry_id: f.required_packed(1, Packing::Hex)?,
+            new_entry_hash: f.required_packed(2, Packing::Hex)?,
+            status: f.required_text(3)?,
+            timestamp: f.int(4)?,
+            server_signer_id: f.required_text(5)?,
+            server_signature: f.required_packed(6, Packing::Base64)?,
+            log_index: f.uint(7)?,
+            device_seq: f.uint(8)?,
+            warnings,
+            signature_format: f.text(10)?,
+        })
+    }
+}
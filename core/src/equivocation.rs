@@ -0,0 +1,87 @@
+//! Equivocation proofs: evidence that a device signed two conflicting entries.
+//!
+//! Two different entries of the same device stream conflict when they link to the same
+//! `previous_entry_hash` (a fork of the chain) or use the same nonce in the same session.
+//! An honest device never does either, so both entries carrying valid device signatures
+//! indicate a cloned or compromised key. The proof is self-contained: anyone holding the
+//! device key can check it, and the server signature records when it was detected.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use crate::cbor::{self, Value};
+use crate::errors::VerifyError;
+use crate::traits::AckSigner;
+use crate::types::{LogEntry, VerifyingKey, compute_entry_hash};
+use crate::verifier::verify_signature;
+
+/// Domain separation label, first element of the signed tuple.
+pub const EQUIVOCATION_LABEL: &str = "taric-equivocation-v1";
+
+/// Two conflicting, validly signed entries of one device, signed by the server.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EquivocationProof {
+    pub device_id: String,
+    /// The entry accepted first.
+    pub accepted: LogEntry,
+    /// The conflicting entry, rejected.
+    pub conflicting: LogEntry,
+    /// When the server detected the conflict (Unix epoch seconds).
+    pub detected_at: i64,
+    pub server_signer_id: String,
+    pub server_signature: String,
+}
+
+/// Whether `a` and `b` are different entries holding the same chain position of one device stream.
+pub fn conflicts(a: &LogEntry, b: &LogEntry) -> bool {
+    a.device_id == b.device_id
+        && a.stream() == b.stream()
+        && a.entry_hash != b.entry_hash
+        && (a.previous_entry_hash == b.previous_entry_hash || (a.session_id == b.session_id && a.nonce == b.nonce))
+}
+
+/// Canonicalize a proof for signing: the entries are bound by their hashes.
+pub fn cbor_for_equivocation_sign(p: &EquivocationProof) -> Vec<u8> {
+    cbor::encode(&Value::Array(vec![
+        EQUIVOCATION_LABEL.into(),
+        (&p.device_id).into(),
+        (&p.accepted.entry_hash).into(),
+        (&p.conflicting.entry_hash).into(),
+        p.detected_at.into(),
+        (&p.server_signer_id).into(),
+    ]))
+}
+
+impl EquivocationProof {
+    /// Build and sign a proof from an accepted entry and a conflicting one.
+    pub fn sign(signer: &dyn AckSigner, accepted: LogEntry, conflicting: LogEntry, detected_at: i64) -> Self {
+        let mut p = EquivocationProof {
+            device_id: accepted.device_id.clone(),
+            accepted,
+            conflicting,
+            detected_at,
+            server_signer_id: signer.signer_id().into(),
+            server_signature: String::new(),
+        };
+        p.server_signature = B64.encode(signer.sign(&cbor_for_equivocation_sign(&p)));
+        p
+    }
+
+    /// Check the server signature, that both entries are intact and signed by `device_key`,
+    /// and that they conflict.
+    pub fn verify(&self, server_key: &VerifyingKey, device_key: &VerifyingKey) -> Result<(), VerifyError> {
+        verify_signature(server_key, &server_key.algo, &cbor_for_equivocation_sign(self), &self.server_signature)?;
+        for e in [&self.accepted, &self.conflicting] {
+            if e.device_id != self.device_id {
+                return Err(VerifyError::InvalidProof("entry of another device".into()));
+            }
+            if compute_entry_hash(e) != e.entry_hash { return Err(VerifyError::HashMismatch); }
+            verify_signature(device_key, &e.algo, &e.signing_input()?, &e.signature)?;
+        }
+        if !conflicts(&self.accepted, &self.conflicting) {
+            return Err(VerifyError::InvalidProof("entries do not conflict".into()));
+        }
+        Ok(())
+    }
+}
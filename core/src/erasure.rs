@@ -0,0 +1,183 @@
+//! Payload commitments and erasure (wire version 8).
+//!
+//! From version 8 the entry hash covers `payload_commitment` instead of the payload itself. With
+//! the `sha256` scheme (the only one before version 9) it is the hex SHA-256 of
+//! `payload_salt || payload` (the salt's bytes, then the payload string's UTF-8); with the
+//! `fields` scheme it is the root of a tree of salted payload fields, see `disclosure`. The salt
+//! keeps short or guessable payloads from being recovered from the commitment. Erasing an entry
+//! drops `payload` and `payload_salt` and keeps the commitment, so its hash, signature and chain
+//! links still verify. The server records each erasure in a signed `Tombstone`.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cbor::{self, Value};
+use crate::disclosure::fields_commitment;
+use crate::errors::VerifyError;
+use crate::kinds::DATA_KIND;
+use crate::payload::PayloadEncoding;
+use crate::traits::AckSigner;
+use crate::types::{compute_entry_hash, LogEntry, VerifyingKey};
+use crate::verifier::verify_signature;
+
+/// Domain separation label, first element of the signed tuple.
+pub const TOMBSTONE_LABEL: &str = "taric-tombstone-v1";
+
+/// Minimum `payload_salt` length in bytes.
+pub const MIN_SALT_LEN: usize = 16;
+
+/// How `payload_commitment` commits to the payload (`payload_commitment_scheme`, version 9 and later).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitmentScheme {
+    /// One hash over the salted payload.
+    Sha256,
+    /// A Merkle tree of salted top-level fields of a JSON object payload, for selective disclosure.
+    Fields,
+}
+
+impl CommitmentScheme {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "sha256" => Some(CommitmentScheme::Sha256),
+            "fields" => Some(CommitmentScheme::Fields),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommitmentScheme::Sha256 => "sha256",
+            CommitmentScheme::Fields => "fields",
+        }
+    }
+}
+
+/// Hex SHA-256 of `salt || payload`.
+pub fn payload_commitment(salt: &[u8], payload: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(payload.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+impl LogEntry {
+    /// Whether the payload of this version 8 entry was erased.
+    pub fn is_erased(&self) -> bool { self.version >= 8 && self.payload_salt.is_none() && self.payload.is_empty() }
+
+    /// The commitment scheme; `sha256` before version 9.
+    pub fn commitment_scheme(&self) -> Result<CommitmentScheme, VerifyError> {
+        match self.payload_commitment_scheme.as_deref() {
+            None => Ok(CommitmentScheme::Sha256),
+            Some(s) => CommitmentScheme::parse(s).ok_or_else(|| VerifyError::Malformed(format!("unknown payload_commitment_scheme {s:?}"))),
+        }
+    }
+
+    /// The commitment to `payload` with `salt` under this entry's scheme.
+    fn commitment_with(&self, salt: &[u8]) -> Result<String, VerifyError> {
+        match self.commitment_scheme()? {
+            CommitmentScheme::Sha256 => Ok(payload_commitment(salt, &self.payload)),
+            CommitmentScheme::Fields => {
+                if self.payload_encoding()? != PayloadEncoding::Utf8 {
+                    return Err(VerifyError::Malformed("fields commitment: payload must be utf8 JSON".into()));
+                }
+                fields_commitment(salt, &self.payload)
+            }
+        }
+    }
+
+    /// Set a fresh random `payload_salt` and the matching `payload_commitment`; call before
+    /// hashing and signing a version 8 entry. Fails if the payload does not suit the scheme.
+    pub fn commit_payload(&mut self) -> Result<(), VerifyError> {
+        let mut salt = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        self.payload_commitment = Some(self.commitment_with(&salt)?);
+        self.payload_salt = Some(B64.encode(salt));
+        Ok(())
+    }
+
+    /// Check `payload` and `payload_salt` match `payload_commitment` (version 8 and later).
+    pub fn check_commitment(&self) -> Result<(), VerifyError> {
+        let (Some(salt), Some(commitment)) = (&self.payload_salt, &self.payload_commitment) else { return Ok(()) };
+        let salt = B64.decode(salt).map_err(|_| VerifyError::Malformed("payload_salt is not base64".into()))?;
+        if salt.len() < MIN_SALT_LEN {
+            return Err(VerifyError::Malformed(format!("payload_salt shorter than {MIN_SALT_LEN} bytes")));
+        }
+        if self.commitment_with(&salt)? != *commitment { return Err(VerifyError::CommitmentMismatch); }
+        Ok(())
+    }
+
+    /// A copy of this entry without `payload` and `payload_salt`, which still verifies; `None`
+    /// before version 8, where the hash covers the payload.
+    pub fn redacted(&self) -> Option<LogEntry> {
+        (self.version >= 8).then(|| LogEntry { payload: String::new(), payload_salt: None, ..self.clone() })
+    }
+
+    /// A copy of this entry with its payload erased. Only `data` entries of version 8 and later
+    /// can be erased: other kinds carry chain state.
+    pub fn erased(&self) -> Result<LogEntry, VerifyError> {
+        if self.kind() != DATA_KIND { return Err(VerifyError::Malformed(format!("{} entries cannot be erased", self.kind()))); }
+        self.redacted().ok_or_else(|| VerifyError::Malformed("erasure requires version 8".into()))
+    }
+}
+
+/// Server-signed record that the payload of an entry was erased.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Tombstone {
+    pub device_id: String,
+    pub entry_hash: String,
+    /// Commitment to the erased payload, as covered by the entry hash.
+    pub payload_commitment: String,
+    /// Why the payload was erased, e.g. a request reference.
+    pub reason: String,
+    /// When the payload was erased (Unix epoch seconds).
+    pub erased_at: i64,
+    pub server_signer_id: String,
+    pub server_signature: String,
+}
+
+/// Canonicalize a tombstone for signing (exclude server_signature).
+pub fn cbor_for_tombstone_sign(t: &Tombstone) -> Vec<u8> {
+    cbor::encode(&Value::Array(vec![
+        TOMBSTONE_LABEL.into(),
+        (&t.device_id).into(),
+        (&t.entry_hash).into(),
+        (&t.payload_commitment).into(),
+        (&t.reason).into(),
+        t.erased_at.into(),
+        (&t.server_signer_id).into(),
+    ]))
+}
+
+impl Tombstone {
+    /// Build and sign a tombstone for `entry`, which must be erasable.
+    pub fn sign(signer: &dyn AckSigner, entry: &LogEntry, reason: &str, erased_at: i64) -> Result<Self, VerifyError> {
+        entry.erased()?;
+        let mut t = Tombstone {
+            device_id: entry.device_id.clone(),
+            entry_hash: entry.entry_hash.clone(),
+            payload_commitment: entry.payload_commitment.clone().unwrap_or_default(),
+            reason: reason.into(),
+            erased_at,
+            server_signer_id: signer.signer_id().into(),
+            server_signature: String::new(),
+        };
+        t.server_signature = B64.encode(signer.sign(&cbor_for_tombstone_sign(&t)));
+        Ok(t)
+    }
+
+    /// Check the server signature and that the tombstone is for `entry`, erased or not.
+    pub fn verify(&self, server_key: &VerifyingKey, entry: &LogEntry) -> Result<(), VerifyError> {
+        verify_signature(server_key, &server_key.algo, &cbor_for_tombstone_sign(self), &self.server_signature)?;
+        if compute_entry_hash(entry) != entry.entry_hash { return Err(VerifyError::HashMismatch); }
+        if entry.entry_hash != self.entry_hash || entry.device_id != self.device_id {
+            return Err(VerifyError::InvalidProof("tombstone of another entry".into()));
+        }
+        if entry.payload_commitment.as_deref() != Some(self.payload_commitment.as_str()) {
+            return Err(VerifyError::InvalidProof("tombstone commits to another payload".into()));
+        }
+        Ok(())
+    }
+}
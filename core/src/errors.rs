@@ -11,12 +11,44 @@ pub enum VerifyError {
     DeviceUnknown(String),
     #[error("revoked key for device {0}")]
     Revoked(String),
+    #[error("device retired: {0}")]
+    Retired(String),
     #[error("hash mismatch: provided entry_hash does not match computed")]
     HashMismatch,
+    /// The payload and its salt do not hash to `payload_commitment` (version 8 and later).
+    #[error("payload does not match payload_commitment")]
+    CommitmentMismatch,
+    /// A blob does not match the attachment that names it. Carries the blob hash and why.
+    #[error("attachment mismatch: {0}")]
+    AttachmentMismatch(String),
     #[error("invalid signature")]
     InvalidSignature,
     #[error("previous hash mismatch")]
     PreviousHashMismatch,
     #[error("nonce not monotonic")]
     NonceNotMonotonic,
+    /// A different, validly signed entry of the device already holds this chain position.
+    /// Carries the `entry_hash` of the accepted entry it conflicts with.
+    #[error("equivocation: conflicts with accepted entry {0}")]
+    Equivocation(String),
+    /// A new session did not boot with a higher counter than the device's last one
+    /// (or a session changed its counter). Carries the device's highest accepted boot counter.
+    #[error("boot counter rollback: device last booted with counter {0}")]
+    BootCounterRollback(u64),
+    /// The entry counter did not increase. Carries the device's last accepted counter.
+    #[error("entry counter not increasing: device last sent counter {0}")]
+    EntryCounterRegression(u64),
+    /// The device announced a new key with a `key_rotation` entry; entries signed with any
+    /// other key are rejected. Carries the current key id.
+    #[error("key rotated: device now signs with key {0}")]
+    KeyRotated(String),
+    /// A `session_end` entry closed this session. Carries the session id.
+    #[error("session ended: {0}")]
+    SessionEnded(String),
+    /// A `data` payload does not match its device class schema. Carries the class, schema
+    /// version and the first violation.
+    #[error("schema violation: {0}")]
+    SchemaViolation(String),
+    #[error("invalid proof: {0}")]
+    InvalidProof(String),
 }
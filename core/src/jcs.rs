@@ -0,0 +1,125 @@
+//! JSON Canonicalization Scheme (RFC 8785), the canonical form of entries with `canon` `jcs`
+//! (version 11 and later), for clients without a deterministic CBOR encoder.
+//!
+//! Object members are sorted by the UTF-16 code units of their names and written without
+//! whitespace; strings escape only `"`, `\` and control characters (`\b`, `\t`, `\n`, `\f`, `\r`
+//! by name, the others as `\u00xx`). Numbers are written as ECMAScript's `Number.prototype.toString`
+//! writes the IEEE 754 double: integers as plain decimal digits, which is exact as long as they
+//! are I-JSON safe (magnitude at most `MAX_SAFE_INTEGER`; `check_numbers` refuses others), and
+//! other values in their shortest round-trip form. Entries hold no numbers but integers
+//! (`check_integers`). In JavaScript the same bytes come from `JSON.stringify` of the object with
+//! its keys sorted, encoded as UTF-8.
+
+use serde_json::Value;
+
+/// Largest integer magnitude an I-JSON number holds exactly (2^53 - 1).
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+fn string(out: &mut Vec<u8>, s: &str) {
+    out.push(b'"');
+    for c in s.chars() {
+        match c {
+            '"' => out.extend(b"\\\""),
+            '\\' => out.extend(b"\\\\"),
+            '\u{8}' => out.extend(b"\\b"),
+            '\t' => out.extend(b"\\t"),
+            '\n' => out.extend(b"\\n"),
+            '\u{c}' => out.extend(b"\\f"),
+            '\r' => out.extend(b"\\r"),
+            c if c < ' ' => out.extend(format!("\\u{:04x}", c as u32).as_bytes()),
+            c => out.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    out.push(b'"');
+}
+
+/// A non-integer number as ECMAScript writes it: the shortest digits that round-trip, in plain
+/// notation for decimal exponents from -6 to 20, otherwise as `d.ddde±x`.
+fn number(out: &mut Vec<u8>, x: f64) {
+    if x == 0.0 { return out.push(b'0'); }
+    if x < 0.0 { out.push(b'-'); }
+    // `{:e}` gives the shortest round-trip digits: `d[.ddd]e<exp>`.
+    let sci = format!("{:e}", x.abs());
+    let (mantissa, exp) = sci.split_once('e').expect("exponent");
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    // x = 0.digits * 10^n
+    let n = exp.parse::<i32>().expect("integer exponent") + 1;
+    let text = if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat(-n as usize))
+    } else {
+        let sign = if n > 0 { '+' } else { '-' };
+        match digits.split_at(1) {
+            (d, "") => format!("{d}e{sign}{}", (n - 1).abs()),
+            (d, rest) => format!("{d}.{rest}e{sign}{}", (n - 1).abs()),
+        }
+    };
+    out.extend(text.as_bytes());
+}
+
+fn encode_into(out: &mut Vec<u8>, v: &Value) {
+    match v {
+        Value::Null => out.extend(b"null"),
+        Value::Bool(b) => out.extend(if *b { &b"true"[..] } else { &b"false"[..] }),
+        // Integers in full; see `check_numbers`.
+        Value::Number(n) if n.is_i64() || n.is_u64() => out.extend(n.to_string().as_bytes()),
+        Value::Number(n) => number(out, n.as_f64().expect("a JSON number is finite")),
+        Value::String(s) => string(out, s),
+        Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 { out.push(b','); }
+                encode_into(out, item);
+            }
+            out.push(b']');
+        }
+        Value::Object(members) => {
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+            out.push(b'{');
+            for (i, (name, value)) in members.into_iter().enumerate() {
+                if i > 0 { out.push(b','); }
+                string(out, name);
+                out.push(b':');
+                encode_into(out, value);
+            }
+            out.push(b'}');
+        }
+    }
+}
+
+/// The canonical UTF-8 text of `v`.
+pub fn encode(v: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(&mut out, v);
+    out
+}
+
+/// Check every number in `v` that is an integer is one RFC 8785 writes exactly, i.e. an I-JSON
+/// safe one; other numbers are doubles already.
+pub fn check_numbers(v: &Value) -> Result<(), String> {
+    check(v, false)
+}
+
+/// Check every number in `v` is an integer RFC 8785 writes exactly, i.e. an I-JSON safe one.
+pub fn check_integers(v: &Value) -> Result<(), String> {
+    check(v, true)
+}
+
+fn check(v: &Value, integers_only: bool) -> Result<(), String> {
+    match v {
+        Value::Number(n) => match n.as_i64().map(i64::unsigned_abs).or(n.as_u64()) {
+            Some(m) if m <= MAX_SAFE_INTEGER => Ok(()),
+            Some(_) => Err(format!("integer {n} is beyond 2^53 - 1")),
+            None if integers_only => Err(format!("number {n} is not an integer")),
+            None => Ok(()),
+        },
+        Value::Array(items) => items.iter().try_for_each(|v| check(v, integers_only)),
+        Value::Object(members) => members.values().try_for_each(|v| check(v, integers_only)),
+        _ => Ok(()),
+    }
+}
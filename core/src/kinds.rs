@@ -0,0 +1,124 @@
+//! Entry kinds (wire version 5): a registry of well-known kinds whose payloads the core
+//! validates and acts upon. `data` is the opaque default and the kind of every entry before
+//! version 5; the other kinds carry a JSON object payload with the fields below.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::errors::VerifyError;
+use crate::payload::PayloadEncoding;
+use crate::types::{LogEntry, VerifyingKey};
+
+/// Kind of entries without a `kind` field.
+pub const DATA_KIND: &str = "data";
+
+/// Every kind the core understands; other values are rejected.
+pub const KNOWN_KINDS: [&str; 5] = [DATA_KIND, "key_rotation", "session_end", "firmware_update", "tamper_alarm"];
+
+/// `key_rotation`: from the next entry on, the device signs with this key. The entry itself is
+/// signed with the key being replaced, which proves the device held it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct KeyRotation {
+    pub key_id: String,
+    pub algo: String,
+    pub pubkey_base64: String,
+}
+
+/// `session_end`: the entry's session is closed; later entries in it are rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SessionEnd {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// `firmware_update`: the device now runs `to_version`, the image with this SHA-256.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FirmwareUpdate {
+    /// Version the device ran before; must match the last reported one, if any.
+    #[serde(default)]
+    pub from_version: Option<String>,
+    pub to_version: String,
+    /// Hex SHA-256 of the installed image.
+    pub image_sha256: String,
+}
+
+/// `tamper_alarm`: a tamper sensor fired; the device is quarantined.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TamperAlarm {
+    pub sensor: String,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+/// A validated entry kind and its parsed payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    /// Opaque payload.
+    Data,
+    KeyRotation(KeyRotation),
+    SessionEnd(SessionEnd),
+    FirmwareUpdate(FirmwareUpdate),
+    TamperAlarm(TamperAlarm),
+}
+
+impl KeyRotation {
+    /// The announced key.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey {
+            algo: self.algo.clone(),
+            key: B64.decode(&self.pubkey_base64).unwrap_or_default(),
+            key_id: Some(self.key_id.clone()),
+        }
+    }
+}
+
+fn malformed(kind: &str, msg: impl std::fmt::Display) -> VerifyError {
+    VerifyError::Malformed(format!("{kind} payload: {msg}"))
+}
+
+fn payload<T: DeserializeOwned>(e: &LogEntry) -> Result<T, VerifyError> {
+    serde_json::from_str(&e.payload).map_err(|err| malformed(e.kind(), err))
+}
+
+fn non_empty(kind: &str, name: &str, value: &str) -> Result<(), VerifyError> {
+    if value.is_empty() { Err(malformed(kind, format!("empty {name}"))) } else { Ok(()) }
+}
+
+/// Check the entry's kind is registered and its payload matches the kind's schema.
+pub fn parse(e: &LogEntry) -> Result<EntryKind, VerifyError> {
+    let kind = e.kind();
+    if kind != DATA_KIND && e.payload_encoding()? != PayloadEncoding::Utf8 {
+        return Err(malformed(kind, "must be utf8 JSON"));
+    }
+    match kind {
+        DATA_KIND => Ok(EntryKind::Data),
+        "key_rotation" => {
+            let k: KeyRotation = payload(e)?;
+            non_empty(kind, "key_id", &k.key_id)?;
+            if e.key_id.is_none() { return Err(malformed(kind, "entry must carry the key_id being replaced")); }
+            if e.key_id.as_deref() == Some(k.key_id.as_str()) { return Err(malformed(kind, "key_id unchanged")); }
+            if k.algo != "ed25519" { return Err(VerifyError::UnsupportedAlgo(k.algo)); }
+            match B64.decode(&k.pubkey_base64) {
+                Ok(key) if key.len() == 32 => Ok(EntryKind::KeyRotation(k)),
+                _ => Err(malformed(kind, "pubkey_base64 is not a base64 ed25519 key")),
+            }
+        }
+        "session_end" => Ok(EntryKind::SessionEnd(payload(e)?)),
+        "firmware_update" => {
+            let f: FirmwareUpdate = payload(e)?;
+            non_empty(kind, "to_version", &f.to_version)?;
+            if f.image_sha256.len() != 64 || !f.image_sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(malformed(kind, "image_sha256 is not a hex SHA-256"));
+            }
+            Ok(EntryKind::FirmwareUpdate(f))
+        }
+        "tamper_alarm" => {
+            let t: TamperAlarm = payload(e)?;
+            non_empty(kind, "sensor", &t.sensor)?;
+            Ok(EntryKind::TamperAlarm(t))
+        }
+        other => Err(VerifyError::Malformed(format!("unknown kind {other:?}"))),
+    }
+}
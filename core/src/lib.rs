@@ -1,23 +1,69 @@
 //! TARIC core library: verification, chaining, and ACK signing.
 //!
 //! Implements the wire format from `docs/api/wire-format.md`:
-//! - CBOR-based canonicalization for hashing and signing
+//! - CBOR-based canonicalization for hashing and signing (RFC 8949 deterministic encoding),
+//!   with published test vectors
 //! - SHA-256 entry hashing (hex-encoded)
 //! - Ed25519 signature verification for device entries
 //! - Server ACK generation and signing
-//! - Pluggable device trust and chain state
+//! - Signed chain heads for device resynchronization
+//! - RFC 6962 Merkle tree over accepted entries (global transparency log),
+//!   with inclusion and consistency proofs
+//! - Signed checkpoints (tree heads) of that log and witness co-signatures
+//! - Detection of equivocating devices (conflicting signed entries) with signed proofs
+//! - Device quarantine driven by tamper indicators
+//! - Boot counters (wire version 2) to detect device state rollback
+//! - Cumulative entry counters (wire version 3) to detect truncated chains
+//! - Several independent chains (streams) per device (wire version 4)
+//! - Typed entry kinds (wire version 5): key rotation, session end, firmware update and
+//!   tamper alarm payloads are validated and acted upon; `data` stays opaque
+//! - Explicit payload encodings (wire version 6): `utf8`, `base64` or `cbor`, with decoding helpers
+//! - Optional payload schema validation per device class (JSON Schema subset)
+//! - End-to-end encrypted payloads (HPKE, wire version 7), verified and chained without decrypting
+//! - Salted payload commitments (wire version 8): payloads can be erased, with a signed tombstone, while the chain still verifies
+//! - Selective disclosure of payload fields committed as a salted Merkle tree (wire version 9)
+//! - Content-addressed attachments (wire version 10): blobs stored apart, named by SHA-256 and size
+//! - RFC 8785 canonical JSON as an alternative canonical form (wire version 11)
+//! - Binary CBOR envelope of entries and ACKs, converting losslessly to and from JSON
+//! - COSE_Sign1 signing of entries and ACKs, verified alongside the native form
+//! - Pluggable device trust, chain state and device status
 //!
 //! See `docs/context.md` for the high-level overview.
 
+pub mod attachments;
+pub mod cbor;
+pub mod checkpoint;
+pub mod cose;
+pub mod disclosure;
+pub mod encryption;
+pub mod envelope;
+pub mod erasure;
+pub mod equivocation;
 pub mod errors;
+pub mod jcs;
+pub mod kinds;
+pub mod merkle;
+pub mod payload;
+pub mod schema;
 pub mod traits;
 pub mod types;
+pub mod vectors;
 pub mod verifier;
 
+pub use attachments::Attachment;
+pub use checkpoint::{Checkpoint, Cosignature};
+pub use disclosure::{DisclosedField, Disclosure};
+pub use encryption::EncryptedPayload;
+pub use erasure::{CommitmentScheme, Tombstone};
+pub use equivocation::EquivocationProof;
 pub use errors::VerifyError;
-pub use traits::{AckSigner, ChainStore, DeviceTrust};
-pub use types::{Ack, LogEntry, VerifyingKey, cbor_for_ack_sign};
-pub use verifier::{Ed25519AckSigner, InMemoryChainStore, Verifier};
+pub use kinds::{EntryKind, DATA_KIND, KNOWN_KINDS};
+pub use merkle::{ConsistencyProof, InclusionProof, MerkleTree};
+pub use payload::{DecodedPayload, PayloadEncoding};
+pub use schema::{PayloadSchema, SchemaPolicy};
+pub use traits::{AckSigner, ChainStore, DeviceStatusStore, DeviceTrust};
+pub use types::{Ack, ChainHead, DeviceStatus, LogEntry, CANON_CBOR, CANON_JCS, DEFAULT_STREAM, LATEST_ENTRY_VERSION, SessionHead, VerifyingKey, cbor_for_ack_sign, cbor_for_head_sign};
+pub use verifier::{Ed25519AckSigner, InMemoryChainStore, InMemoryDeviceStatus, RollbackPolicy, Verifier, DEFAULT_SIGNATURE_FAILURE_LIMIT};
 
 /// Library version string.
 pub fn version() -> &'static str { "taric-core 0.1.0" }
@@ -0,0 +1,272 @@
+//! RFC 6962 Merkle tree over accepted entries (the global transparency log).
+//!
+//! Leaf `i` is the `i`-th accepted entry in server order. Its leaf data is the 32 raw bytes
+//! of the entry's `entry_hash`, so any party holding the entries can recompute the tree:
+//! - leaf hash: `SHA-256(0x00 || leaf data)`
+//! - node hash: `SHA-256(0x01 || left || right)`
+//! - root of the empty tree: `SHA-256("")`
+//!
+//! Inclusion and consistency proofs follow RFC 6962 section 2.1; verification follows the
+//! algorithms of RFC 9162 section 2.1.3.2 and 2.1.4.2.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::errors::VerifyError;
+
+/// A 32-byte SHA-256 tree hash.
+pub type Hash = [u8; 32];
+
+/// Hash of a leaf's data.
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    let mut h = Sha256::new();
+    h.update([0x00]);
+    h.update(data);
+    h.finalize().into()
+}
+
+/// Hash of an interior node.
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut h = Sha256::new();
+    h.update([0x01]);
+    h.update(left);
+    h.update(right);
+    h.finalize().into()
+}
+
+/// Root hash of the empty tree.
+pub fn empty_root() -> Hash { Sha256::digest([]).into() }
+
+/// Leaf hash for an accepted entry, from its hex `entry_hash`.
+pub fn entry_leaf_hash(entry_hash: &str) -> Result<Hash, VerifyError> {
+    let bytes = hex::decode(entry_hash).map_err(|_| VerifyError::Malformed("entry_hash hex".into()))?;
+    if bytes.len() != 32 { return Err(VerifyError::Malformed("entry_hash length".into())); }
+    Ok(leaf_hash(&bytes))
+}
+
+/// Largest power of two strictly less than `n` (`n >= 2`).
+pub(crate) fn split_point(n: u64) -> u64 {
+    debug_assert!(n >= 2);
+    1 << (63 - (n - 1).leading_zeros())
+}
+
+/// Append-only Merkle tree.
+///
+/// Keeps the hash of every complete, aligned subtree (`levels[k][i]` covers leaves
+/// `i*2^k .. (i+1)*2^k`), so appends are O(log n) and the root of any earlier size can
+/// still be computed.
+#[derive(Debug, Clone, Default)]
+pub struct MerkleTree {
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    /// Create an empty tree.
+    pub fn new() -> Self { Self::default() }
+
+    /// Number of leaves.
+    pub fn len(&self) -> u64 { self.levels.first().map_or(0, |l| l.len() as u64) }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Append a leaf hash; returns its leaf index.
+    pub fn push_leaf_hash(&mut self, leaf: Hash) -> u64 {
+        let index = self.len();
+        let mut node = leaf;
+        let mut level = 0;
+        loop {
+            if self.levels.len() == level { self.levels.push(Vec::new()); }
+            self.levels[level].push(node);
+            let n = self.levels[level].len();
+            if n % 2 == 1 { break; }
+            node = node_hash(&self.levels[level][n - 2], &self.levels[level][n - 1]);
+            level += 1;
+        }
+        index
+    }
+
+    /// Append an accepted entry by its hex `entry_hash`; returns its leaf index.
+    pub fn push_entry_hash(&mut self, entry_hash: &str) -> Result<u64, VerifyError> {
+        Ok(self.push_leaf_hash(entry_leaf_hash(entry_hash)?))
+    }
+
+    /// Root hash of the current tree.
+    pub fn root(&self) -> Hash { self.root_at(self.len()).expect("current size is valid") }
+
+    /// Root hash of the tree as it was when it had `size` leaves.
+    pub fn root_at(&self, size: u64) -> Option<Hash> {
+        if size > self.len() { return None; }
+        if size == 0 { return Some(empty_root()); }
+        Some(self.subtree(0, size))
+    }
+
+    /// Audit path proving leaf `index` is in the tree of the first `tree_size` leaves.
+    pub fn inclusion_proof(&self, index: u64, tree_size: u64) -> Option<Vec<Hash>> {
+        if index >= tree_size || tree_size > self.len() { return None; }
+        let mut path = Vec::new();
+        self.path(index, 0, tree_size, &mut path);
+        Some(path)
+    }
+
+    /// Proof that the tree of `first` leaves is a prefix of the tree of `second` leaves.
+    pub fn consistency_proof(&self, first: u64, second: u64) -> Option<Vec<Hash>> {
+        if first > second || second > self.len() { return None; }
+        let mut proof = Vec::new();
+        if first > 0 && first < second { self.subproof(first, 0, second, true, &mut proof); }
+        Some(proof)
+    }
+
+    /// RFC 6962 `PATH(m, D[lo:hi])`, `m` relative to `lo`.
+    fn path(&self, m: u64, lo: u64, hi: u64, out: &mut Vec<Hash>) {
+        let n = hi - lo;
+        if n == 1 { return; }
+        let k = split_point(n);
+        if m < k {
+            self.path(m, lo, lo + k, out);
+            out.push(self.subtree(lo + k, hi));
+        } else {
+            self.path(m - k, lo + k, hi, out);
+            out.push(self.subtree(lo, lo + k));
+        }
+    }
+
+    /// RFC 6962 `SUBPROOF(m, D[lo:hi], b)`.
+    fn subproof(&self, m: u64, lo: u64, hi: u64, b: bool, out: &mut Vec<Hash>) {
+        let n = hi - lo;
+        if m == n {
+            if !b { out.push(self.subtree(lo, hi)); }
+            return;
+        }
+        let k = split_point(n);
+        if m <= k {
+            self.subproof(m, lo, lo + k, b, out);
+            out.push(self.subtree(lo + k, hi));
+        } else {
+            self.subproof(m - k, lo + k, hi, false, out);
+            out.push(self.subtree(lo, lo + k));
+        }
+    }
+
+    /// Hash of leaves `lo..hi` (`hi > lo`), following the RFC 6962 split.
+    pub(crate) fn subtree(&self, lo: u64, hi: u64) -> Hash {
+        let n = hi - lo;
+        if n.is_power_of_two() && lo.is_multiple_of(n) {
+            return self.levels[n.trailing_zeros() as usize][(lo / n) as usize];
+        }
+        let k = split_point(n);
+        node_hash(&self.subtree(lo, lo + k), &self.subtree(lo + k, hi))
+    }
+}
+
+/// Check that `leaf` is leaf `index` of the tree of `tree_size` leaves with root `root`.
+pub fn verify_inclusion(leaf: &Hash, index: u64, tree_size: u64, path: &[Hash], root: &Hash) -> Result<(), VerifyError> {
+    if index >= tree_size { return Err(VerifyError::InvalidProof("leaf index beyond tree size".into())); }
+    let (mut fn_, mut sn) = (index, tree_size - 1);
+    let mut r = *leaf;
+    for p in path {
+        if sn == 0 { return Err(VerifyError::InvalidProof("inclusion path too long".into())); }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 { fn_ >>= 1; sn >>= 1; }
+        } else {
+            r = node_hash(&r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    if sn != 0 { return Err(VerifyError::InvalidProof("inclusion path too short".into())); }
+    if r != *root { return Err(VerifyError::InvalidProof("inclusion root mismatch".into())); }
+    Ok(())
+}
+
+/// Check that the tree of `first` leaves with root `first_root` is a prefix of the tree of
+/// `second` leaves with root `second_root`.
+pub fn verify_consistency(first: u64, second: u64, first_root: &Hash, second_root: &Hash, proof: &[Hash]) -> Result<(), VerifyError> {
+    let invalid = |why: &str| Err(VerifyError::InvalidProof(why.into()));
+    if first > second { return invalid("first tree larger than second"); }
+    if first == second {
+        if !proof.is_empty() { return invalid("non-empty proof for equal sizes"); }
+        return if first_root == second_root { Ok(()) } else { invalid("roots differ for equal sizes") };
+    }
+    if first == 0 {
+        // The empty tree is a prefix of every tree.
+        return if proof.is_empty() { Ok(()) } else { invalid("non-empty proof from empty tree") };
+    }
+    let mut path: Vec<Hash> = Vec::with_capacity(proof.len() + 1);
+    if first.is_power_of_two() { path.push(*first_root); }
+    path.extend_from_slice(proof);
+    let Some((first_node, rest)) = path.split_first() else { return invalid("empty consistency proof") };
+
+    let (mut fn_, mut sn) = (first - 1, second - 1);
+    while fn_ & 1 == 1 { fn_ >>= 1; sn >>= 1; }
+    let (mut fr, mut sr) = (*first_node, *first_node);
+    for c in rest {
+        if sn == 0 { return invalid("consistency proof too long"); }
+        if fn_ & 1 == 1 || fn_ == sn {
+            fr = node_hash(c, &fr);
+            sr = node_hash(c, &sr);
+            while fn_ & 1 == 0 && fn_ != 0 { fn_ >>= 1; sn >>= 1; }
+        } else {
+            sr = node_hash(&sr, c);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    if sn != 0 { return invalid("consistency proof too short"); }
+    if fr != *first_root || sr != *second_root { return invalid("consistency root mismatch"); }
+    Ok(())
+}
+
+fn decode_path(path: &[String]) -> Result<Vec<Hash>, VerifyError> {
+    path.iter()
+        .map(|h| {
+            let bytes = hex::decode(h).map_err(|_| VerifyError::Malformed("proof hash hex".into()))?;
+            bytes.try_into().map_err(|_| VerifyError::Malformed("proof hash length".into()))
+        })
+        .collect()
+}
+
+fn encode_path(path: &[Hash]) -> Vec<String> { path.iter().map(hex::encode).collect() }
+
+/// Inclusion proof as served over the API (hashes hex-encoded).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InclusionProof {
+    pub leaf_index: u64,
+    pub tree_size: u64,
+    pub audit_path: Vec<String>,
+}
+
+impl InclusionProof {
+    pub fn new(leaf_index: u64, tree_size: u64, path: &[Hash]) -> Self {
+        Self { leaf_index, tree_size, audit_path: encode_path(path) }
+    }
+
+    /// Check that the entry with hex `entry_hash` is in the tree with root `root`.
+    pub fn verify(&self, entry_hash: &str, root: &Hash) -> Result<(), VerifyError> {
+        self.verify_leaf(&entry_leaf_hash(entry_hash)?, root)
+    }
+
+    /// Check that the leaf with hash `leaf` is in the tree with root `root`.
+    pub fn verify_leaf(&self, leaf: &Hash, root: &Hash) -> Result<(), VerifyError> {
+        verify_inclusion(leaf, self.leaf_index, self.tree_size, &decode_path(&self.audit_path)?, root)
+    }
+}
+
+/// Consistency proof as served over the API (hashes hex-encoded).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConsistencyProof {
+    pub first: u64,
+    pub second: u64,
+    pub consistency_path: Vec<String>,
+}
+
+impl ConsistencyProof {
+    pub fn new(first: u64, second: u64, path: &[Hash]) -> Self {
+        Self { first, second, consistency_path: encode_path(path) }
+    }
+
+    /// Check that the tree with `first_root` is a prefix of the tree with `second_root`.
+    pub fn verify(&self, first_root: &Hash, second_root: &Hash) -> Result<(), VerifyError> {
+        verify_consistency(self.first, self.second, first_root, second_root, &decode_path(&self.consistency_path)?)
+    }
+}
@@ -0,0 +1,142 @@
+//! Payload encodings (wire version 6). `payload` is always a JSON string; `payload_encoding`
+//! says how it maps to the bytes the device logged:
+//! - `utf8`: the text itself (the default, and the only encoding before version 6)
+//! - `base64`: standard base64 of raw bytes
+//! - `cbor`: standard base64 of exactly one CBOR data item of the data model `cbor::decode`
+//!   reads (no floats or tags)
+//! - `hpke` (version 7 and later): an end-to-end encrypted payload, see `encryption`
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use serde_json::Value as JsonValue;
+
+use crate::cbor::{self, Value as CborValue};
+use crate::encryption::EncryptedPayload;
+use crate::errors::VerifyError;
+use crate::types::LogEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadEncoding { Utf8, Base64, Cbor, Hpke }
+
+impl PayloadEncoding {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "utf8" => Some(PayloadEncoding::Utf8),
+            "base64" => Some(PayloadEncoding::Base64),
+            "cbor" => Some(PayloadEncoding::Cbor),
+            "hpke" => Some(PayloadEncoding::Hpke),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayloadEncoding::Utf8 => "utf8",
+            PayloadEncoding::Base64 => "base64",
+            PayloadEncoding::Cbor => "cbor",
+            PayloadEncoding::Hpke => "hpke",
+        }
+    }
+}
+
+/// A payload decoded according to its encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedPayload {
+    Text(String),
+    Bytes(Vec<u8>),
+    Cbor(CborValue),
+    /// Readable only with the recipient's key (`LogEntry::decrypt_payload`).
+    Encrypted(EncryptedPayload),
+}
+
+impl DecodedPayload {
+    /// Render for display: text as a string, raw bytes as a hex string, CBOR as the equivalent
+    /// JSON value (byte strings as hex, non-text map keys in their JSON form, integers below
+    /// `i64::MIN` as decimal strings), an encrypted payload as its envelope.
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            DecodedPayload::Text(s) => JsonValue::String(s.clone()),
+            DecodedPayload::Bytes(b) => JsonValue::String(hex::encode(b)),
+            DecodedPayload::Cbor(v) => cbor_to_json(v),
+            DecodedPayload::Encrypted(p) => serde_json::to_value(p).expect("envelope serializes"),
+        }
+    }
+}
+
+/// Interpret logged bytes per `encoding` (not `hpke`).
+fn decode_bytes(encoding: PayloadEncoding, bytes: Vec<u8>) -> Result<DecodedPayload, VerifyError> {
+    match encoding {
+        PayloadEncoding::Utf8 => String::from_utf8(bytes).map(DecodedPayload::Text).map_err(|_| VerifyError::Malformed("payload is not UTF-8".into())),
+        PayloadEncoding::Base64 => Ok(DecodedPayload::Bytes(bytes)),
+        PayloadEncoding::Cbor => cbor::decode(&bytes)
+            .map(DecodedPayload::Cbor)
+            .map_err(|e| VerifyError::Malformed(format!("payload is not one CBOR item: {e}"))),
+        PayloadEncoding::Hpke => Err(VerifyError::Malformed("nested encryption".into())),
+    }
+}
+
+fn cbor_to_json(v: &CborValue) -> JsonValue {
+    match v {
+        CborValue::Null => JsonValue::Null,
+        CborValue::Bool(b) => JsonValue::Bool(*b),
+        CborValue::Unsigned(n) => (*n).into(),
+        CborValue::Negative(n) => match i64::try_from(*n) {
+            Ok(n) => (-1 - n).into(),
+            Err(_) => JsonValue::String((-1 - i128::from(*n)).to_string()),
+        },
+        CborValue::Bytes(b) => JsonValue::String(hex::encode(b)),
+        CborValue::Text(s) => JsonValue::String(s.clone()),
+        CborValue::Array(items) => items.iter().map(cbor_to_json).collect(),
+        CborValue::Map(m) => m.iter()
+            .map(|(k, v)| {
+                let key = match k { CborValue::Text(s) => s.clone(), other => cbor_to_json(other).to_string() };
+                (key, cbor_to_json(v))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+    }
+}
+
+impl LogEntry {
+    /// The payload encoding; `utf8` before version 6.
+    pub fn payload_encoding(&self) -> Result<PayloadEncoding, VerifyError> {
+        match self.payload_encoding.as_deref() {
+            None => Ok(PayloadEncoding::Utf8),
+            Some(s) => PayloadEncoding::parse(s).ok_or_else(|| VerifyError::Malformed(format!("unknown payload_encoding {s:?}"))),
+        }
+    }
+
+    /// The bytes the device logged: the UTF-8 text, or the base64-decoded bytes (for `cbor`,
+    /// the CBOR item exactly as sent). For `hpke`, the envelope's UTF-8 text.
+    pub fn payload_bytes(&self) -> Result<Vec<u8>, VerifyError> {
+        match self.payload_encoding()? {
+            PayloadEncoding::Utf8 | PayloadEncoding::Hpke => Ok(self.payload.as_bytes().to_vec()),
+            PayloadEncoding::Base64 | PayloadEncoding::Cbor => {
+                B64.decode(&self.payload).map_err(|_| VerifyError::Malformed("payload is not base64".into()))
+            }
+        }
+    }
+
+    /// Decode the payload according to its encoding; an `hpke` payload's envelope is checked
+    /// but not decrypted.
+    pub fn decoded_payload(&self) -> Result<DecodedPayload, VerifyError> {
+        if self.is_erased() { return Err(VerifyError::Malformed("payload erased".into())); }
+        match self.payload_encoding()? {
+            PayloadEncoding::Utf8 => Ok(DecodedPayload::Text(self.payload.clone())),
+            PayloadEncoding::Hpke => EncryptedPayload::parse(&self.payload).map(DecodedPayload::Encrypted),
+            encoding => decode_bytes(encoding, self.payload_bytes()?),
+        }
+    }
+
+    /// Decrypt an `hpke` payload with the recipient's X25519 private key and decode the
+    /// plaintext per its `plaintext_encoding`. Other payloads are decoded as is.
+    pub fn decrypt_payload(&self, recipient_sk: &[u8; 32]) -> Result<DecodedPayload, VerifyError> {
+        match self.decoded_payload()? {
+            DecodedPayload::Encrypted(p) => {
+                let encoding = p.plaintext_encoding().ok_or_else(|| VerifyError::Malformed("invalid plaintext_encoding".into()))?;
+                decode_bytes(encoding, p.open(recipient_sk, &self.device_id)?)
+            }
+            plain => Ok(plain),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Payload schemas per device class: a JSON Schema subset checked against `data` payloads.
+//!
+//! Supported keywords: `type` (a name or a list of names), `enum`, `const`, `properties`,
+//! `required`, `additionalProperties` (boolean or schema), `items`, `minItems`, `maxItems`,
+//! `minLength`, `maxLength`, `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`.
+//! Other keywords are ignored, as JSON Schema does with unknown keywords. `true` and `false`
+//! are the schemas accepting everything and nothing.
+//!
+//! `utf8` payloads must be JSON text; `cbor` payloads are checked in their JSON form
+//! (`DecodedPayload::to_json`); `base64` payloads cannot match a schema. Encrypted (`hpke`)
+//! payloads are not checked: the verifier cannot read them.
+
+use serde_json::Value;
+
+use crate::errors::VerifyError;
+use crate::payload::DecodedPayload;
+use crate::types::LogEntry;
+
+/// One version of the payload schema of a device class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadSchema {
+    pub class: String,
+    pub version: u32,
+    pub schema: Value,
+}
+
+/// What to do with a `data` entry whose payload does not match its device's schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaPolicy {
+    /// Reject it with `VerifyError::SchemaViolation`.
+    Reject,
+    /// Accept it; the ACK carries the violation in `warnings`.
+    Warn,
+}
+
+impl PayloadSchema {
+    /// Build a schema version, checking `schema` is an object or a boolean.
+    pub fn new(class: impl Into<String>, version: u32, schema: Value) -> Result<Self, String> {
+        let class = class.into();
+        if !schema.is_object() && !schema.is_boolean() {
+            return Err(format!("schema {class} v{version} is not an object or a boolean"));
+        }
+        Ok(Self { class, version, schema })
+    }
+
+    /// Check the payload of `entry` against this schema.
+    pub fn check(&self, entry: &LogEntry) -> Result<(), VerifyError> {
+        let violation = |msg: String| VerifyError::SchemaViolation(format!("{} v{}: {msg}", self.class, self.version));
+        let instance = match entry.decoded_payload()? {
+            DecodedPayload::Text(s) => serde_json::from_str(&s).map_err(|e| violation(format!("payload is not JSON: {e}")))?,
+            cbor @ DecodedPayload::Cbor(_) => cbor.to_json(),
+            DecodedPayload::Bytes(_) => return Err(violation("binary payload".into())),
+            DecodedPayload::Encrypted(_) => return Ok(()),
+        };
+        validate(&self.schema, &instance, "$").map_err(violation)
+    }
+}
+
+fn type_matches(name: &str, v: &Value) -> bool {
+    match name {
+        "null" => v.is_null(),
+        "boolean" => v.is_boolean(),
+        "object" => v.is_object(),
+        "array" => v.is_array(),
+        "string" => v.is_string(),
+        "number" => v.is_number(),
+        "integer" => v.as_f64().is_some_and(|f| f.fract() == 0.0),
+        _ => false,
+    }
+}
+
+/// Validate `v` (at JSON path `path`) against `schema`; the error names the first violation.
+pub fn validate(schema: &Value, v: &Value, path: &str) -> Result<(), String> {
+    let s = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return Err(format!("{path}: no value allowed")),
+        Value::Object(s) => s,
+        _ => return Err(format!("{path}: invalid schema")),
+    };
+    let fail = |msg: String| Err(format!("{path}: {msg}"));
+
+    if let Some(t) = s.get("type") {
+        let names: Vec<&str> = match t {
+            Value::String(n) => vec![n.as_str()],
+            Value::Array(ns) => ns.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !names.iter().any(|n| type_matches(n, v)) {
+            return fail(format!("expected {}", names.join(" or ")));
+        }
+    }
+    if let Some(Value::Array(options)) = s.get("enum") {
+        if !options.contains(v) { return fail(format!("{v} is not one of {}", Value::Array(options.clone()))); }
+    }
+    if let Some(c) = s.get("const") {
+        if c != v { return fail(format!("expected {c}")); }
+    }
+
+    match v {
+        Value::Object(obj) => {
+            if let Some(Value::Array(required)) = s.get("required") {
+                if let Some(missing) = required.iter().filter_map(Value::as_str).find(|k| !obj.contains_key(*k)) {
+                    return fail(format!("missing property {missing:?}"));
+                }
+            }
+            let props = s.get("properties").and_then(Value::as_object);
+            for (k, item) in obj {
+                let item_path = format!("{path}.{k}");
+                match (props.and_then(|p| p.get(k)), s.get("additionalProperties")) {
+                    (Some(p), _) => validate(p, item, &item_path)?,
+                    (None, Some(Value::Bool(false))) => return fail(format!("unexpected property {k:?}")),
+                    (None, Some(extra)) => validate(extra, item, &item_path)?,
+                    (None, None) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = s.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min { return fail(format!("fewer than {min} items")); }
+            }
+            if let Some(max) = s.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max { return fail(format!("more than {max} items")); }
+            }
+            if let Some(item_schema) = s.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate(item_schema, item, &format!("{path}[{i}]"))?;
+                }
+            }
+        }
+        Value::String(text) => {
+            let len = text.chars().count() as u64;
+            if let Some(min) = s.get("minLength").and_then(Value::as_u64) {
+                if len < min { return fail(format!("shorter than {min} characters")); }
+            }
+            if let Some(max) = s.get("maxLength").and_then(Value::as_u64) {
+                if len > max { return fail(format!("longer than {max} characters")); }
+            }
+        }
+        Value::Number(n) => {
+            let x = n.as_f64().unwrap_or(f64::NAN);
+            let bound = |k: &str| s.get(k).and_then(Value::as_f64);
+            if bound("minimum").is_some_and(|m| x < m) { return fail(format!("{n} is below {}", s["minimum"])); }
+            if bound("maximum").is_some_and(|m| x > m) { return fail(format!("{n} is above {}", s["maximum"])); }
+            if bound("exclusiveMinimum").is_some_and(|m| x <= m) { return fail(format!("{n} is not above {}", s["exclusiveMinimum"])); }
+            if bound("exclusiveMaximum").is_some_and(|m| x >= m) { return fail(format!("{n} is not below {}", s["exclusiveMaximum"])); }
+        }
+        _ => {}
+    }
+    Ok(())
+}
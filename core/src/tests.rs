@@ -2,12 +2,25 @@ use std::sync::Arc;
 
 use base64::Engine as _;
 use base64::engine::general_purpose::STANDARD as B64;
-use ed25519_dalek::{Signer, SigningKey, VerifyingKey as DalekVk};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier as _, VerifyingKey as DalekVk};
 
+use crate::attachments::Attachment;
+use crate::cbor::{self, Value as Cbor};
+use crate::checkpoint::{Checkpoint, Cosignature};
+use crate::cose::{cose_for_ack_sign, COSE_SIGN1};
+use crate::disclosure::Disclosure;
+use crate::encryption::{seal_with_ephemeral, EncryptedPayload};
+use crate::equivocation::EquivocationProof;
+use crate::erasure::Tombstone;
 use crate::errors::VerifyError;
-use crate::traits::{ChainStore, DeviceTrust};
-use crate::types::{LogEntry, VerifyingKey, compute_entry_hash, cbor_for_sign};
-use crate::verifier::{Ed25519AckSigner, InMemoryChainStore, Verifier};
+use crate::jcs;
+use crate::merkle::{ConsistencyProof, InclusionProof, MerkleTree, leaf_hash, verify_consistency, verify_inclusion};
+use crate::traits::{ChainStore, DeviceStatusStore, DeviceTrust};
+use crate::types::{jcs_for_hash, Ack, DeviceStatus, LogEntry, SessionHead, CANON_CBOR, CANON_JCS, DEFAULT_STREAM, LATEST_ENTRY_VERSION, cbor_for_ack_sign, VerifyingKey, compute_entry_hash, cbor_for_head_sign, cbor_for_sign};
+use crate::kinds::DATA_KIND;
+use crate::payload::{DecodedPayload, PayloadEncoding};
+use crate::schema::{validate, PayloadSchema, SchemaPolicy};
+use crate::verifier::{Ed25519AckSigner, InMemoryChainStore, InMemoryDeviceStatus, RollbackPolicy, Verifier};
 
 /// Simple static trust for tests.
 struct StaticTrust { key: VerifyingKey, revoked: bool }
@@ -19,6 +32,13 @@ impl DeviceTrust for StaticTrust {
     fn is_revoked(&self, _device_id: &str, _key_id: Option<&str>) -> bool { self.revoked }
 }
 
+/// Static trust whose device belongs to a class with a payload schema.
+struct ClassTrust { inner: StaticTrust, schema: Arc<PayloadSchema> }
+impl DeviceTrust for ClassTrust {
+    fn get_key(&self, device_id: &str, key_id: Option<&str>) -> Option<VerifyingKey> { self.inner.get_key(device_id, key_id) }
+    fn payload_schema(&self, _device_id: &str) -> Option<Arc<PayloadSchema>> { Some(self.schema.clone()) }
+}
+
 fn make_entry(sign_sk: &SigningKey, device_id: &str, key_id: Option<&str>, prev: Option<&str>, nonce: u64, ts: i64, payload: &str) -> LogEntry {
     let mut e = LogEntry {
         version: 1,
@@ -32,6 +52,17 @@ fn make_entry(sign_sk: &SigningKey, device_id: &str, key_id: Option<&str>, prev:
         payload: payload.into(),
         signature: String::new(),
         previous_entry_hash: prev.map(|s| s.to_string()),
+        boot_counter: None,
+        entry_counter: None,
+        stream_id: None,
+        kind: None,
+        payload_encoding: None,
+        payload_salt: None,
+        payload_commitment: None,
+        payload_commitment_scheme: None,
+        attachments: None,
+        canon: None,
+        signature_format: None,
     };
     // compute hash first (excludes entry_hash + signature)
     e.entry_hash = compute_entry_hash(&e);
@@ -42,6 +73,13 @@ fn make_entry(sign_sk: &SigningKey, device_id: &str, key_id: Option<&str>, prev:
     e
 }
 
+/// Re-hash and re-sign an entry after its fields were changed.
+fn resign(sign_sk: &SigningKey, mut e: LogEntry) -> LogEntry {
+    e.entry_hash = compute_entry_hash(&e);
+    e.signature = B64.encode(sign_sk.sign(&e.signing_input().unwrap()).to_bytes());
+    e
+}
+
 fn keys() -> (SigningKey, DalekVk) {
     let sk_bytes = [42u8; 32];
     let sk = SigningKey::from_bytes(&sk_bytes);
@@ -112,6 +150,43 @@ fn rejects_prev_hash_mismatch_and_nonce() {
     assert_eq!(err, VerifyError::NonceNotMonotonic);
 }
 
+/// A chain store implementing only the required methods, as stores written against the
+/// original three-method trait do.
+#[derive(Default)]
+struct LastOnly {
+    last_hash: std::sync::Mutex<std::collections::HashMap<(String, String), String>>,
+    last_nonce: std::sync::Mutex<std::collections::HashMap<(String, String, String), u64>>,
+}
+
+impl ChainStore for LastOnly {
+    fn last_hash(&self, device_id: &str, stream_id: &str) -> Option<String> {
+        self.last_hash.lock().unwrap().get(&(device_id.into(), stream_id.into())).cloned()
+    }
+    fn last_nonce(&self, device_id: &str, stream_id: &str, session_id: &str) -> Option<u64> {
+        self.last_nonce.lock().unwrap().get(&(device_id.into(), stream_id.into(), session_id.into())).copied()
+    }
+    fn update(&self, e: &LogEntry) {
+        self.last_hash.lock().unwrap().insert((e.device_id.clone(), e.stream().into()), e.entry_hash.clone());
+        self.last_nonce.lock().unwrap().insert((e.device_id.clone(), e.stream().into(), e.session_id.clone()), e.nonce);
+    }
+}
+
+#[test]
+fn minimal_chain_store_still_chains() {
+    let (sk, vk) = keys();
+    let (trust, _) = trust_and_store(&vk);
+    let verifier = Verifier::new(trust, Arc::new(LastOnly::default()), Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32])));
+
+    let e1 = make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    let e2 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 2, 1_700_000_100, "B");
+    verifier.process_entry(&e1, 1_700_000_050).unwrap();
+    assert_eq!(verifier.process_entry(&e2, 1_700_000_150).unwrap().status, "accepted");
+    // Without successor lookups a fork is a chain break, not an equivocation
+    let fork = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 3, 1_700_000_200, "C");
+    assert_eq!(verifier.process_entry(&fork, 1_700_000_250).unwrap_err(), VerifyError::PreviousHashMismatch);
+    assert_eq!(verifier.chain_head("dev-1", DEFAULT_STREAM, 1_700_000_300).last_entry_hash, Some(e2.entry_hash));
+}
+
 #[test]
 fn rejects_revoked() {
     let (sk, vk) = keys();
@@ -166,3 +241,1168 @@ fn per_session_nonce_behavior() {
     let err = verifier.process_entry(&e_bad, 1_700_000_070).unwrap_err();
     assert_eq!(err, VerifyError::NonceNotMonotonic);
 }
+
+#[test]
+fn replay_rebuilds_chain_state() {
+    let (sk, vk) = keys();
+    let signer = Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]));
+
+    let e1 = make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    let e2 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 2, 1_700_000_100, "B");
+
+    // Replay into a fresh store, as a restarted server would
+    let (trust, store) = trust_and_store(&vk);
+    let replayer = Verifier::new(trust.clone(), store.clone(), signer.clone());
+    replayer.replay_entry(&e1).unwrap();
+    replayer.replay_entry(&e2).unwrap();
+    assert_eq!(store.last_hash("dev-1", DEFAULT_STREAM), Some(e2.entry_hash.clone()));
+    assert_eq!(store.last_nonce("dev-1", DEFAULT_STREAM, &e2.session_id), Some(2));
+
+    // The live verifier continues the chain where the log left off
+    let verifier = Verifier::new(trust, store, signer);
+    let e3 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e2.entry_hash), 3, 1_700_000_200, "C");
+    verifier.process_entry(&e3, 1_700_000_250).expect("e3 accepted after replay");
+}
+
+#[test]
+fn replay_rejects_tampered_entry() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let replayer = Verifier::new(trust, store.clone(), Arc::new(signer));
+
+    let mut e1 = make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    e1.payload = "edited".into();
+    assert_eq!(replayer.replay_entry(&e1).unwrap_err(), VerifyError::HashMismatch);
+
+    // Replaying without the device's key still checks the hash and the link to the chain head
+    assert_eq!(replayer.replay_untrusted_entry(&e1).unwrap_err(), VerifyError::HashMismatch);
+    let detached = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&"ab".repeat(32)), 1, 1_700_000_000, "A");
+    assert_eq!(replayer.replay_untrusted_entry(&detached).unwrap_err(), VerifyError::PreviousHashMismatch);
+    assert_eq!(store.last_hash("dev-1", DEFAULT_STREAM), None);
+}
+
+#[test]
+fn chain_head_reports_signed_state() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let server_vk = DalekVk::from(&SigningKey::from_bytes(&[9u8; 32]));
+    let verifier = Verifier::new(trust, store, Arc::new(signer));
+
+    let empty = verifier.chain_head("dev-1", DEFAULT_STREAM, 1_700_000_000);
+    assert_eq!(empty.last_entry_hash, None);
+    assert!(empty.sessions.is_empty());
+
+    let e1 = make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    verifier.process_entry(&e1, 1_700_000_010).unwrap();
+    let e2 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 2, 1_700_000_020, "B");
+    verifier.process_entry(&e2, 1_700_000_030).unwrap();
+
+    let head = verifier.chain_head("dev-1", DEFAULT_STREAM, 1_700_000_040);
+    assert_eq!(head.last_entry_hash, Some(e2.entry_hash.clone()));
+    assert_eq!(head.sessions, vec![SessionHead { session_id: e2.session_id.clone(), last_nonce: 2 }]);
+    assert_eq!(head.server_signer_id, "server-key-1");
+
+    let sig = Signature::from_slice(&B64.decode(head.server_signature.as_bytes()).unwrap()).unwrap();
+    server_vk.verify(&cbor_for_head_sign(&head), &sig).expect("head signature verifies");
+}
+
+/// RFC 6962 reference leaves and roots (as used by the certificate-transparency test suites).
+fn rfc6962_leaves() -> Vec<Vec<u8>> {
+    vec![
+        vec![],
+        vec![0x00],
+        vec![0x10],
+        vec![0x20, 0x21],
+        vec![0x30, 0x31],
+        vec![0x40, 0x41, 0x42, 0x43],
+        vec![0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57],
+        vec![0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x6b, 0x6c, 0x6d, 0x6e, 0x6f],
+    ]
+}
+
+fn rfc6962_tree() -> MerkleTree {
+    let mut tree = MerkleTree::new();
+    for leaf in rfc6962_leaves() { tree.push_leaf_hash(leaf_hash(&leaf)); }
+    tree
+}
+
+#[test]
+fn merkle_roots_match_rfc6962_vectors() {
+    let roots = [
+        "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+        "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+        "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+        "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+        "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+        "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+    ];
+    let tree = rfc6962_tree();
+    assert_eq!(hex::encode(tree.root_at(0).unwrap()), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    for (i, root) in roots.iter().enumerate() {
+        assert_eq!(hex::encode(tree.root_at(i as u64 + 1).unwrap()), *root, "root at size {}", i + 1);
+    }
+    assert_eq!(tree.root_at(9), None);
+}
+
+#[test]
+fn merkle_leaf_is_raw_entry_hash() {
+    let (sk, _) = keys();
+    let e1 = make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    let mut tree = MerkleTree::new();
+    assert_eq!(tree.push_entry_hash(&e1.entry_hash).unwrap(), 0);
+    assert_eq!(tree.root(), leaf_hash(&hex::decode(&e1.entry_hash).unwrap()));
+    assert!(tree.push_entry_hash("not-hex").is_err());
+}
+
+#[test]
+fn checkpoint_sign_and_verify() {
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let server_vk = VerifyingKey {
+        algo: "ed25519".into(),
+        key: DalekVk::from(&SigningKey::from_bytes(&[9u8; 32])).to_bytes().to_vec(),
+        key_id: None,
+    };
+    let tree = rfc6962_tree();
+    let cp = Checkpoint::sign(&signer, tree.len(), &tree.root(), 1_700_000_000);
+    assert_eq!(cp.tree_size, 8);
+    assert_eq!(cp.root().unwrap(), tree.root());
+    cp.verify(&server_vk).expect("checkpoint verifies");
+
+    let mut forged = cp.clone();
+    forged.tree_size = 7;
+    assert_eq!(forged.verify(&server_vk).unwrap_err(), VerifyError::InvalidSignature);
+}
+
+#[test]
+fn merkle_inclusion_proofs_verify() {
+    let tree = rfc6962_tree();
+    let leaves: Vec<_> = rfc6962_leaves().iter().map(|l| leaf_hash(l)).collect();
+    for size in 1..=tree.len() {
+        let root = tree.root_at(size).unwrap();
+        for index in 0..size {
+            let path = tree.inclusion_proof(index, size).unwrap();
+            verify_inclusion(&leaves[index as usize], index, size, &path, &root).expect("inclusion verifies");
+            // wrong leaf, wrong index, truncated path
+            assert!(verify_inclusion(&leaves[(index as usize + 1) % 8], index, size, &path, &root).is_err());
+            if size > 1 {
+                assert!(verify_inclusion(&leaves[index as usize], (index + 1) % size, size, &path, &root).is_err());
+                assert!(verify_inclusion(&leaves[index as usize], index, size, &path[..path.len() - 1], &root).is_err());
+            }
+        }
+    }
+    assert_eq!(tree.inclusion_proof(8, 8), None);
+}
+
+#[test]
+fn merkle_consistency_proofs_verify() {
+    let tree = rfc6962_tree();
+    for second in 0..=tree.len() {
+        for first in 0..=second {
+            let proof = tree.consistency_proof(first, second).unwrap();
+            let (r1, r2) = (tree.root_at(first).unwrap(), tree.root_at(second).unwrap());
+            verify_consistency(first, second, &r1, &r2, &proof).expect("consistency verifies");
+            if first > 0 && first < second {
+                // a forked second tree must not verify
+                let mut forged = r2;
+                forged[0] ^= 1;
+                assert!(verify_consistency(first, second, &r1, &forged, &proof).is_err());
+                assert!(verify_consistency(first, second, &r2, &r2, &proof).is_err());
+            }
+        }
+    }
+}
+
+#[test]
+fn merkle_proofs_for_entries() {
+    let (sk, _) = keys();
+    let mut tree = MerkleTree::new();
+    let mut prev: Option<String> = None;
+    let mut entries = Vec::new();
+    for n in 1..=5u64 {
+        let e = make_entry(&sk, "dev-1", Some("001-key1-1"), prev.as_deref(), n, 1_700_000_000 + n as i64, "X");
+        tree.push_entry_hash(&e.entry_hash).unwrap();
+        prev = Some(e.entry_hash.clone());
+        entries.push(e);
+    }
+    let proof = InclusionProof::new(2, 5, &tree.inclusion_proof(2, 5).unwrap());
+    proof.verify(&entries[2].entry_hash, &tree.root()).expect("entry inclusion verifies");
+    assert!(matches!(proof.verify(&entries[3].entry_hash, &tree.root()), Err(VerifyError::InvalidProof(_))));
+
+    let cons = ConsistencyProof::new(3, 5, &tree.consistency_proof(3, 5).unwrap());
+    cons.verify(&tree.root_at(3).unwrap(), &tree.root()).expect("consistency verifies");
+}
+
+#[test]
+fn ack_binds_log_position() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let server_vk = DalekVk::from(&SigningKey::from_bytes(&[9u8; 32]));
+    let verifier = Verifier::new(trust, store.clone(), Arc::new(signer));
+
+    let a1 = make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    let b1 = make_entry(&sk, "dev-2", Some("001-key1-1"), None, 1, 1_700_000_000, "B");
+    let a2 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&a1.entry_hash), 2, 1_700_000_100, "C");
+    let acks: Vec<_> = [&a1, &b1, &a2].iter().map(|e| verifier.process_entry(e, 1_700_000_200).unwrap()).collect();
+    let positions: Vec<_> = acks.iter().map(|a| (a.log_index, a.device_seq)).collect();
+    assert_eq!(positions, vec![(Some(0), Some(0)), (Some(1), Some(0)), (Some(2), Some(1))]);
+    assert_eq!(store.log_size(), 3);
+    assert_eq!(store.device_count("dev-1"), 2);
+
+    // Rejected entries do not consume a position
+    assert!(verifier.process_entry(&a2, 1_700_000_300).is_err());
+    assert_eq!(store.log_size(), 3);
+
+    // The position is covered by the server signature
+    let mut ack = acks[2].clone();
+    let sig = Signature::from_slice(&B64.decode(ack.server_signature.as_bytes()).unwrap()).unwrap();
+    server_vk.verify(&cbor_for_ack_sign(&ack), &sig).expect("ack signature verifies");
+    ack.log_index = Some(5);
+    assert!(server_vk.verify(&cbor_for_ack_sign(&ack), &sig).is_err());
+}
+
+#[test]
+fn witness_cosignature_sign_and_verify() {
+    let server = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let witness = Ed25519AckSigner::from_secret_key("witness-1", [11u8; 32]);
+    let witness_vk = VerifyingKey {
+        algo: "ed25519".into(),
+        key: DalekVk::from(&SigningKey::from_bytes(&[11u8; 32])).to_bytes().to_vec(),
+        key_id: None,
+    };
+    let tree = rfc6962_tree();
+    let cp = Checkpoint::sign(&server, tree.len(), &tree.root(), 1_700_000_000);
+    let cosig = Cosignature::sign(&witness, &cp, 1_700_000_010);
+    assert_eq!(cosig.witness_id, "witness-1");
+    cosig.verify(&cp, &witness_vk).expect("cosignature verifies");
+
+    // Attaching cosignatures does not change what the server signed
+    let mut published = cp.clone();
+    published.cosignatures.push(cosig.clone());
+    assert_eq!(crate::checkpoint::cbor_for_checkpoint_sign(&published), crate::checkpoint::cbor_for_checkpoint_sign(&cp));
+
+    // A different view of the log does not carry the witness's signature
+    let other = Checkpoint::sign(&server, tree.len() - 1, &tree.root_at(tree.len() - 1).unwrap(), 1_700_000_000);
+    assert_eq!(cosig.verify(&other, &witness_vk).unwrap_err(), VerifyError::InvalidSignature);
+}
+
+#[test]
+fn detects_equivocation_with_verifiable_proof() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let server_vk = VerifyingKey {
+        algo: "ed25519".into(),
+        key: DalekVk::from(&SigningKey::from_bytes(&[9u8; 32])).to_bytes().to_vec(),
+        key_id: None,
+    };
+    let device_vk = trust.get_key("dev-1", None).unwrap();
+    let verifier = Verifier::new(trust, store, Arc::new(signer));
+
+    let e1 = make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    let e2 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 2, 1_700_000_100, "B");
+    verifier.process_entry(&e1, 1_700_000_050).unwrap();
+    verifier.process_entry(&e2, 1_700_000_150).unwrap();
+
+    // Fork: a second, validly signed successor of e1
+    let fork = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 7, 1_700_000_200, "C");
+    assert_eq!(verifier.process_entry(&fork, 1_700_000_250).unwrap_err(), VerifyError::Equivocation(e2.entry_hash.clone()));
+
+    // Same session and nonce, different content
+    let reused = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e2.entry_hash), 1, 1_700_000_300, "D");
+    assert_eq!(verifier.process_entry(&reused, 1_700_000_350).unwrap_err(), VerifyError::Equivocation(e1.entry_hash.clone()));
+
+    // Resubmitting an accepted entry is a duplicate, not an equivocation
+    assert_eq!(verifier.process_entry(&e2, 1_700_000_400).unwrap_err(), VerifyError::PreviousHashMismatch);
+
+    // Conflicts with a bad signature are not evidence
+    let mut forged = fork.clone();
+    forged.signature = e1.signature.clone();
+    assert_eq!(verifier.process_entry(&forged, 1_700_000_450).unwrap_err(), VerifyError::InvalidSignature);
+
+    let server = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let proof = EquivocationProof::sign(&server, e2.clone(), fork.clone(), 1_700_000_250);
+    proof.verify(&server_vk, &device_vk).expect("proof verifies");
+    assert!(EquivocationProof::sign(&server, e2.clone(), forged, 1_700_000_250).verify(&server_vk, &device_vk).is_err());
+    assert!(matches!(
+        EquivocationProof::sign(&server, e1, e2, 1_700_000_250).verify(&server_vk, &device_vk),
+        Err(VerifyError::InvalidProof(_))
+    ));
+}
+
+#[test]
+fn quarantine_and_retirement() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let statuses = Arc::new(InMemoryDeviceStatus::new());
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let verifier = Verifier::new(trust, store.clone(), Arc::new(signer)).with_device_status(statuses.clone(), 2);
+
+    let e1 = make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    assert_eq!(verifier.process_entry(&e1, 1_700_000_050).unwrap().status, "accepted");
+
+    // Consecutive signature failures quarantine the device; a valid signature resets the count
+    let mut bad = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 2, 1_700_000_100, "B");
+    bad.signature = e1.signature.clone();
+    assert_eq!(verifier.process_entry(&bad, 1_700_000_150).unwrap_err(), VerifyError::InvalidSignature);
+    let e2 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 2, 1_700_000_200, "C");
+    verifier.process_entry(&e2, 1_700_000_250).unwrap();
+    assert!(verifier.process_entry(&bad, 1_700_000_300).is_err());
+    assert_eq!(statuses.status("dev-1"), DeviceStatus::Active);
+    assert!(verifier.process_entry(&bad, 1_700_000_350).is_err());
+    assert_eq!(statuses.status("dev-1"), DeviceStatus::Quarantined);
+
+    // Quarantined entries are still verified and chained, with a distinct ACK status
+    let e3 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e2.entry_hash), 3, 1_700_000_400, "D");
+    let ack = verifier.process_entry(&e3, 1_700_000_450).unwrap();
+    assert_eq!((ack.status.as_str(), ack.log_index), ("quarantined", Some(2)));
+    assert_eq!(store.last_hash("dev-1", DEFAULT_STREAM), Some(e3.entry_hash.clone()));
+
+    // Equivocation quarantines immediately
+    let a1 = make_entry(&sk, "dev-2", Some("001-key1-1"), None, 1, 1_700_000_000, "A");
+    let a1b = make_entry(&sk, "dev-2", Some("001-key1-1"), None, 5, 1_700_000_000, "B");
+    verifier.process_entry(&a1, 1_700_000_050).unwrap();
+    assert!(matches!(verifier.process_entry(&a1b, 1_700_000_060), Err(VerifyError::Equivocation(_))));
+    assert_eq!(statuses.status("dev-2"), DeviceStatus::Quarantined);
+
+    // Retired devices are rejected without touching the chain
+    statuses.set_status("dev-1", DeviceStatus::Retired);
+    let e4 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e3.entry_hash), 4, 1_700_000_500, "E");
+    assert_eq!(verifier.process_entry(&e4, 1_700_000_550).unwrap_err(), VerifyError::Retired("dev-1".into()));
+    assert_eq!(store.last_hash("dev-1", DEFAULT_STREAM), Some(e3.entry_hash));
+}
+
+
+#[test]
+fn boot_counter_must_increase_across_sessions() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let verifier = Verifier::new(trust.clone(), store, Arc::new(signer));
+
+    // Boot 5, two entries in one session, then boot 6
+    let e1 = resign(&sk, LogEntry { version: 2, session_id: "boot-5".into(), boot_counter: Some(5), ..make_entry(&sk, "dev-1", None, None, 1, 1_700_000_000, "A") });
+    let e2 = resign(&sk, LogEntry { version: 2, session_id: "boot-5".into(), boot_counter: Some(5), ..make_entry(&sk, "dev-1", None, Some(&e1.entry_hash), 2, 1_700_000_100, "B") });
+    let e3 = resign(&sk, LogEntry { version: 2, session_id: "boot-6".into(), boot_counter: Some(6), ..make_entry(&sk, "dev-1", None, Some(&e2.entry_hash), 1, 1_700_000_200, "C") });
+    for e in [&e1, &e2, &e3] { verifier.process_entry(e, 1_700_000_300).unwrap(); }
+
+    // The counter is covered by the hash
+    let mut forged = e3.clone();
+    forged.boot_counter = Some(9);
+    assert_eq!(verifier.process_entry(&forged, 1_700_000_300).unwrap_err(), VerifyError::HashMismatch);
+
+    // A restored image starts a fresh session with an old counter
+    let rolled = resign(&sk, LogEntry { version: 2, session_id: "boot-4".into(), boot_counter: Some(4), ..make_entry(&sk, "dev-1", None, Some(&e3.entry_hash), 1, 1_700_000_400, "D") });
+    assert_eq!(verifier.process_entry(&rolled, 1_700_000_450).unwrap_err(), VerifyError::BootCounterRollback(6));
+    // ... or falls back to version 1 without a counter
+    let v1 = make_entry(&sk, "dev-1", None, Some(&e3.entry_hash), 1, 1_700_000_400, "E");
+    assert_eq!(verifier.process_entry(&v1, 1_700_000_450).unwrap_err(), VerifyError::BootCounterRollback(6));
+
+    // Version and counter must agree
+    let mut bad = e1.clone();
+    bad.boot_counter = None;
+    assert!(matches!(verifier.process_entry(&bad, 1_700_000_500), Err(VerifyError::Malformed(_))));
+
+    // With the quarantine policy the rollback is accepted and flagged
+    let statuses = Arc::new(InMemoryDeviceStatus::new());
+    let (_, store) = trust_and_store(&vk);
+    let verifier = Verifier::new(trust, store, Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32])))
+        .with_device_status(statuses.clone(), 3)
+        .with_rollback_policy(RollbackPolicy::Quarantine);
+    for e in [&e1, &e2, &e3] { assert_eq!(verifier.process_entry(e, 1_700_000_300).unwrap().status, "accepted"); }
+    assert_eq!(verifier.process_entry(&rolled, 1_700_000_450).unwrap().status, "quarantined");
+    assert_eq!(statuses.status("dev-1"), DeviceStatus::Quarantined);
+}
+
+#[test]
+fn entry_counter_only_moves_forward() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let verifier = Verifier::new(trust, store.clone(), Arc::new(signer));
+    let v3 = |prev: Option<&str>, nonce: u64, counter: u64| {
+        let e = make_entry(&sk, "dev-1", None, prev, nonce, 1_700_000_000 + nonce as i64, "A");
+        resign(&sk, LogEntry { version: 3, session_id: "boot-1".into(), boot_counter: Some(1), entry_counter: Some(counter), ..e })
+    };
+
+    let e1 = v3(None, 1, 10);
+    verifier.process_entry(&e1, 1_700_000_100).unwrap();
+    // A gap is accepted; the store reports the last counter so callers can detect it
+    let e2 = v3(Some(&e1.entry_hash), 2, 14);
+    assert_eq!(store.entry_counter("dev-1", DEFAULT_STREAM), Some(10));
+    verifier.process_entry(&e2, 1_700_000_100).unwrap();
+    assert_eq!(store.entry_counter("dev-1", DEFAULT_STREAM), Some(14));
+
+    let stale = v3(Some(&e2.entry_hash), 3, 14);
+    assert_eq!(verifier.process_entry(&stale, 1_700_000_100).unwrap_err(), VerifyError::EntryCounterRegression(14));
+
+    // Fields must match the version
+    let mut early = resign(&sk, LogEntry { version: 2, session_id: "boot-1".into(), boot_counter: Some(1), ..make_entry(&sk, "dev-2", None, None, 1, 1_700_000_000, "A") });
+    early.entry_counter = Some(1);
+    assert_eq!(verifier.process_entry(&early, 1_700_000_100).unwrap_err(), VerifyError::Malformed("entry_counter requires version 3".into()));
+    let mut future = e1.clone();
+    future.version = LATEST_ENTRY_VERSION + 1;
+    assert_eq!(verifier.process_entry(&future, 1_700_000_100).unwrap_err(), VerifyError::Malformed(format!("unsupported version {}", LATEST_ENTRY_VERSION + 1)));
+}
+
+#[test]
+fn streams_are_independent_chains() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let verifier = Verifier::new(trust, store.clone(), Arc::new(signer));
+    let entry = |stream: &str, prev: Option<&str>, nonce: u64, counter: u64| {
+        let e = make_entry(&sk, "dev-1", None, prev, nonce, 1_700_000_000 + nonce as i64, stream);
+        resign(&sk, LogEntry { version: 4, session_id: "boot-1".into(), boot_counter: Some(1), entry_counter: Some(counter), stream_id: Some(stream.into()), ..e })
+    };
+
+    // A version 1 entry continues in the default stream
+    let legacy = make_entry(&sk, "dev-1", None, None, 1, 1_700_000_000, "L");
+    verifier.process_entry(&legacy, 1_700_000_100).unwrap();
+    let sensor1 = entry("sensor", None, 1, 1);
+    let sensor3 = entry("sensor", Some(&sensor1.entry_hash), 2, 3);
+    let security1 = entry("security", None, 1, 1);
+    let security2 = entry("security", Some(&security1.entry_hash), 2, 2);
+    verifier.process_entry(&sensor1, 1_700_000_100).unwrap();
+    verifier.process_entry(&security1, 1_700_000_100).unwrap();
+    // The security stream does not wait for the sensor stream
+    verifier.process_entry(&security2, 1_700_000_100).unwrap();
+    verifier.process_entry(&sensor3, 1_700_000_100).unwrap();
+
+    assert_eq!(store.streams("dev-1"), vec!["default".to_string(), "security".into(), "sensor".into()]);
+    assert_eq!(store.last_hash("dev-1", "sensor"), Some(sensor3.entry_hash.clone()));
+    assert_eq!(store.last_hash("dev-1", DEFAULT_STREAM), Some(legacy.entry_hash.clone()));
+    assert_eq!(store.entry_counter("dev-1", "security"), Some(2));
+    assert_eq!(store.device_count("dev-1"), 5);
+    let head = verifier.chain_head("dev-1", "security", 1_700_000_200);
+    assert_eq!((head.stream_id.as_str(), head.last_entry_hash), ("security", Some(security2.entry_hash.clone())));
+
+    // Chains do not cross streams, and forks are detected per stream
+    let crossed = entry("security", Some(&sensor3.entry_hash), 3, 3);
+    assert_eq!(verifier.process_entry(&crossed, 1_700_000_100).unwrap_err(), VerifyError::PreviousHashMismatch);
+    let fork = entry("sensor", Some(&sensor1.entry_hash), 9, 9);
+    assert_eq!(verifier.process_entry(&fork, 1_700_000_100).unwrap_err(), VerifyError::Equivocation(sensor3.entry_hash.clone()));
+
+    // The stream is covered by the hash
+    let mut moved = security2.clone();
+    moved.stream_id = Some("sensor".into());
+    assert_eq!(verifier.process_entry(&moved, 1_700_000_100).unwrap_err(), VerifyError::HashMismatch);
+}
+
+#[test]
+fn entry_kinds_are_validated_and_applied() {
+    let (sk, vk) = keys();
+    let sk2 = SigningKey::from_bytes(&[43u8; 32]);
+    let (trust, store) = trust_and_store(&vk);
+    let statuses = Arc::new(InMemoryDeviceStatus::new());
+    let signer = Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]));
+    let verifier = Verifier::new(trust.clone(), store.clone(), signer.clone()).with_device_status(statuses.clone(), 3);
+    let counter = std::cell::Cell::new(0);
+    let entry = |key: &SigningKey, key_id: &str, session: &str, boot: u64, prev: Option<&str>, nonce: u64, kind: &str, payload: &str| {
+        counter.set(counter.get() + 1);
+        let e = make_entry(key, "dev-1", Some(key_id), prev, nonce, 1_700_000_000 + counter.get() as i64, "");
+        resign(key, LogEntry {
+            version: 5,
+            session_id: session.into(),
+            boot_counter: Some(boot),
+            entry_counter: Some(counter.get()),
+            stream_id: Some(DEFAULT_STREAM.into()),
+            kind: Some(kind.into()),
+            payload: payload.into(),
+            ..e
+        })
+    };
+    let mut accepted = Vec::new();
+
+    let data = entry(&sk, "001-key1-1", "s1", 1, None, 1, DATA_KIND, "\u{1}opaque");
+    verifier.process_entry(&data, 1_700_000_100).unwrap();
+    accepted.push(data);
+    let prev = accepted.last().unwrap().entry_hash.clone();
+
+    // Unknown kinds and payloads that do not match the kind's schema are rejected
+    let unknown = entry(&sk, "001-key1-1", "s1", 1, Some(&prev), 2, "reboot", "{}");
+    assert_eq!(verifier.process_entry(&unknown, 1_700_000_100).unwrap_err(), VerifyError::Malformed("unknown kind \"reboot\"".into()));
+    let bad = entry(&sk, "001-key1-1", "s1", 1, Some(&prev), 2, "firmware_update", r#"{"to_version":"1.1","image_sha256":"abc"}"#);
+    assert_eq!(verifier.process_entry(&bad, 1_700_000_100).unwrap_err(), VerifyError::Malformed("firmware_update payload: image_sha256 is not a hex SHA-256".into()));
+    let missing = entry(&sk, "001-key1-1", "s1", 1, Some(&prev), 2, "key_rotation", "{}");
+    assert!(matches!(verifier.process_entry(&missing, 1_700_000_100), Err(VerifyError::Malformed(m)) if m.starts_with("key_rotation payload: missing field")));
+
+    // Firmware updates are tracked and must start from the running version
+    let image = "ab".repeat(32);
+    let update = entry(&sk, "001-key1-1", "s1", 1, Some(&prev), 2, "firmware_update", &format!(r#"{{"to_version":"1.1","image_sha256":"{image}"}}"#));
+    verifier.process_entry(&update, 1_700_000_100).unwrap();
+    assert_eq!(store.firmware_version("dev-1").as_deref(), Some("1.1"));
+    let stale = entry(&sk, "001-key1-1", "s1", 1, Some(&update.entry_hash), 3, "firmware_update", &format!(r#"{{"from_version":"1.0","to_version":"1.2","image_sha256":"{image}"}}"#));
+    assert_eq!(verifier.process_entry(&stale, 1_700_000_100).unwrap_err(), VerifyError::Malformed("firmware update from 1.0, but device runs 1.1".into()));
+    accepted.push(update);
+
+    // After a key rotation, signed with the old key, only the announced key is valid
+    let announce = format!(r#"{{"key_id":"001-key1-2","algo":"ed25519","pubkey_base64":"{}"}}"#, B64.encode(sk2.verifying_key().to_bytes()));
+    let rotation = entry(&sk, "001-key1-1", "s1", 1, Some(&accepted[1].entry_hash), 3, "key_rotation", &announce);
+    verifier.process_entry(&rotation, 1_700_000_100).unwrap();
+    let old_key = entry(&sk, "001-key1-1", "s1", 1, Some(&rotation.entry_hash), 4, DATA_KIND, "x");
+    assert_eq!(verifier.process_entry(&old_key, 1_700_000_100).unwrap_err(), VerifyError::KeyRotated("001-key1-2".into()));
+    accepted.push(rotation);
+    let new_key = entry(&sk2, "001-key1-2", "s1", 1, Some(&accepted[2].entry_hash), 4, DATA_KIND, "x");
+    verifier.process_entry(&new_key, 1_700_000_100).unwrap();
+    accepted.push(new_key);
+
+    // A session end closes the session; the device continues in a new one
+    let end = entry(&sk2, "001-key1-2", "s1", 1, Some(&accepted[3].entry_hash), 5, "session_end", r#"{"reason":"shutdown"}"#);
+    verifier.process_entry(&end, 1_700_000_100).unwrap();
+    let late = entry(&sk2, "001-key1-2", "s1", 1, Some(&end.entry_hash), 6, DATA_KIND, "x");
+    assert_eq!(verifier.process_entry(&late, 1_700_000_100).unwrap_err(), VerifyError::SessionEnded("s1".into()));
+    accepted.push(end);
+    let next = entry(&sk2, "001-key1-2", "s2", 2, Some(&accepted[4].entry_hash), 1, DATA_KIND, "x");
+    verifier.process_entry(&next, 1_700_000_100).unwrap();
+    let sessions = verifier.chain_head("dev-1", DEFAULT_STREAM, 1_700_000_100).sessions;
+    assert_eq!(sessions.iter().map(|s| s.session_id.as_str()).collect::<Vec<_>>(), ["s2"]);
+    accepted.push(next);
+
+    // A tamper alarm is accepted and quarantines the device
+    let alarm = entry(&sk2, "001-key1-2", "s2", 2, Some(&accepted[5].entry_hash), 2, "tamper_alarm", r#"{"sensor":"case_open"}"#);
+    assert_eq!(verifier.process_entry(&alarm, 1_700_000_100).unwrap().status, "quarantined");
+    assert_eq!(statuses.status("dev-1"), DeviceStatus::Quarantined);
+    accepted.push(alarm);
+
+    // Replaying the accepted entries rebuilds the state the kinds set
+    let replayed: Arc<dyn ChainStore> = Arc::new(InMemoryChainStore::new());
+    let replayer = Verifier::new(trust, replayed.clone(), signer);
+    for e in &accepted { replayer.replay_entry(e).unwrap(); }
+    assert_eq!(replayed.firmware_version("dev-1").as_deref(), Some("1.1"));
+    assert_eq!(replayed.rotated_key("dev-1").and_then(|k| k.key_id).as_deref(), Some("001-key1-2"));
+    assert!(replayed.session_ended("dev-1", DEFAULT_STREAM, "s1"));
+}
+
+#[test]
+fn payload_encodings_decode_and_are_checked() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let verifier = Verifier::new(trust, store, Arc::new(signer));
+    let entry = |prev: Option<&str>, nonce: u64, kind: &str, encoding: &str, payload: &str| {
+        resign(&sk, LogEntry {
+            version: 6,
+            session_id: "s1".into(),
+            boot_counter: Some(1),
+            entry_counter: Some(nonce),
+            stream_id: Some(DEFAULT_STREAM.into()),
+            kind: Some(kind.into()),
+            payload_encoding: Some(encoding.into()),
+            payload: payload.into(),
+            ..make_entry(&sk, "dev-1", None, prev, nonce, 1_700_000_000 + nonce as i64, "")
+        })
+    };
+
+    // Entries before version 6 are utf8
+    let legacy = make_entry(&sk, "dev-1", None, None, 1, 1_700_000_000, "{\"t\":1}");
+    assert_eq!(legacy.payload_encoding(), Ok(PayloadEncoding::Utf8));
+    assert_eq!(legacy.payload_bytes().unwrap(), b"{\"t\":1}");
+
+    let raw = entry(None, 1, DATA_KIND, "base64", &B64.encode([0u8, 255, 16]));
+    assert_eq!(raw.payload_bytes().unwrap(), vec![0u8, 255, 16]);
+    assert_eq!(raw.decoded_payload().unwrap().to_json(), serde_json::json!("00ff10"));
+    verifier.process_entry(&raw, 1_700_000_100).unwrap();
+
+    // {"t": 22, "raw": h'0102'} as CBOR; the bytes are kept exactly as sent
+    let item = cbor::encode(&Cbor::Map(vec![("t".into(), 22u64.into()), ("raw".into(), Cbor::Bytes(vec![1, 2]))]));
+    let cbor = entry(Some(&raw.entry_hash), 2, DATA_KIND, "cbor", &B64.encode(&item));
+    assert_eq!(cbor.payload_bytes().unwrap(), item);
+    assert!(matches!(cbor.decoded_payload().unwrap(), DecodedPayload::Cbor(_)));
+    assert_eq!(cbor.decoded_payload().unwrap().to_json(), serde_json::json!({"t": 22, "raw": "0102"}));
+    verifier.process_entry(&cbor, 1_700_000_100).unwrap();
+
+    // The encoding is covered by the hash, and payloads must match it
+    let mut relabeled = cbor.clone();
+    relabeled.payload_encoding = Some("base64".into());
+    assert_eq!(verifier.process_entry(&relabeled, 1_700_000_100).unwrap_err(), VerifyError::HashMismatch);
+    let not_base64 = entry(Some(&cbor.entry_hash), 3, DATA_KIND, "base64", "not base64!");
+    assert_eq!(verifier.process_entry(&not_base64, 1_700_000_100).unwrap_err(), VerifyError::Malformed("payload is not base64".into()));
+    let truncated = entry(Some(&cbor.entry_hash), 3, DATA_KIND, "cbor", &B64.encode(&item[..item.len() - 1]));
+    assert!(matches!(verifier.process_entry(&truncated, 1_700_000_100), Err(VerifyError::Malformed(m)) if m.starts_with("payload is not one CBOR item")));
+    // Floats and tags are outside the data model; a half float is not read as a simple value
+    for item in [vec![0xf9, 0x00, 0x14], vec![0xfb, 0x40, 0x09, 0x21, 0xfb, 0x54, 0x44, 0x2d, 0x18], vec![0xc1, 0x00]] {
+        let e = entry(Some(&cbor.entry_hash), 3, DATA_KIND, "cbor", &B64.encode(&item));
+        assert!(matches!(verifier.process_entry(&e, 1_700_000_100), Err(VerifyError::Malformed(m)) if m.starts_with("payload is not one CBOR item")));
+    }
+    let wide = entry(Some(&cbor.entry_hash), 3, DATA_KIND, "cbor", &B64.encode(cbor::encode(&Cbor::Array(vec![Cbor::Negative(u64::MAX), (-5i64).into()]))));
+    assert_eq!(wide.decoded_payload().unwrap().to_json(), serde_json::json!(["-18446744073709551616", -5]));
+    let unknown = entry(Some(&cbor.entry_hash), 3, DATA_KIND, "hex", "00");
+    assert_eq!(verifier.process_entry(&unknown, 1_700_000_100).unwrap_err(), VerifyError::Malformed("unknown payload_encoding \"hex\"".into()));
+    let binary_kind = entry(Some(&cbor.entry_hash), 3, "session_end", "base64", &B64.encode("{}"));
+    assert_eq!(verifier.process_entry(&binary_kind, 1_700_000_100).unwrap_err(), VerifyError::Malformed("session_end payload: must be utf8 JSON".into()));
+}
+
+#[test]
+fn payload_schema_per_device_class() {
+    let (sk, vk) = keys();
+    let schema = serde_json::json!({
+        "type": "object",
+        "required": ["temperature"],
+        "properties": {
+            "temperature": { "type": "number", "minimum": -40, "maximum": 125 },
+            "unit": { "enum": ["C", "F"] },
+            "samples": { "type": "array", "items": { "type": "integer" }, "maxItems": 3 }
+        },
+        "additionalProperties": false
+    });
+    assert_eq!(PayloadSchema::new("thermo", 2, serde_json::json!([])).unwrap_err(), "schema thermo v2 is not an object or a boolean");
+    let trust = Arc::new(ClassTrust {
+        inner: StaticTrust { key: VerifyingKey { algo: "ed25519".into(), key: vk.to_bytes().to_vec(), key_id: None }, revoked: false },
+        schema: Arc::new(PayloadSchema::new("thermo", 2, schema.clone()).unwrap()),
+    });
+    let signer = Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]));
+    let store: Arc<dyn ChainStore> = Arc::new(InMemoryChainStore::new());
+    let rejecting = Verifier::new(trust.clone(), store.clone(), signer.clone()).with_schema_policy(SchemaPolicy::Reject);
+    let warning = Verifier::new(trust.clone(), store.clone(), signer.clone()).with_schema_policy(SchemaPolicy::Warn);
+
+    let e1 = make_entry(&sk, "dev-1", None, None, 1, 1_700_000_000, r#"{"temperature":21.5,"unit":"C","samples":[1,2]}"#);
+    assert!(rejecting.process_entry(&e1, 1_700_000_100).unwrap().warnings.is_empty());
+    let hot = make_entry(&sk, "dev-1", None, Some(&e1.entry_hash), 2, 1_700_000_001, r#"{"temperature":200}"#);
+    assert_eq!(rejecting.process_entry(&hot, 1_700_000_100).unwrap_err(), VerifyError::SchemaViolation("thermo v2: $.temperature: 200 is above 125".into()));
+
+    // Under the warn policy the entry is accepted and the signed ACK carries the violation
+    let ack = warning.process_entry(&hot, 1_700_000_100).unwrap();
+    assert_eq!(ack.status, "accepted");
+    assert_eq!(ack.warnings, vec!["schema violation: thermo v2: $.temperature: 200 is above 125".to_string()]);
+    let server_vk = DalekVk::from(&SigningKey::from_bytes(&[9u8; 32]));
+    let sig = Signature::from_slice(&B64.decode(&ack.server_signature).unwrap()).unwrap();
+    server_vk.verify(&cbor_for_ack_sign(&ack), &sig).expect("ack signature covers warnings");
+    let mut stripped = ack.clone();
+    stripped.warnings.clear();
+    assert!(server_vk.verify(&cbor_for_ack_sign(&stripped), &sig).is_err());
+
+    // Without a policy payloads are not checked
+    let plain = Verifier::new(trust, store, signer);
+    let text = make_entry(&sk, "dev-1", None, Some(&hot.entry_hash), 3, 1_700_000_002, "not json");
+    assert!(plain.process_entry(&text, 1_700_000_100).is_ok());
+
+    for (value, err) in [
+        (serde_json::json!({}), "$: missing property \"temperature\""),
+        (serde_json::json!({"temperature": 1, "extra": 1}), "$: unexpected property \"extra\""),
+        (serde_json::json!({"temperature": 1, "unit": "K"}), "$.unit: \"K\" is not one of [\"C\",\"F\"]"),
+        (serde_json::json!({"temperature": 1, "samples": [1, 2.5]}), "$.samples[1]: expected integer"),
+        (serde_json::json!({"temperature": 1, "samples": [1, 2, 3, 4]}), "$.samples: more than 3 items"),
+        (serde_json::json!([21.5]), "$: expected object"),
+    ] {
+        assert_eq!(validate(&schema, &value, "$").unwrap_err(), err);
+    }
+}
+
+#[test]
+fn hpke_matches_rfc9180_base_vector() {
+    // RFC 9180 A.2.1: DHKEM(X25519, HKDF-SHA256), HKDF-SHA256, ChaCha20Poly1305, base mode
+    let h = |s: &str| hex::decode(s).unwrap();
+    let sk_e: [u8; 32] = h("f4ec9b33b792c372c1d2c2063507b684ef925b8c75a42dbcbf57d63ccd381600").try_into().unwrap();
+    let sk_r: [u8; 32] = h("8057991eef8f1f1af18f4a9491d16a1ce333f695d4db8e38da75975c4478e0fb").try_into().unwrap();
+    let pk_r = x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(sk_r));
+    assert_eq!(pk_r.to_bytes().to_vec(), h("4310ee97d88cc1f088a5576c77ab0cf5c3ac797f3d95139c6c84b5429c59662a"));
+    let info = h("4f6465206f6e2061204772656369616e2055726e");
+    let (pt, aad) = (h("4265617574792069732074727574682c20747275746820626561757479"), h("436f756e742d30"));
+    let (enc, ct) = seal_with_ephemeral(&x25519_dalek::StaticSecret::from(sk_e), &pk_r, &info, &aad, &pt).unwrap();
+    assert_eq!(enc.to_vec(), h("1afa08d3dec047a643885163f1180476fa7ddb54c6a8029ea33f95796bf2ac4a"));
+    assert_eq!(ct, h("1c5250d8034ec2b784ba2cfd69dbdb8af406cfe3ff938e131f0def8c8b60b4db21993c62ce81883d2dd1b51a28"));
+    let opened = crate::encryption::open(&x25519_dalek::StaticSecret::from(sk_r), &enc, &info, &aad, &ct);
+    assert_eq!(opened.as_ref(), Ok(&pt));
+
+    // A low-order point gives an all-zero shared secret, on either side
+    let low_order = x25519_dalek::PublicKey::from([0u8; 32]);
+    let refused = VerifyError::Malformed("encrypted payload: recipient key is a low-order point".into());
+    assert_eq!(seal_with_ephemeral(&x25519_dalek::StaticSecret::from(sk_e), &low_order, &info, &aad, &pt).unwrap_err(), refused);
+    assert!(EncryptedPayload::seal("r", low_order.as_bytes(), "dev-1", &pt, PayloadEncoding::Base64).is_err());
+    let opened = crate::encryption::open(&x25519_dalek::StaticSecret::from(sk_r), &[0u8; 32], &info, &aad, &ct);
+    assert_eq!(opened.unwrap_err(), VerifyError::Malformed("encrypted payload: enc is a low-order point".into()));
+}
+
+#[test]
+fn encrypted_payloads_verify_without_the_key() {
+    let (sk, vk) = keys();
+    let recipient_sk = [7u8; 32];
+    let recipient_pk = x25519_dalek::PublicKey::from(&x25519_dalek::StaticSecret::from(recipient_sk)).to_bytes();
+    let trust = Arc::new(ClassTrust {
+        inner: StaticTrust { key: VerifyingKey { algo: "ed25519".into(), key: vk.to_bytes().to_vec(), key_id: None }, revoked: false },
+        schema: Arc::new(PayloadSchema::new("thermo", 1, serde_json::json!({"type": "object"})).unwrap()),
+    });
+    let signer = Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]));
+    let verifier = Verifier::new(trust, Arc::new(InMemoryChainStore::new()), signer).with_schema_policy(SchemaPolicy::Reject);
+    let entry = |prev: Option<&str>, nonce: u64, version: u8, payload: &str| {
+        resign(&sk, LogEntry {
+            version,
+            session_id: "s1".into(),
+            boot_counter: Some(1),
+            entry_counter: Some(nonce),
+            stream_id: Some(DEFAULT_STREAM.into()),
+            kind: Some(DATA_KIND.into()),
+            payload_encoding: Some("hpke".into()),
+            payload: payload.into(),
+            ..make_entry(&sk, "dev-1", None, prev, nonce, 1_700_000_000 + nonce as i64, "")
+        })
+    };
+    let item = cbor::encode(&Cbor::Map(vec![("t".into(), 21u64.into())]));
+    let sealed = EncryptedPayload::seal("ops-2026", &recipient_pk, "dev-1", &item, PayloadEncoding::Cbor).unwrap();
+    let envelope = serde_json::to_string(&sealed).unwrap();
+
+    // hpke needs version 7
+    assert_eq!(verifier.process_entry(&entry(None, 1, 6, &envelope), 1_700_000_100).unwrap_err(), VerifyError::Malformed("payload_encoding hpke requires version 7".into()));
+
+    // Accepted and chained without the key; the schema is not applied to ciphertext
+    let e1 = entry(None, 1, 7, &envelope);
+    assert_eq!(verifier.process_entry(&e1, 1_700_000_100).unwrap().status, "accepted");
+    assert!(matches!(e1.decoded_payload().unwrap(), DecodedPayload::Encrypted(ref p) if p.recipient == "ops-2026"));
+    assert_eq!(e1.decrypt_payload(&recipient_sk).unwrap().to_json(), serde_json::json!({"t": 21}));
+    assert_eq!(e1.decrypt_payload(&[8u8; 32]).unwrap_err(), VerifyError::Malformed("encrypted payload: decryption failed (wrong key, device or corrupted ciphertext)".into()));
+
+    // The ciphertext is bound to the device through the AAD
+    let mut moved = e1.clone();
+    moved.device_id = "dev-2".into();
+    assert!(moved.decrypt_payload(&recipient_sk).is_err());
+
+    // The server still checks the envelope
+    let mut bad = sealed.clone();
+    bad.suite = "p256".into();
+    let e2 = entry(Some(&e1.entry_hash), 2, 7, &serde_json::to_string(&bad).unwrap());
+    assert_eq!(verifier.process_entry(&e2, 1_700_000_100).unwrap_err(), VerifyError::Malformed("encrypted payload: unsupported suite \"p256\"".into()));
+    let mut nested = sealed;
+    nested.plaintext_encoding = "hpke".into();
+    let e2 = entry(Some(&e1.entry_hash), 2, 7, &serde_json::to_string(&nested).unwrap());
+    assert_eq!(verifier.process_entry(&e2, 1_700_000_100).unwrap_err(), VerifyError::Malformed("encrypted payload: invalid plaintext_encoding \"hpke\"".into()));
+}
+
+#[test]
+fn payload_commitments_allow_erasure() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]));
+    let verifier = Verifier::new(trust.clone(), store, signer.clone());
+    let entry = |prev: Option<&str>, nonce: u64, version: u8, kind: &str, payload: &str| {
+        let mut e = LogEntry {
+            version,
+            session_id: "s1".into(),
+            boot_counter: Some(1),
+            entry_counter: Some(nonce),
+            stream_id: Some(DEFAULT_STREAM.into()),
+            kind: Some(kind.into()),
+            payload_encoding: Some("utf8".into()),
+            payload: payload.into(),
+            ..make_entry(&sk, "dev-1", None, prev, nonce, 1_700_000_000 + nonce as i64, "")
+        };
+        if version >= 8 { e.commit_payload().unwrap(); }
+        resign(&sk, e)
+    };
+    let e1 = entry(None, 1, 8, DATA_KIND, r#"{"badge_holder":"Alice"}"#);
+    let e2 = entry(Some(&e1.entry_hash), 2, 8, DATA_KIND, r#"{"badge_holder":"Bob"}"#);
+    let e3 = entry(Some(&e2.entry_hash), 3, 8, "session_end", "{}");
+    for e in [&e1, &e2, &e3] { verifier.process_entry(e, 1_700_000_100).unwrap(); }
+
+    // The hash covers the commitment, and the payload must match it
+    let mut swapped = e2.clone();
+    swapped.payload = r#"{"badge_holder":"Eve"}"#.into();
+    assert_eq!(compute_entry_hash(&swapped), e2.entry_hash);
+    assert_eq!(swapped.check_commitment().unwrap_err(), VerifyError::CommitmentMismatch);
+    let mut unsalted = e2.clone();
+    unsalted.payload_salt = None;
+    assert_eq!(verifier.process_entry(&unsalted, 1_700_000_100).unwrap_err(), VerifyError::Malformed("version 8 requires payload_salt".into()));
+    let mut early = entry(None, 1, 7, DATA_KIND, "x");
+    early.payload_salt = Some(B64.encode([0u8; 16]));
+    assert_eq!(verifier.process_entry(&early, 1_700_000_100).unwrap_err(), VerifyError::Malformed("payload_salt requires version 8".into()));
+
+    // Erasure keeps hash, signature and chain verifiable
+    let erased = e2.erased().unwrap();
+    assert!(erased.is_erased() && erased.payload.is_empty());
+    assert_eq!(compute_entry_hash(&erased), e2.entry_hash);
+    assert_eq!(erased.decoded_payload().unwrap_err(), VerifyError::Malformed("payload erased".into()));
+    assert_eq!(e3.erased().unwrap_err(), VerifyError::Malformed("session_end entries cannot be erased".into()));
+    let replayed = Verifier::new(trust, Arc::new(InMemoryChainStore::new()), signer.clone());
+    for e in [&e1, &erased, &e3] { replayed.replay_entry(e).unwrap(); }
+    assert_eq!(replayed.chain_head("dev-1", DEFAULT_STREAM, 0).last_entry_hash, Some(e3.entry_hash.clone()));
+    // Devices cannot submit erased entries
+    let fresh = Verifier::new(trust_and_store(&vk).0, Arc::new(InMemoryChainStore::new()), signer.clone());
+    fresh.process_entry(&e1, 1_700_000_100).unwrap();
+    assert_eq!(fresh.process_entry(&erased, 1_700_000_100).unwrap_err(), VerifyError::Malformed("version 8 requires payload_salt".into()));
+
+    // The tombstone binds the erasure to the entry's commitment
+    let tombstone = Tombstone::sign(signer.as_ref(), &e2, "erasure request 17", 1_700_000_200).unwrap();
+    let server_vk = VerifyingKey { algo: "ed25519".into(), key: DalekVk::from(&SigningKey::from_bytes(&[9u8; 32])).to_bytes().to_vec(), key_id: None };
+    tombstone.verify(&server_vk, &erased).unwrap();
+    tombstone.verify(&server_vk, &e2).unwrap();
+    assert_eq!(tombstone.verify(&server_vk, &e1).unwrap_err(), VerifyError::InvalidProof("tombstone of another entry".into()));
+    let mut edited = tombstone.clone();
+    edited.reason = "no reason".into();
+    assert_eq!(edited.verify(&server_vk, &erased).unwrap_err(), VerifyError::InvalidSignature);
+    assert!(Tombstone::sign(signer.as_ref(), &e3, "", 0).is_err());
+}
+
+#[test]
+fn selective_disclosure_of_payload_fields() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let signer = Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]));
+    let verifier = Verifier::new(trust, store, signer);
+    let device_key = VerifyingKey { algo: "ed25519".into(), key: vk.to_bytes().to_vec(), key_id: None };
+    let entry = |prev: Option<&str>, nonce: u64, version: u8, scheme: Option<&str>, payload: &str| {
+        let mut e = LogEntry {
+            version,
+            session_id: "s1".into(),
+            boot_counter: Some(1),
+            entry_counter: Some(nonce),
+            stream_id: Some(DEFAULT_STREAM.into()),
+            kind: Some(DATA_KIND.into()),
+            payload_encoding: Some("utf8".into()),
+            payload: payload.into(),
+            ..make_entry(&sk, "dev-1", None, prev, nonce, 1_700_000_000 + nonce as i64, "")
+        };
+        e.payload_commitment_scheme = scheme.map(String::from);
+        e.commit_payload()?;
+        Ok::<_, VerifyError>(resign(&sk, e))
+    };
+    let payload = r#"{"door_id":"D-17","badge_holder":"Alice","granted":true,"reader":{"zone":2,"fw":"1.4"}}"#;
+    let e1 = entry(None, 1, 9, Some("fields"), payload).unwrap();
+    verifier.process_entry(&e1, 1_700_000_100).unwrap();
+
+    // Reveal one field; the rest of the payload stays hidden
+    let disclosure = e1.disclose(&["door_id", "reader"]).unwrap();
+    disclosure.verify(&device_key).unwrap();
+    let published = serde_json::to_string(&disclosure).unwrap();
+    assert!(!published.contains("Alice") && !published.contains(e1.payload_salt.as_deref().unwrap()));
+    let received: Disclosure = serde_json::from_str(&published).unwrap();
+    assert_eq!(received.fields[1].value, serde_json::json!({"fw": "1.4", "zone": 2}));
+    received.verify(&device_key).unwrap();
+
+    let mut forged = disclosure.clone();
+    forged.fields[0].value = serde_json::json!("D-18");
+    assert_eq!(forged.verify(&device_key).unwrap_err(), VerifyError::InvalidProof("field \"door_id\": inclusion root mismatch".into()));
+    let mut renamed = disclosure.clone();
+    renamed.fields[0].name = "badge_holder".into();
+    assert!(renamed.verify(&device_key).is_err());
+    let mut resigned = disclosure.clone();
+    resigned.entry.payload_commitment = Some(hex::encode([0u8; 32]));
+    assert_eq!(resigned.verify(&device_key).unwrap_err(), VerifyError::HashMismatch);
+    assert_eq!(e1.disclose(&["pin"]).unwrap_err(), VerifyError::Malformed("fields commitment: no field \"pin\"".into()));
+
+    // The payload must match the field tree
+    let mut swapped = e1.clone();
+    swapped.payload = payload.replace("Alice", "Mallory");
+    assert_eq!(swapped.check_commitment().unwrap_err(), VerifyError::CommitmentMismatch);
+    assert_eq!(entry(None, 1, 9, Some("fields"), "[1,2]").unwrap_err(), VerifyError::Malformed("fields commitment: payload is not a JSON object".into()));
+
+    // Leaves hold the values' RFC 8785 text, so every implementation rebuilds them alike
+    let leaf = crate::disclosure::field_leaf_data(b"salt", "temp_c", &serde_json::json!(2.15e1));
+    assert_eq!(leaf, cbor::encode(&Cbor::Array(vec![Cbor::Bytes(b"salt".to_vec()), "temp_c".into(), "21.5".into()])));
+    let floats = entry(None, 1, 9, Some("fields"), r#"{"temp_c":2.15e1,"reader":{"zone":2,"gain":1e30}}"#).unwrap();
+    floats.disclose(&["temp_c", "reader"]).unwrap().verify(&device_key).unwrap();
+    assert_eq!(
+        entry(None, 1, 9, Some("fields"), r#"{"serial":9007199254740993}"#).unwrap_err(),
+        VerifyError::Malformed("fields commitment: integer 9007199254740993 is beyond 2^53 - 1".into())
+    );
+
+    // Scheme gating and the default scheme
+    let e2 = entry(Some(&e1.entry_hash), 2, 9, Some("sha256"), payload).unwrap();
+    verifier.process_entry(&e2, 1_700_000_100).unwrap();
+    assert!(e2.disclose(&["door_id"]).is_err());
+    let early = entry(Some(&e2.entry_hash), 3, 8, Some("fields"), payload).unwrap();
+    assert_eq!(verifier.process_entry(&early, 1_700_000_100).unwrap_err(), VerifyError::Malformed("payload_commitment_scheme requires version 9".into()));
+    assert!(entry(None, 1, 9, Some("blake3"), payload).is_err());
+
+    // An erased fields entry still verifies, but discloses nothing
+    let erased = e1.erased().unwrap();
+    assert_eq!(compute_entry_hash(&erased), e1.entry_hash);
+    assert_eq!(erased.disclose(&["door_id"]).unwrap_err(), VerifyError::Malformed("payload erased".into()));
+}
+
+#[test]
+fn attachments_are_signed_by_content() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let verifier = Verifier::new(trust, store, Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32])));
+    let entry = |prev: Option<&str>, nonce: u64, version: u8, attachments: Option<Vec<Attachment>>| {
+        let mut e = LogEntry {
+            version,
+            session_id: "s1".into(),
+            boot_counter: Some(1),
+            entry_counter: Some(nonce),
+            stream_id: Some(DEFAULT_STREAM.into()),
+            kind: Some(DATA_KIND.into()),
+            payload_encoding: Some("utf8".into()),
+            payload: "{}".into(),
+            ..make_entry(&sk, "dev-1", None, prev, nonce, 1_700_000_000 + nonce as i64, "")
+        };
+        if version >= 9 { e.payload_commitment_scheme = Some("sha256".into()); }
+        e.attachments = attachments;
+        e.commit_payload().unwrap();
+        resign(&sk, e)
+    };
+    let image = vec![0xffu8; 4096];
+    let e1 = entry(None, 1, 10, Some(vec![Attachment::of(&image), Attachment::of(b"dump")]));
+    verifier.process_entry(&e1, 1_700_000_100).unwrap();
+    assert_eq!(e1.attachments()[0].size, 4096);
+    e1.attachments()[0].check_blob(&image).unwrap();
+
+    // Blobs are checked by size and content
+    assert_eq!(
+        e1.attachments()[1].check_blob(b"dumq").unwrap_err(),
+        VerifyError::AttachmentMismatch(format!("{}: content hash differs", e1.attachments()[1].sha256)),
+    );
+    assert!(matches!(e1.attachments()[0].check_blob(&image[1..]), Err(VerifyError::AttachmentMismatch(_))));
+    // The hash covers the attachment list
+    let mut swapped = e1.clone();
+    swapped.attachments.as_mut().unwrap()[1] = Attachment::of(b"other");
+    assert_ne!(compute_entry_hash(&swapped), e1.entry_hash);
+
+    // List checks and version gating
+    let e2 = entry(Some(&e1.entry_hash), 2, 10, Some(vec![]));
+    verifier.process_entry(&e2, 1_700_000_100).unwrap();
+    let twice = entry(Some(&e2.entry_hash), 3, 10, Some(vec![Attachment::of(b"a"), Attachment::of(b"a")]));
+    assert_eq!(verifier.process_entry(&twice, 1_700_000_100).unwrap_err(), VerifyError::Malformed(format!("duplicate attachment {}", Attachment::of(b"a").sha256)));
+    let upper = entry(Some(&e2.entry_hash), 3, 10, Some(vec![Attachment { sha256: "AB".repeat(32), size: 1 }]));
+    assert!(matches!(verifier.process_entry(&upper, 1_700_000_100), Err(VerifyError::Malformed(_))));
+    assert_eq!(verifier.process_entry(&entry(Some(&e2.entry_hash), 3, 10, None), 1_700_000_100).unwrap_err(), VerifyError::Malformed("version 10 requires attachments".into()));
+    assert_eq!(
+        verifier.process_entry(&entry(Some(&e2.entry_hash), 3, 9, Some(vec![])), 1_700_000_100).unwrap_err(),
+        VerifyError::Malformed("attachments requires version 10".into()),
+    );
+}
+
+#[test]
+fn deterministic_cbor_matches_rfc8949_examples() {
+    let cases: Vec<(Cbor, &str)> = vec![
+        (0u64.into(), "00"),
+        (23u64.into(), "17"),
+        (24u64.into(), "1818"),
+        (1000u64.into(), "1903e8"),
+        (1_000_000u64.into(), "1a000f4240"),
+        (1_000_000_000_000u64.into(), "1b000000e8d4a51000"),
+        (u64::MAX.into(), "1bffffffffffffffff"),
+        ((-1i64).into(), "20"),
+        ((-100i64).into(), "3863"),
+        ((-1000i64).into(), "3903e7"),
+        (i64::MIN.into(), "3b7fffffffffffffff"),
+        ("".into(), "60"),
+        ("IETF".into(), "6449455446"),
+        ("\u{fc}".into(), "62c3bc"),
+        ("\u{6c34}".into(), "63e6b0b4"),
+        (Cbor::Bytes(vec![1, 2, 3, 4]), "4401020304"),
+        (Cbor::Array(vec![1u64.into(), Cbor::Array(vec![2u64.into(), 3u64.into()]), Cbor::Array(vec![4u64.into(), 5u64.into()])]), "8301820203820405"),
+        (Cbor::Array((1u64..=25).map(Cbor::from).collect()), "98190102030405060708090a0b0c0d0e0f101112131415161718181819"),
+        (Cbor::Bool(false), "f4"),
+        (Cbor::Null, "f6"),
+        (None::<u64>.into(), "f6"),
+    ];
+    for (value, expected) in cases {
+        assert_eq!(hex::encode(cbor::encode(&value)), expected, "{value:?}");
+    }
+    // Map entries are sorted by their encoded keys: shorter keys first, then bytewise
+    let map = Cbor::Map(vec![("b".into(), Cbor::Array(vec![2u64.into(), 3u64.into()])), (10u64.into(), 1u64.into()), ("a".into(), 1u64.into())]);
+    assert_eq!(hex::encode(cbor::encode(&map)), "a30a016161016162820203");
+}
+
+#[test]
+fn published_test_vectors_are_current() {
+    let published: serde_json::Value = serde_json::from_str(include_str!("../../docs/api/test-vectors.json")).unwrap();
+    assert!(published == crate::vectors::test_vectors(), "regenerate with `cargo run -p taric-core --example test_vectors > docs/api/test-vectors.json`");
+
+    // The vectors are consistent: hashes, signatures and entries verify
+    let key = |name: &str| VerifyingKey {
+        algo: "ed25519".into(),
+        key: B64.decode(published["keys"][name]["pubkey_base64"].as_str().unwrap()).unwrap(),
+        key_id: None,
+    };
+    let mut verified = 0;
+    for v in published["entries"].as_array().unwrap() {
+        let entry: LogEntry = serde_json::from_value(v["entry"].clone()).unwrap();
+        let hash_cbor = hex::decode(v["hash_cbor_hex"].as_str().unwrap()).unwrap();
+        assert_eq!(hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&hash_cbor)), v["entry_hash"].as_str().unwrap());
+        assert_eq!(compute_entry_hash(&entry), entry.entry_hash);
+        crate::verifier::verify_signature(&key("device"), "ed25519", &cbor_for_sign(&entry), &entry.signature).unwrap();
+        entry.check_commitment().unwrap();
+        verified += 1;
+    }
+    assert_eq!(verified, LATEST_ENTRY_VERSION as usize);
+    for v in published["acks"].as_array().unwrap() {
+        let ack: Ack = serde_json::from_value(v["input"].clone()).unwrap();
+        assert_eq!(hex::encode(cbor_for_ack_sign(&ack)), v["sign_cbor_hex"].as_str().unwrap());
+        crate::verifier::verify_signature(&key("server"), "ed25519", &cbor_for_ack_sign(&ack), &ack.server_signature).unwrap();
+    }
+    let checkpoint: Checkpoint = serde_json::from_value(published["checkpoints"][0]["input"].clone()).unwrap();
+    checkpoint.verify(&key("server")).unwrap();
+    checkpoint.cosignatures[0].verify(&checkpoint, &key("witness")).unwrap();
+    let proof: EquivocationProof = serde_json::from_value(published["equivocation_proofs"][0]["input"].clone()).unwrap();
+    proof.verify(&key("server"), &key("device")).unwrap();
+    for v in published["cross_profile"].as_array().unwrap().iter().chain(published["cose_sign1"].as_array().unwrap().iter().take(1)) {
+        let entry: LogEntry = serde_json::from_value(v.get("entry").unwrap_or(&v["input"]).clone()).unwrap();
+        assert_eq!(compute_entry_hash(&entry), entry.entry_hash);
+        crate::verifier::verify_signature(&key("device"), "ed25519", &entry.signing_input().unwrap(), &entry.signature).unwrap();
+    }
+}
+
+#[test]
+fn cbor_envelope_is_lossless_and_compact() {
+    let vectors = crate::vectors::test_vectors();
+    for v in vectors["entries"].as_array().unwrap() {
+        let entry: LogEntry = serde_json::from_value(v["entry"].clone()).unwrap();
+        let bytes = entry.to_cbor();
+        assert_eq!(LogEntry::from_cbor(&bytes).unwrap(), entry);
+        assert!(bytes.len() * 10 < serde_json::to_vec(&entry).unwrap().len() * 7, "{}: {} bytes", v["name"], bytes.len());
+    }
+    for v in vectors["acks"].as_array().unwrap() {
+        let ack: Ack = serde_json::from_value(v["input"].clone()).unwrap();
+        assert_eq!(Ack::from_cbor(&ack.to_cbor()).unwrap(), ack);
+    }
+
+    // Values that do not pack into bytes travel as text
+    let (sk, _) = keys();
+    let mut odd = make_entry(&sk, "sensor-7", None, Some(&"AB".repeat(32)), 1, -5, "");
+    odd.signature = "not base64!".into();
+    odd.payload_encoding = Some("base64".into());
+    odd.payload = "AQ".into();
+    odd.attachments = Some(vec![Attachment::of(b"x"), Attachment { sha256: "nothex".into(), size: 0 }]);
+    assert_eq!(LogEntry::from_cbor(&odd.to_cbor()).unwrap(), odd);
+    let erased = LogEntry { payload: String::new(), ..odd.clone() };
+    assert_eq!(LogEntry::from_cbor(&erased.to_cbor()).unwrap(), erased);
+
+    // Strict parsing
+    let entry: LogEntry = serde_json::from_value(vectors["entries"][0]["entry"].clone()).unwrap();
+    let mut bytes = entry.to_cbor();
+    bytes.push(0);
+    assert_eq!(LogEntry::from_cbor(&bytes).unwrap_err(), VerifyError::Malformed("cbor envelope: trailing bytes after the data item".into()));
+    let unknown = cbor::encode(&Cbor::Map(vec![(99u64.into(), Cbor::Null)]));
+    assert!(matches!(LogEntry::from_cbor(&unknown), Err(VerifyError::Malformed(m)) if m.contains("unknown key")));
+    let missing = cbor::encode(&Cbor::Map(vec![(1u64.into(), 1u64.into())]));
+    assert_eq!(LogEntry::from_cbor(&missing).unwrap_err(), VerifyError::Malformed("cbor envelope: missing payload".into()));
+    let wrong = cbor::encode(&Cbor::Map(vec![(1u64.into(), "1".into())]));
+    assert_eq!(LogEntry::from_cbor(&wrong).unwrap_err(), VerifyError::Malformed("cbor envelope: version: expected an unsigned integer".into()));
+    assert!(cbor::decode(&[0x9f, 0x01, 0xff]).is_err());
+    assert!(cbor::decode(&[0x5a, 0xff, 0xff, 0xff, 0xff]).is_err());
+    assert_eq!(cbor::decode(&hex::decode("a26161016162820203").unwrap()).unwrap(), Cbor::Map(vec![("a".into(), 1u64.into()), ("b".into(), Cbor::Array(vec![2u64.into(), 3u64.into()]))]));
+}
+
+#[test]
+fn cose_sign1_entries_and_acks_verify() {
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let server_vk = DalekVk::from(&SigningKey::from_bytes(&[9u8; 32]));
+    let verifier = Verifier::new(trust, store, Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32])));
+    let cose_signed = |sk: &SigningKey, mut e: LogEntry| {
+        e.signature_format = Some(COSE_SIGN1.into());
+        e.signature = B64.encode(sk.sign(&e.signing_input().unwrap()).to_bytes());
+        e
+    };
+    let e1 = cose_signed(&sk, make_entry(&sk, "dev-1", Some("001-key1-1"), None, 1, 1_700_000_000, "{\"t\":22.5}"));
+    let message = e1.to_cose_sign1().unwrap();
+    assert_eq!(message[0], 0xd2);
+    assert_eq!(LogEntry::from_cose_sign1(&message).unwrap(), e1);
+    assert_eq!(LogEntry::from_cose_sign1(&message[1..]).unwrap(), e1);
+
+    // Verifiable as plain COSE: Sig_structure over the message's own header and payload
+    let Cbor::Array(items) = cbor::decode(&message[1..]).unwrap() else { panic!("not an array") };
+    let Ok([Cbor::Bytes(protected), Cbor::Map(unprotected), Cbor::Bytes(payload), Cbor::Bytes(sig)]) = <[Cbor; 4]>::try_from(items) else { panic!("not COSE_Sign1") };
+    assert_eq!(hex::encode(&protected), format!("a20127044a{}", hex::encode("001-key1-1")));
+    assert!(unprotected.is_empty());
+    let sig_structure = cbor::encode(&Cbor::Array(vec!["Signature1".into(), Cbor::Bytes(protected.clone()), Cbor::Bytes(Vec::new()), Cbor::Bytes(payload.clone())]));
+    vk.verify(&sig_structure, &Signature::from_slice(&sig).unwrap()).expect("COSE signature verifies");
+
+    // The ACK of a COSE-signed entry is COSE-signed
+    let ack = verifier.process_entry(&e1, 1_700_000_050).expect("COSE entry accepted");
+    assert_eq!(ack.signature_format.as_deref(), Some(COSE_SIGN1));
+    assert_eq!(Ack::from_cose_sign1(&ack.to_cose_sign1().unwrap()).unwrap(), ack);
+    let ack_sig = Signature::from_slice(&B64.decode(&ack.server_signature).unwrap()).unwrap();
+    server_vk.verify(&cose_for_ack_sign(&ack), &ack_sig).expect("COSE ACK signature verifies");
+    assert!(server_vk.verify(&cbor_for_ack_sign(&ack), &ack_sig).is_err());
+
+    // A native signature does not pass as COSE, nor the other way round
+    let e2 = make_entry(&sk, "dev-1", Some("001-key1-1"), Some(&e1.entry_hash), 2, 1_700_000_100, "{}");
+    assert!(e2.to_cose_sign1().is_err());
+    let relabelled = LogEntry { signature_format: Some(COSE_SIGN1.into()), ..e2.clone() };
+    assert_eq!(verifier.process_entry(&relabelled, 1_700_000_150).unwrap_err(), VerifyError::InvalidSignature);
+    let unknown = LogEntry { signature_format: Some("jws".into()), ..e2.clone() };
+    assert_eq!(verifier.process_entry(&unknown, 1_700_000_150).unwrap_err(), VerifyError::Malformed("unknown signature_format jws".into()));
+    assert_eq!(verifier.process_entry(&e2, 1_700_000_150).unwrap().signature_format, None);
+
+    // Only the deterministic encoding is accepted: alg -8 in a two-byte form
+    let loose = [&[0xa2, 0x01, 0x38, 0x07][..], &protected[3..]].concat();
+    let loose = cbor::encode(&Cbor::Array(vec![Cbor::Bytes(loose), Cbor::Map(Vec::new()), Cbor::Bytes(payload), Cbor::Bytes(sig)]));
+    assert_eq!(LogEntry::from_cose_sign1(&loose).unwrap_err(), VerifyError::Malformed("cose: not the deterministic encoding of its content".into()));
+
+    // From version 8 the payload rides in the unprotected header, so erasure keeps the signature
+    let device = SigningKey::from_bytes(&crate::vectors::DEVICE_SEED);
+    let v10: LogEntry = serde_json::from_value(crate::vectors::test_vectors()["entries"][9]["entry"].clone()).unwrap();
+    let v10 = cose_signed(&device, v10);
+    let Cbor::Array(items) = cbor::decode(&v10.to_cose_sign1().unwrap()[1..]).unwrap() else { panic!("not an array") };
+    assert!(matches!(&items[1], Cbor::Map(h) if h.len() == 2 && h[0].0 == "payload".into()));
+    let erased = v10.erased().unwrap();
+    assert_eq!(LogEntry::from_cose_sign1(&erased.to_cose_sign1().unwrap()).unwrap(), erased);
+    let device_key = VerifyingKey { algo: "ed25519".into(), key: device.verifying_key().to_bytes().to_vec(), key_id: None };
+    crate::verifier::verify_signature(&device_key, "ed25519", &erased.signing_input().unwrap(), &erased.signature).expect("erased entry verifies");
+}
+
+#[test]
+fn jcs_profile_hashes_and_signs_canonical_json() {
+    // RFC 8785 sections 3.2.2 and 3.2.3
+    let sorted = serde_json::json!({ "\u{20ac}": "Euro Sign", "\r": "Carriage Return", "\u{fb33}": "Hebrew Letter Dalet With Dagesh", "1": "One", "\u{1f600}": "Emoji: Grinning Face", "\u{80}": "Control", "\u{f6}": "Latin Small Letter O With Diaeresis" });
+    assert_eq!(
+        String::from_utf8(jcs::encode(&sorted)).unwrap(),
+        "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",\"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",\"\u{1f600}\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+    );
+    assert_eq!(jcs::encode(&serde_json::json!("\u{20ac}$\u{f}\nA'B\"\\\\\"/")), "\"\u{20ac}$\\u000f\\nA'B\\\"\\\\\\\\\\\"/\"".as_bytes());
+    assert_eq!(jcs::encode(&serde_json::json!([-1, 0, { "b": null, "a": true }])), b"[-1,0,{\"a\":true,\"b\":null}]");
+    jcs::check_integers(&serde_json::json!([jcs::MAX_SAFE_INTEGER, -(jcs::MAX_SAFE_INTEGER as i64)])).unwrap();
+    assert!(jcs::check_integers(&serde_json::json!({ "n": jcs::MAX_SAFE_INTEGER + 1 })).is_err());
+    assert!(jcs::check_integers(&serde_json::json!(1.5)).is_err());
+    // Numbers as ECMAScript writes them (RFC 8785 section 3.2.2.3)
+    let numbers = serde_json::json!([1e30, 4.5, 0.002, 1e-7, 333333333.3333333, -0.0, 1e21, 1e20, 5e-324]);
+    assert_eq!(jcs::encode(&numbers), b"[1e+30,4.5,0.002,1e-7,333333333.3333333,0,1e+21,100000000000000000000,5e-324]");
+    jcs::check_numbers(&numbers).unwrap();
+
+    let (sk, vk) = keys();
+    let (trust, store) = trust_and_store(&vk);
+    let verifier = Verifier::new(trust, store, Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32])));
+    let entry = |prev: Option<&str>, nonce: u64, version: u8, canon: Option<&str>| {
+        let mut e = LogEntry {
+            version,
+            session_id: "s1".into(),
+            boot_counter: Some(1),
+            entry_counter: Some(nonce),
+            stream_id: Some(DEFAULT_STREAM.into()),
+            kind: Some(DATA_KIND.into()),
+            payload_encoding: Some("utf8".into()),
+            payload: "{\"t\":1}".into(),
+            ..make_entry(&sk, "dev-1", None, prev, nonce, 1_700_000_000 + nonce as i64, "")
+        };
+        e.payload_commitment_scheme = Some("sha256".into());
+        e.attachments = Some(Vec::new());
+        e.canon = canon.map(String::from);
+        e.commit_payload().unwrap();
+        resign(&sk, e)
+    };
+
+    // The hash covers the entry's JSON object without the signature, salt and payload
+    let e1 = entry(None, 1, 11, Some(CANON_JCS));
+    verifier.process_entry(&e1, 1_700_000_100).expect("jcs entry accepted");
+    let text = String::from_utf8(jcs_for_hash(&e1)).unwrap();
+    assert!(text.starts_with("{\"algo\":\"ed25519\",\"attachments\":[],\"boot_counter\":1,\"canon\":\"jcs\",\"device_id\":\"dev-1\","), "{text}");
+    assert!(text.contains("\"key_id\":null") && !text.contains("\"payload\"") && !text.contains("payload_salt") && !text.contains("\"entry_hash\""));
+    assert_eq!(e1.entry_hash, hex::encode(<sha2::Sha256 as sha2::Digest>::digest(text.as_bytes())));
+    assert!(String::from_utf8(e1.signing_input().unwrap()).unwrap().contains(&format!("\"entry_hash\":\"{}\"", e1.entry_hash)));
+    assert_eq!(compute_entry_hash(&e1.erased().unwrap()), e1.entry_hash);
+    assert_eq!(LogEntry::from_cbor(&e1.to_cbor()).unwrap(), e1);
+
+    // The profile is bound: relabelling an entry breaks its hash
+    let e2 = entry(Some(&e1.entry_hash), 2, 11, Some(CANON_CBOR));
+    let relabelled = LogEntry { canon: Some(CANON_JCS.into()), ..e2.clone() };
+    assert_eq!(verifier.process_entry(&relabelled, 1_700_000_100).unwrap_err(), VerifyError::HashMismatch);
+    verifier.process_entry(&e2, 1_700_000_100).expect("cbor entry accepted");
+
+    // Gating, and integers the JSON form cannot carry exactly
+    assert_eq!(verifier.process_entry(&entry(None, 1, 10, Some(CANON_JCS)), 0).unwrap_err(), VerifyError::Malformed("canon requires version 11".into()));
+    assert_eq!(verifier.process_entry(&entry(None, 1, 11, None), 0).unwrap_err(), VerifyError::Malformed("version 11 requires canon".into()));
+    assert_eq!(verifier.process_entry(&entry(None, 1, 11, Some("json")), 0).unwrap_err(), VerifyError::Malformed("unknown canon json".into()));
+    let mut big = entry(Some(&e2.entry_hash), 3, 11, Some(CANON_JCS));
+    big.entry_counter = Some(1 << 53);
+    assert_eq!(
+        verifier.process_entry(&resign(&sk, big), 1_700_000_100).unwrap_err(),
+        VerifyError::Malformed("canon jcs: integer 9007199254740992 is beyond 2^53 - 1".into())
+    );
+}
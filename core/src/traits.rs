@@ -1,12 +1,17 @@
 /// Abstraction over device trust. Implementers decide how to map (device_id, key_id)
 /// to a verifying key, and whether a key is revoked.
-use crate::types::VerifyingKey;
+use std::sync::Arc;
+
+use crate::schema::PayloadSchema;
+use crate::types::{DeviceStatus, LogEntry, VerifyingKey};
 
 pub trait DeviceTrust: Send + Sync {
     /// Return a verifying key for `device_id` and an optional `key_id`.
     fn get_key(&self, device_id: &str, key_id: Option<&str>) -> Option<VerifyingKey>;
     /// Indicate whether a device/key is revoked (if true, verification must fail).
     fn is_revoked(&self, _device_id: &str, _key_id: Option<&str>) -> bool { false }
+    /// Payload schema of the device's class, at the version the device is pinned to (if any).
+    fn payload_schema(&self, _device_id: &str) -> Option<Arc<PayloadSchema>> { None }
 }
 
 /// Server-side ACK signer. Used to sign acknowledgements sent back to devices.
@@ -17,12 +22,73 @@ pub trait AckSigner: Send + Sync {
     fn sign(&self, msg: &[u8]) -> Vec<u8>;
 }
 
-/// Trait to persist minimal chain state per device.
+/// Trait to persist minimal chain state per device stream. Each (device, stream) is an
+/// independent chain with its own last hash, session nonces and entry counter.
+///
+/// Only `last_hash`, `last_nonce` and `update` are required. The other methods default to a
+/// store that remembers nothing else, which turns off what relies on them (noted on each);
+/// `InMemoryChainStore` implements them all.
 pub trait ChainStore: Send + Sync {
-    /// Get the last known hash, if any, for a device.
-    fn last_hash(&self, device_id: &str) -> Option<String>;
-    /// Get the last known nonce, if any, for a (device, session).
-    fn last_nonce(&self, device_id: &str, session_id: &str) -> Option<u64>;
-    /// Update the (hash, nonce) for a device and session after accepting an entry.
-    fn update(&self, device_id: &str, session_id: &str, last_hash: String, last_nonce: u64);
+    /// Get the last known hash, if any, for a device stream.
+    fn last_hash(&self, device_id: &str, stream_id: &str) -> Option<String>;
+    /// Get the last known nonce, if any, for a (device, stream, session).
+    fn last_nonce(&self, device_id: &str, stream_id: &str, session_id: &str) -> Option<u64>;
+    /// Record an accepted entry: its hash becomes the stream's last hash and its nonce the
+    /// session's last nonce, and its boot and entry counters are remembered. Also advances
+    /// `log_size` and the device's `device_count` by one.
+    fn update(&self, entry: &LogEntry);
+
+    /// List `(session_id, last_nonce)` for every session seen in a device stream. Default: none,
+    /// so chain heads list no sessions.
+    fn session_nonces(&self, _device_id: &str, _stream_id: &str) -> Vec<(String, u64)> { Vec::new() }
+    /// List the streams a device has accepted entries in. Default: none.
+    fn streams(&self, _device_id: &str) -> Vec<String> { Vec::new() }
+    /// Number of entries accepted so far across all devices, i.e. the next global log index.
+    /// Default: 0, so ACKs carry `log_index` 0.
+    fn log_size(&self) -> u64 { 0 }
+    /// Number of entries accepted so far for a device (all streams), i.e. its next sequence
+    /// number. Default: 0, so ACKs carry `device_seq` 0.
+    fn device_count(&self, _device_id: &str) -> u64 { 0 }
+    /// Hash of the accepted entry of a device stream that links to `previous_entry_hash`
+    /// (`None`: the stream's first entry), if any. Default: none, so a fork is refused as a
+    /// chain break rather than reported as equivocation.
+    fn successor(&self, _device_id: &str, _stream_id: &str, _previous_entry_hash: Option<&str>) -> Option<String> { None }
+    /// Hash of the accepted entry at `nonce` in a (device, stream, session), if any. Default:
+    /// none, with the same effect as `successor`.
+    fn entry_at_nonce(&self, _device_id: &str, _stream_id: &str, _session_id: &str, _nonce: u64) -> Option<String> { None }
+    /// Highest boot counter accepted for a device, if any. Default: none, so boot counter
+    /// rollbacks go undetected.
+    fn boot_counter(&self, _device_id: &str) -> Option<u64> { None }
+    /// Boot counter of a (device, session), if its entries carried one. Default: none.
+    fn session_boot_counter(&self, _device_id: &str, _session_id: &str) -> Option<u64> { None }
+    /// Entry counter of the last accepted entry of a device stream that carried one. Default:
+    /// none, so entry counter regressions go undetected.
+    fn entry_counter(&self, _device_id: &str, _stream_id: &str) -> Option<u64> { None }
+    /// Key announced by the device's latest accepted `key_rotation` entry, if any. Default: none,
+    /// so rotations are not enforced and the trust source's keys stay valid.
+    fn rotated_key(&self, _device_id: &str) -> Option<VerifyingKey> { None }
+    /// Remember the key a device rotated to (`key.key_id` is set). Default: forgets it.
+    fn rotate_key(&self, _device_id: &str, _key: VerifyingKey) {}
+    /// Whether a `session_end` entry closed a (device, stream, session). Default: false, so
+    /// ended sessions stay open.
+    fn session_ended(&self, _device_id: &str, _stream_id: &str, _session_id: &str) -> bool { false }
+    /// Close a (device, stream, session). Default: does nothing.
+    fn end_session(&self, _device_id: &str, _stream_id: &str, _session_id: &str) {}
+    /// Firmware version from the device's latest accepted `firmware_update` entry, if any.
+    /// Default: none, so `from_version` is not checked.
+    fn firmware_version(&self, _device_id: &str) -> Option<String> { None }
+    /// Remember the firmware version a device runs. Default: forgets it.
+    fn set_firmware_version(&self, _device_id: &str, _version: &str) {}
+}
+
+/// Per-device status and the tamper indicators that drive it.
+pub trait DeviceStatusStore: Send + Sync {
+    /// Current status of a device; devices never seen are `Active`.
+    fn status(&self, device_id: &str) -> DeviceStatus;
+    /// Set the status of a device.
+    fn set_status(&self, device_id: &str, status: DeviceStatus);
+    /// Count a failed signature check; returns the device's consecutive failures so far.
+    fn record_signature_failure(&self, device_id: &str) -> u32;
+    /// Reset the consecutive failure count after a valid signature.
+    fn clear_signature_failures(&self, device_id: &str);
 }
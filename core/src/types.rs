@@ -1,7 +1,13 @@
 use serde::{Deserialize, Serialize};
-use serde_cbor::to_vec;
 use sha2::{Digest, Sha256};
 
+use crate::attachments::Attachment;
+use crate::cbor::{self, Value};
+use crate::cose::{cose_for_ack_sign, cose_for_sign, COSE_SIGN1};
+use crate::errors::VerifyError;
+use crate::jcs;
+use crate::kinds::DATA_KIND;
+
 /// Public verifying key material for a device.
 #[derive(Clone, Debug)]
 pub struct VerifyingKey {
@@ -24,6 +30,90 @@ pub struct LogEntry {
     pub payload: String,
     pub signature: String,
     pub previous_entry_hash: Option<String>,
+    /// Device boot counter, strictly increasing with every new session (version 2 and later).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_counter: Option<u64>,
+    /// Cumulative number of entries the device has created in this entry's stream, including this one
+    /// (version 3 and later).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_counter: Option<u64>,
+    /// Chain this entry belongs to among the device's independent chains (version 4 and later).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<String>,
+    /// Registered kind of the entry, which fixes the payload schema (version 5 and later).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// How `payload` maps to the logged bytes: `utf8`, `base64`, `cbor` (version 6 and later)
+    /// or `hpke` (version 7 and later).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_encoding: Option<String>,
+    /// Base64 salt of the payload commitment (version 8 and later); dropped, with the payload,
+    /// when the payload is erased.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_salt: Option<String>,
+    /// Commitment to the payload, hashed in place of it (version 8 and later); see `erasure`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_commitment: Option<String>,
+    /// How `payload_commitment` is computed: `sha256` or `fields` (version 9 and later).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_commitment_scheme: Option<String>,
+    /// Blobs stored apart from the entry, by SHA-256 and size (version 10 and later).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachments: Option<Vec<Attachment>>,
+    /// Canonical form the hash and signature are computed over: `cbor` or `jcs` (version 11
+    /// and later); `cbor` before.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canon: Option<String>,
+    /// What `signature` is made over: absent for `cbor_for_sign`, or `cose_sign1` for the COSE
+    /// `Sig_structure` (see `cose`). Not covered by the entry hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_format: Option<String>,
+}
+
+/// Highest log entry wire version understood; see `docs/api/wire-format.md` for what each adds.
+pub const LATEST_ENTRY_VERSION: u8 = 11;
+
+/// `canon` of the RFC 8949 deterministic CBOR arrays, the only form before version 11.
+pub const CANON_CBOR: &str = "cbor";
+/// `canon` of the RFC 8785 canonical JSON objects (see `jcs`).
+pub const CANON_JCS: &str = "jcs";
+
+/// Stream of entries before version 4, which have no `stream_id`.
+pub const DEFAULT_STREAM: &str = "default";
+
+impl LogEntry {
+    /// The stream this entry is chained in.
+    pub fn stream(&self) -> &str { self.stream_id.as_deref().unwrap_or(DEFAULT_STREAM) }
+    /// The entry kind; `data` before version 5.
+    pub fn kind(&self) -> &str { self.kind.as_deref().unwrap_or(DATA_KIND) }
+    /// The canonical form; `cbor` before version 11.
+    pub fn canon(&self) -> &str { self.canon.as_deref().unwrap_or(CANON_CBOR) }
+
+    /// The bytes the entry hash is computed over, per `canon`.
+    pub fn hashing_input(&self) -> Vec<u8> {
+        if self.canon() == CANON_JCS { jcs_for_hash(self) } else { cbor_for_hash(self) }
+    }
+
+    /// The bytes `signature` is made over, per `signature_format` and `canon`.
+    pub fn signing_input(&self) -> Result<Vec<u8>, VerifyError> {
+        match self.signature_format.as_deref() {
+            None if self.canon() == CANON_JCS => Ok(jcs_for_sign(self)),
+            None => Ok(cbor_for_sign(self)),
+            Some(COSE_SIGN1) => cose_for_sign(self),
+            Some(other) => Err(VerifyError::Malformed(format!("unknown signature_format {other}"))),
+        }
+    }
+}
+
+impl Ack {
+    /// The bytes `server_signature` is made over, per `signature_format`.
+    pub fn signing_input(&self) -> Result<Vec<u8>, VerifyError> {
+        match self.signature_format.as_deref() {
+            None => Ok(cbor_for_ack_sign(self)),
+            Some(COSE_SIGN1) => Ok(cose_for_ack_sign(self)),
+            Some(other) => Err(VerifyError::Malformed(format!("unknown signature_format {other}"))),
+        }
+    }
 }
 
 /// ACK as defined in the wire format.
@@ -35,51 +125,164 @@ pub struct Ack {
     pub timestamp: i64,
     pub server_signer_id: String,
     pub server_signature: String,
+    /// Position of the entry in the server's global log (its Merkle leaf index); null unless accepted.
+    #[serde(default)]
+    pub log_index: Option<u64>,
+    /// Position of the entry in its device's chain, starting at 0; null unless accepted.
+    #[serde(default)]
+    pub device_seq: Option<u64>,
+    /// Problems that did not prevent acceptance, e.g. a payload schema violation under the warn policy.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// What `server_signature` is made over: absent for `cbor_for_ack_sign`, or `cose_sign1`
+    /// (see `cose`). ACKs of COSE-signed entries are COSE-signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_format: Option<String>,
+}
+
+/// Last nonce of one session, as reported in a `ChainHead`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionHead {
+    pub session_id: String,
+    pub last_nonce: u64,
+}
+
+/// Server-signed view of a device's chain state, used by devices to resynchronize.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChainHead {
+    pub device_id: String,
+    pub stream_id: String,
+    pub last_entry_hash: Option<String>,
+    /// Open sessions ordered by `session_id`.
+    pub sessions: Vec<SessionHead>,
+    pub timestamp: i64,
+    pub server_signer_id: String,
+    pub server_signature: String,
+}
+
+/// Lifecycle status of a device, consulted by the `Verifier`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceStatus {
+    /// Entries are verified, chained and ACKed as `accepted`.
+    #[default]
+    Active,
+    /// Suspected of compromise: entries are still verified and chained, but ACKed as `quarantined`.
+    Quarantined,
+    /// Decommissioned: entries are rejected.
+    Retired,
+}
+
+impl DeviceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceStatus::Active => "active",
+            DeviceStatus::Quarantined => "quarantined",
+            DeviceStatus::Retired => "retired",
+        }
+    }
+}
+
+/// Canonical fields of an entry in wire order, excluding `entry_hash` and `signature`.
+/// Fields introduced by later wire versions are appended after the version 1 fields. From
+/// version 8 the payload commitment takes the place of the payload.
+fn canonical_fields(e: &LogEntry) -> Vec<Value> {
+    let opt_text = |s: &Option<String>| Value::from(s.as_deref());
+    let mut fields = vec![
+        e.version.into(),
+        (&e.device_id).into(),
+        e.timestamp.into(),
+        (&e.session_id).into(),
+        e.nonce.into(),
+        (&e.algo).into(),
+        opt_text(&e.key_id),
+        if e.version >= 8 { opt_text(&e.payload_commitment) } else { (&e.payload).into() },
+        opt_text(&e.previous_entry_hash),
+    ];
+    if e.version >= 2 { fields.push(e.boot_counter.into()); }
+    if e.version >= 3 { fields.push(e.entry_counter.into()); }
+    if e.version >= 4 { fields.push(opt_text(&e.stream_id)); }
+    if e.version >= 5 { fields.push(opt_text(&e.kind)); }
+    if e.version >= 6 { fields.push(opt_text(&e.payload_encoding)); }
+    if e.version >= 9 { fields.push(opt_text(&e.payload_commitment_scheme)); }
+    if e.version >= 10 {
+        fields.push(e.attachments.as_ref().map_or(Value::Null, |list| {
+            Value::Array(list.iter().map(|a| Value::Array(vec![(&a.sha256).into(), a.size.into()])).collect())
+        }));
+    }
+    if e.version >= 11 { fields.push(opt_text(&e.canon)); }
+    fields
 }
 
 /// Canonicalize a log entry for hashing per docs (exclude entry_hash and signature).
 pub fn cbor_for_hash(e: &LogEntry) -> Vec<u8> {
-    to_vec(&(
-        e.version,
-        &e.device_id,
-        e.timestamp,
-        &e.session_id,
-        e.nonce,
-        &e.algo,
-        &e.key_id,
-        &e.payload,
-        &e.previous_entry_hash,
-    ))
-    .expect("CBOR serialization should not fail")
+    cbor::encode(&Value::Array(canonical_fields(e)))
 }
 
 /// Canonicalize a log entry for signature per docs (includes entry_hash, excludes signature).
 pub fn cbor_for_sign(e: &LogEntry) -> Vec<u8> {
-    to_vec(&(
-        e.version,
-        &e.entry_hash,
-        &e.device_id,
-        e.timestamp,
-        &e.session_id,
-        e.nonce,
-        &e.algo,
-        &e.key_id,
-        &e.payload,
-        &e.previous_entry_hash,
-    ))
-    .expect("CBOR serialization should not fail")
-}
-
-/// Canonicalize ACK for signing per docs (exclude server_signature).
+    let mut fields = canonical_fields(e);
+    fields.insert(1, (&e.entry_hash).into());
+    cbor::encode(&Value::Array(fields))
+}
+
+/// Canonicalize ACK for signing per docs (exclude server_signature). `warnings` is appended
+/// only when there are any, so ACKs without warnings keep their original form.
 pub fn cbor_for_ack_sign(a: &Ack) -> Vec<u8> {
-    to_vec(&(&a.entry_id, &a.new_entry_hash, &a.status, a.timestamp, &a.server_signer_id))
-        .expect("CBOR serialization should not fail")
+    let mut fields: Vec<Value> = vec![
+        (&a.entry_id).into(),
+        (&a.new_entry_hash).into(),
+        (&a.status).into(),
+        a.timestamp.into(),
+        (&a.server_signer_id).into(),
+        a.log_index.into(),
+        a.device_seq.into(),
+    ];
+    if !a.warnings.is_empty() {
+        fields.push(Value::Array(a.warnings.iter().map(Value::from).collect()));
+    }
+    cbor::encode(&Value::Array(fields))
+}
+
+/// Canonicalize a chain head for signing (exclude server_signature).
+pub fn cbor_for_head_sign(h: &ChainHead) -> Vec<u8> {
+    let sessions = h.sessions.iter().map(|s| Value::Array(vec![(&s.session_id).into(), s.last_nonce.into()])).collect();
+    cbor::encode(&Value::Array(vec![
+        (&h.device_id).into(),
+        (&h.stream_id).into(),
+        h.last_entry_hash.as_deref().into(),
+        Value::Array(sessions),
+        h.timestamp.into(),
+        (&h.server_signer_id).into(),
+    ]))
+}
+
+/// The entry's JSON object as hashed under `canon` `jcs`: every field but `signature`,
+/// `signature_format` and `payload_salt`, and from version 8 `payload` (the commitment stands
+/// for both). Optional fields of the entry's version that are absent are null.
+pub(crate) fn jcs_object(e: &LogEntry, with_hash: bool) -> serde_json::Value {
+    let mut v = serde_json::to_value(e).expect("entries serialize");
+    let members = v.as_object_mut().expect("entries are objects");
+    for name in ["signature", "signature_format", "payload_salt"] { members.remove(name); }
+    if !with_hash { members.remove("entry_hash"); }
+    if e.version >= 8 { members.remove("payload"); }
+    v
+}
+
+/// RFC 8785 canonical JSON of an entry for hashing (excludes entry_hash and signature).
+pub fn jcs_for_hash(e: &LogEntry) -> Vec<u8> {
+    jcs::encode(&jcs_object(e, false))
+}
+
+/// RFC 8785 canonical JSON of an entry for signature (includes entry_hash, excludes signature).
+pub fn jcs_for_sign(e: &LogEntry) -> Vec<u8> {
+    jcs::encode(&jcs_object(e, true))
 }
 
-/// Compute hex-encoded SHA-256 of the CBOR-hashed tuple.
+/// Compute hex-encoded SHA-256 of the canonical form selected by `canon`.
 pub fn compute_entry_hash(e: &LogEntry) -> String {
     let mut hasher = Sha256::new();
-    hasher.update(cbor_for_hash(e));
+    hasher.update(e.hashing_input());
     let out = hasher.finalize();
     hex::encode(out)
 }
@@ -0,0 +1,284 @@
+//! Test vectors for client implementations, published as `docs/api/test-vectors.json`.
+//!
+//! Everything is derived from fixed keys and inputs, so the output is reproducible: regenerate
+//! the file with `cargo run -p taric-core --example test_vectors > docs/api/test-vectors.json`
+//! after a change to the wire format. A test checks the published file is current.
+//!
+//! Each vector holds its input (the JSON object as sent, with the computed hash and signatures
+//! filled in), the canonical CBOR bytes in hex and, where applicable, the SHA-256 entry hash.
+//! Signatures are Ed25519 (deterministic) in base64; keys are given as 32-byte seeds. The
+//! `cose_sign1` vectors hold the COSE `Sig_structure` and the tagged COSE_Sign1 message instead.
+//! `jcs` holds RFC 8785 canonical JSON of single values, `field_commitments` the leaves of a
+//! `fields` payload commitment (wire version 9), and `cross_profile` the last entry
+//! under both canonical forms (`canon` `cbor` and `jcs`), with the JSON text hashed and signed.
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::{json, Value};
+
+use crate::attachments::Attachment;
+use crate::checkpoint::{cbor_for_checkpoint_sign, cbor_for_cosign, Checkpoint, Cosignature};
+use crate::cbor;
+use crate::cose::{cose_for_ack_sign, cose_for_sign, COSE_SIGN1};
+use crate::disclosure::{field_leaf, field_leaf_data, field_salt, fields_commitment};
+use crate::equivocation::{cbor_for_equivocation_sign, EquivocationProof};
+use crate::erasure::{cbor_for_tombstone_sign, payload_commitment, Tombstone};
+use crate::merkle::MerkleTree;
+use crate::traits::AckSigner;
+use crate::jcs;
+use crate::types::{cbor_for_ack_sign, cbor_for_hash, cbor_for_head_sign, cbor_for_sign, compute_entry_hash, jcs_for_hash, jcs_for_sign, Ack, ChainHead, LogEntry, SessionHead, CANON_JCS};
+use crate::verifier::Ed25519AckSigner;
+
+/// Seed of the device key the entry vectors are signed with.
+pub const DEVICE_SEED: [u8; 32] = [1u8; 32];
+/// Seed of the server key signing ACKs, chain heads, checkpoints and proofs.
+pub const SERVER_SEED: [u8; 32] = [2u8; 32];
+/// Seed of the witness key co-signing the checkpoint.
+pub const WITNESS_SEED: [u8; 32] = [3u8; 32];
+/// `payload_salt` of the version 8 and later entries.
+pub const PAYLOAD_SALT: [u8; 16] = [0x5a; 16];
+
+const DEVICE_ID: &str = "4c1f8a7e-2b9d-4e3a-9f61-0d2c5b7a8e90";
+const SESSION_ID: &str = "9e2d7c41-6a3b-4f05-8c1e-5b0a9d3f7e26";
+
+/// An entry of wire `version`, with every field that version requires.
+fn entry(version: u8, nonce: u64, previous_entry_hash: Option<String>) -> LogEntry {
+    let mut e = LogEntry {
+        version,
+        entry_hash: String::new(),
+        device_id: DEVICE_ID.into(),
+        timestamp: 1_700_000_000 + nonce as i64,
+        session_id: SESSION_ID.into(),
+        nonce,
+        algo: "ed25519".into(),
+        key_id: Some("001-key1".into()),
+        payload: r#"{"door_id":"D-17","badge_holder":"Alice","granted":true}"#.into(),
+        signature: String::new(),
+        previous_entry_hash,
+        boot_counter: (version >= 2).then_some(3),
+        entry_counter: (version >= 3).then_some(40 + nonce),
+        stream_id: (version >= 4).then(|| "access".into()),
+        kind: (version >= 5).then(|| "data".into()),
+        payload_encoding: (version >= 6).then(|| "utf8".into()),
+        payload_salt: None,
+        payload_commitment: None,
+        payload_commitment_scheme: (version >= 9).then(|| "fields".into()),
+        attachments: (version >= 10).then(|| vec![Attachment::of(b"firmware dump"), Attachment::of(&[0u8; 1000])]),
+        canon: (version >= 11).then(|| "cbor".into()),
+        signature_format: None,
+    };
+    if version >= 8 {
+        e.payload_salt = Some(B64.encode(PAYLOAD_SALT));
+        e.payload_commitment = Some(match version {
+            8 => payload_commitment(&PAYLOAD_SALT, &e.payload),
+            _ => fields_commitment(&PAYLOAD_SALT, &e.payload).expect("object payload"),
+        });
+    }
+    e
+}
+
+fn entry_vectors(device: &SigningKey) -> Vec<(LogEntry, Value)> {
+    let mut out = Vec::new();
+    let mut previous: Option<String> = None;
+    for (i, version) in (1..=crate::types::LATEST_ENTRY_VERSION).enumerate() {
+        let mut e = entry(version, i as u64 + 1, previous.clone());
+        e.entry_hash = compute_entry_hash(&e);
+        e.signature = B64.encode(device.sign(&cbor_for_sign(&e)).to_bytes());
+        previous = Some(e.entry_hash.clone());
+        let v = json!({
+            "name": format!("entry version {version}"),
+            "entry": e,
+            "hash_cbor_hex": hex::encode(cbor_for_hash(&e)),
+            "entry_hash": e.entry_hash,
+            "sign_cbor_hex": hex::encode(cbor_for_sign(&e)),
+            "signature": e.signature,
+        });
+        out.push((e, v));
+    }
+    out
+}
+
+fn public_key(seed: [u8; 32]) -> String { B64.encode(SigningKey::from_bytes(&seed).verifying_key().to_bytes()) }
+
+fn signed(name: &str, item: Value, canonical: Vec<u8>) -> Value {
+    json!({ "name": name, "input": item, "sign_cbor_hex": hex::encode(canonical) })
+}
+
+/// Canonical encodings of single values: unsigned and negative integers at each length
+/// boundary, text with multi-byte characters, byte strings, null and nested arrays.
+fn primitive_vectors() -> Vec<Value> {
+    let ints: [i64; 14] = [0, 1, 23, 24, 255, 256, 65_535, 65_536, 4_294_967_295, 4_294_967_296, -1, -24, -25, -1_700_000_000];
+    let mut out: Vec<Value> = ints.iter().map(|&n| json!({ "int": n, "cbor_hex": hex::encode(cbor::encode(&cbor::Value::from(n))) })).collect();
+    out.push(json!({ "int": u64::MAX, "cbor_hex": hex::encode(cbor::encode(&cbor::Value::from(u64::MAX))) }));
+    for s in ["", "a", "IETF", "\u{fc}", "\u{6c34}", "0123456789abcdef0123456789"] {
+        out.push(json!({ "text": s, "cbor_hex": hex::encode(cbor::encode(&cbor::Value::from(s))) }));
+    }
+    for b in [&b""[..], &[1, 2, 3, 4][..]] {
+        out.push(json!({ "bytes_hex": hex::encode(b), "cbor_hex": hex::encode(cbor::encode(&cbor::Value::Bytes(b.to_vec()))) }));
+    }
+    out.push(json!({ "null": null, "cbor_hex": hex::encode(cbor::encode(&cbor::Value::Null)) }));
+    let nested = cbor::Value::Array(vec![1u64.into(), cbor::Value::Array(vec![2u64.into(), 3u64.into()]), "x".into()]);
+    out.push(json!({ "array": [1, [2, 3], "x"], "cbor_hex": hex::encode(cbor::encode(&nested)) }));
+    out
+}
+
+/// RFC 8785 canonical JSON of single values: member order by UTF-16 code units (the example of
+/// RFC 8785 section 3.2.3), string escapes, integers up to the I-JSON limit, and numbers with a
+/// fraction or exponent.
+fn jcs_vectors() -> Vec<Value> {
+    let inputs = [
+        json!({ "\u{20ac}": "Euro Sign", "\r": "Carriage Return", "\u{fb33}": "Hebrew Letter Dalet With Dagesh", "1": "One", "\u{1f600}": "Emoji: Grinning Face", "\u{80}": "Control", "\u{f6}": "Latin Small Letter O With Diaeresis" }),
+        json!("\u{20ac}$\u{f}\nA'B\"\\\\\"/"),
+        json!([0, -1, jcs::MAX_SAFE_INTEGER, -(jcs::MAX_SAFE_INTEGER as i64), true, null, "\u{1}\u{8}\t\u{c}\r\u{1f}"]),
+        json!({ "b": [], "a": { "d": {}, "c": "" } }),
+        json!([1e30, 4.5, 0.002, 1e-7, 333333333.3333333, -0.0, 1e21, 1e20]),
+    ];
+    inputs.into_iter().map(|v| json!({ "input": v, "jcs": String::from_utf8(jcs::encode(&v)).expect("UTF-8") })).collect()
+}
+
+/// Field leaves and root of a `fields` commitment, one leaf per field in name order.
+fn field_vectors() -> Vec<Value> {
+    let payload = r#"{"granted":true,"door_id":"D-17","reader":{"zone":"B","floor":2},"temp_c":21.5}"#;
+    let object: serde_json::Map<String, Value> = serde_json::from_str(payload).expect("object payload");
+    let mut names: Vec<&String> = object.keys().collect();
+    names.sort();
+    let leaves: Vec<Value> = names.into_iter().map(|name| {
+        let salt = field_salt(&PAYLOAD_SALT, name);
+        json!({
+            "name": name,
+            "salt_hex": hex::encode(salt),
+            "value_jcs": String::from_utf8(jcs::encode(&object[name])).expect("UTF-8"),
+            "leaf_cbor_hex": hex::encode(field_leaf_data(&salt, name, &object[name])),
+            "leaf_hash": hex::encode(field_leaf(&salt, name, &object[name])),
+        })
+    }).collect();
+    vec![json!({
+        "payload": payload,
+        "payload_salt_hex": hex::encode(PAYLOAD_SALT),
+        "leaves": leaves,
+        "payload_commitment": fields_commitment(&PAYLOAD_SALT, payload).expect("object payload"),
+    })]
+}
+
+/// The same entry under both canonical forms.
+fn cross_profile_vectors(device: &SigningKey, last: &LogEntry) -> Vec<Value> {
+    let mut jcs_entry = LogEntry { canon: Some(CANON_JCS.into()), ..last.clone() };
+    jcs_entry.entry_hash = compute_entry_hash(&jcs_entry);
+    jcs_entry.signature = B64.encode(device.sign(&jcs_for_sign(&jcs_entry)).to_bytes());
+    let text = |b: Vec<u8>| String::from_utf8(b).expect("UTF-8");
+    vec![
+        json!({
+            "name": "canon cbor",
+            "entry": last,
+            "hash_cbor_hex": hex::encode(cbor_for_hash(last)),
+            "entry_hash": last.entry_hash,
+            "sign_cbor_hex": hex::encode(cbor_for_sign(last)),
+            "signature": last.signature,
+        }),
+        json!({
+            "name": "canon jcs",
+            "entry": jcs_entry,
+            "hash_jcs": text(jcs_for_hash(&jcs_entry)),
+            "entry_hash": jcs_entry.entry_hash,
+            "sign_jcs": text(jcs_for_sign(&jcs_entry)),
+            "signature": jcs_entry.signature,
+        }),
+    ]
+}
+
+/// All test vectors, as published in `docs/api/test-vectors.json`.
+pub fn test_vectors() -> Value {
+    let device = SigningKey::from_bytes(&DEVICE_SEED);
+    let server = Ed25519AckSigner::from_secret_key("server-key-1", SERVER_SEED);
+    let witness = Ed25519AckSigner::from_secret_key("witness-1", WITNESS_SEED);
+    let entries = entry_vectors(&device);
+    let last = &entries.last().expect("entry vectors").0;
+
+    let mut ack = Ack {
+        entry_id: last.entry_hash.clone(),
+        new_entry_hash: last.entry_hash.clone(),
+        status: "accepted".into(),
+        timestamp: 1_700_000_100,
+        server_signer_id: server.signer_id().into(),
+        server_signature: String::new(),
+        log_index: Some(41),
+        device_seq: Some(9),
+        warnings: Vec::new(),
+        signature_format: None,
+    };
+    ack.server_signature = B64.encode(server.sign(&cbor_for_ack_sign(&ack)));
+    let mut warned = ack.clone();
+    warned.warnings = vec!["schema violation: door-reader v2: $.granted: expected string".into()];
+    warned.server_signature = B64.encode(server.sign(&cbor_for_ack_sign(&warned)));
+    let mut rejected = Ack { status: "error:nonce not monotonic".into(), log_index: None, device_seq: None, ..ack.clone() };
+    rejected.server_signature = B64.encode(server.sign(&cbor_for_ack_sign(&rejected)));
+
+    let mut head = ChainHead {
+        device_id: DEVICE_ID.into(),
+        stream_id: "access".into(),
+        last_entry_hash: Some(last.entry_hash.clone()),
+        sessions: vec![SessionHead { session_id: SESSION_ID.into(), last_nonce: last.nonce }],
+        timestamp: 1_700_000_200,
+        server_signer_id: server.signer_id().into(),
+        server_signature: String::new(),
+    };
+    head.server_signature = B64.encode(server.sign(&cbor_for_head_sign(&head)));
+
+    let mut tree = MerkleTree::new();
+    for (e, _) in &entries { tree.push_entry_hash(&e.entry_hash).expect("hex entry hash"); }
+    let mut checkpoint = Checkpoint::sign(&server, tree.len(), &tree.root(), 1_700_000_300);
+    let cosignature = Cosignature::sign(&witness, &checkpoint, 1_700_000_310);
+    let cosign_cbor = cbor_for_cosign(&checkpoint, &cosignature.witness_id, cosignature.timestamp);
+    checkpoint.cosignatures.push(cosignature);
+
+    let erased = last.erased().expect("data entry");
+    let tombstone = Tombstone::sign(&server, &erased, "request 2023-117", 1_700_000_400).expect("erasable");
+
+    // Same chain position as the last entry, another payload
+    let mut forked = last.clone();
+    forked.payload = r#"{"door_id":"D-17","badge_holder":"Mallory","granted":true}"#.into();
+    forked.payload_commitment = Some(fields_commitment(&PAYLOAD_SALT, &forked.payload).expect("object payload"));
+    forked.entry_hash = compute_entry_hash(&forked);
+    forked.signature = B64.encode(device.sign(&cbor_for_sign(&forked)).to_bytes());
+    let proof = EquivocationProof::sign(&server, last.clone(), forked, 1_700_000_500);
+
+    let cross_profile = cross_profile_vectors(&device, last);
+
+    let mut cose_entry = LogEntry { signature_format: Some(COSE_SIGN1.into()), ..last.clone() };
+    let entry_sig_structure = cose_for_sign(&cose_entry).expect("ed25519 entry");
+    cose_entry.signature = B64.encode(device.sign(&entry_sig_structure).to_bytes());
+    let mut cose_ack = Ack { signature_format: Some(COSE_SIGN1.into()), ..ack.clone() };
+    cose_ack.server_signature = B64.encode(server.sign(&cose_for_ack_sign(&cose_ack)));
+    let cose = |name: &str, item: Value, sig_structure: Vec<u8>, message: Vec<u8>| {
+        json!({ "name": name, "input": item, "sig_structure_hex": hex::encode(sig_structure), "cose_sign1_hex": hex::encode(message) })
+    };
+
+    json!({
+        "description": "TARIC canonicalization test vectors: RFC 8949 deterministic CBOR and RFC 8785 canonical JSON, see docs/api/wire-format.md",
+        "keys": {
+            "device": { "algo": "ed25519", "seed_hex": hex::encode(DEVICE_SEED), "pubkey_base64": public_key(DEVICE_SEED) },
+            "server": { "algo": "ed25519", "signer_id": server.signer_id(), "seed_hex": hex::encode(SERVER_SEED), "pubkey_base64": public_key(SERVER_SEED) },
+            "witness": { "algo": "ed25519", "witness_id": witness.signer_id(), "seed_hex": hex::encode(WITNESS_SEED), "pubkey_base64": public_key(WITNESS_SEED) },
+        },
+        "cbor": primitive_vectors(),
+        "jcs": jcs_vectors(),
+        "field_commitments": field_vectors(),
+        "entries": entries.into_iter().map(|(_, v)| v).collect::<Vec<_>>(),
+        "acks": [
+            signed("accepted", json!(ack), cbor_for_ack_sign(&ack)),
+            signed("accepted with warnings", json!(warned), cbor_for_ack_sign(&warned)),
+            signed("rejected", json!(rejected), cbor_for_ack_sign(&rejected)),
+        ],
+        "chain_heads": [signed("chain head", json!(head), cbor_for_head_sign(&head))],
+        "checkpoints": [signed("checkpoint", json!(checkpoint), cbor_for_checkpoint_sign(&checkpoint))],
+        "cosignatures": [signed("co-signature of the checkpoint", json!(checkpoint.cosignatures[0]), cosign_cbor)],
+        "tombstones": [signed("tombstone of the last entry", json!(tombstone), cbor_for_tombstone_sign(&tombstone))],
+        "equivocation_proofs": [signed("fork of the last entry", json!(proof), cbor_for_equivocation_sign(&proof))],
+        "cross_profile": cross_profile,
+        "cose_sign1": [
+            cose("last entry signed as COSE_Sign1", json!(cose_entry), entry_sig_structure, cose_entry.to_cose_sign1().expect("COSE-signed")),
+            cose("its ACK signed as COSE_Sign1", json!(cose_ack), cose_for_ack_sign(&cose_ack), cose_ack.to_cose_sign1().expect("COSE-signed")),
+        ],
+    })
+}
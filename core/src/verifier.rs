@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
 use base64::engine::general_purpose::STANDARD as B64;
@@ -6,34 +7,167 @@ use base64::Engine as _;
 use ed25519_dalek::{Signature, SigningKey, VerifyingKey as DalekVerifyingKey, Signer as _, Verifier as _};
 
 use crate::errors::VerifyError;
-use crate::traits::{AckSigner, ChainStore, DeviceTrust};
-use crate::types::{Ack, LogEntry, cbor_for_ack_sign, cbor_for_sign, compute_entry_hash};
+use crate::jcs;
+use crate::kinds::{self, EntryKind, FirmwareUpdate};
+use crate::schema::SchemaPolicy;
+use crate::traits::{AckSigner, ChainStore, DeviceStatusStore, DeviceTrust};
+use crate::types::{jcs_object, Ack, ChainHead, DeviceStatus, LogEntry, CANON_CBOR, CANON_JCS, LATEST_ENTRY_VERSION, SessionHead, VerifyingKey, cbor_for_head_sign, compute_entry_hash};
+
+/// (device, stream, previous entry hash) -> accepted successor hash.
+type SuccessorMap = HashMap<(String, String, Option<String>), String>;
 
 /// Simple in-memory chain store suitable for tests and single-process demos.
 #[derive(Default)]
 pub struct InMemoryChainStore {
-    // last hash per device
-    last_hash: Mutex<HashMap<String, String>>,
-    // last nonce per (device, session)
-    last_nonce: Mutex<HashMap<(String, String), u64>>,
+    // last hash per (device, stream)
+    last_hash: Mutex<HashMap<(String, String), String>>,
+    // last nonce per (device, stream, session)
+    last_nonce: Mutex<HashMap<(String, String, String), u64>>,
+    // accepted entries overall and per device
+    log_size: AtomicU64,
+    device_count: Mutex<HashMap<String, u64>>,
+    // accepted entry hash per (device, stream, previous hash) and per (device, stream, session, nonce)
+    successors: Mutex<SuccessorMap>,
+    by_nonce: Mutex<HashMap<(String, String, String, u64), String>>,
+    // highest boot counter per device and boot counter per (device, session)
+    boot_counter: Mutex<HashMap<String, u64>>,
+    session_boot_counter: Mutex<HashMap<(String, String), u64>>,
+    // last entry counter per (device, stream)
+    entry_counter: Mutex<HashMap<(String, String), u64>>,
+    // state set by entry kinds: rotated key and firmware per device, ended (device, stream, session)s
+    rotated_key: Mutex<HashMap<String, VerifyingKey>>,
+    firmware: Mutex<HashMap<String, String>>,
+    ended_sessions: Mutex<HashSet<(String, String, String)>>,
 }
 
 impl InMemoryChainStore {
     /// Create a new, empty in-memory chain store.
-    pub fn new() -> Self { Self { last_hash: Mutex::new(HashMap::new()), last_nonce: Mutex::new(HashMap::new()) } }
+    pub fn new() -> Self { Self::default() }
 }
 
+fn key2(a: &str, b: &str) -> (String, String) { (a.to_string(), b.to_string()) }
+
 impl ChainStore for InMemoryChainStore {
-    fn last_hash(&self, device_id: &str) -> Option<String> {
-        self.last_hash.lock().unwrap().get(device_id).cloned()
+    fn last_hash(&self, device_id: &str, stream_id: &str) -> Option<String> {
+        self.last_hash.lock().unwrap().get(&key2(device_id, stream_id)).cloned()
+    }
+    fn last_nonce(&self, device_id: &str, stream_id: &str, session_id: &str) -> Option<u64> {
+        self.last_nonce.lock().unwrap().get(&(device_id.to_string(), stream_id.to_string(), session_id.to_string())).copied()
+    }
+    fn session_nonces(&self, device_id: &str, stream_id: &str) -> Vec<(String, u64)> {
+        self.last_nonce.lock().unwrap()
+            .iter()
+            .filter(|((d, st, _), _)| d == device_id && st == stream_id)
+            .map(|((_, _, s), n)| (s.clone(), *n))
+            .collect()
+    }
+    fn streams(&self, device_id: &str) -> Vec<String> {
+        let mut streams: Vec<String> = self.last_hash.lock().unwrap()
+            .keys()
+            .filter(|(d, _)| d == device_id)
+            .map(|(_, st)| st.clone())
+            .collect();
+        streams.sort();
+        streams
+    }
+    fn log_size(&self) -> u64 { self.log_size.load(Ordering::SeqCst) }
+    fn device_count(&self, device_id: &str) -> u64 {
+        self.device_count.lock().unwrap().get(device_id).copied().unwrap_or(0)
+    }
+    fn successor(&self, device_id: &str, stream_id: &str, previous_entry_hash: Option<&str>) -> Option<String> {
+        let key = (device_id.to_string(), stream_id.to_string(), previous_entry_hash.map(str::to_string));
+        self.successors.lock().unwrap().get(&key).cloned()
+    }
+    fn entry_at_nonce(&self, device_id: &str, stream_id: &str, session_id: &str, nonce: u64) -> Option<String> {
+        let key = (device_id.to_string(), stream_id.to_string(), session_id.to_string(), nonce);
+        self.by_nonce.lock().unwrap().get(&key).cloned()
+    }
+    fn boot_counter(&self, device_id: &str) -> Option<u64> {
+        self.boot_counter.lock().unwrap().get(device_id).copied()
+    }
+    fn session_boot_counter(&self, device_id: &str, session_id: &str) -> Option<u64> {
+        self.session_boot_counter.lock().unwrap().get(&key2(device_id, session_id)).copied()
+    }
+    fn entry_counter(&self, device_id: &str, stream_id: &str) -> Option<u64> {
+        self.entry_counter.lock().unwrap().get(&key2(device_id, stream_id)).copied()
+    }
+    fn rotated_key(&self, device_id: &str) -> Option<VerifyingKey> {
+        self.rotated_key.lock().unwrap().get(device_id).cloned()
+    }
+    fn rotate_key(&self, device_id: &str, key: VerifyingKey) {
+        self.rotated_key.lock().unwrap().insert(device_id.to_string(), key);
     }
-    fn last_nonce(&self, device_id: &str, session_id: &str) -> Option<u64> {
-        self.last_nonce.lock().unwrap().get(&(device_id.to_string(), session_id.to_string())).copied()
+    fn session_ended(&self, device_id: &str, stream_id: &str, session_id: &str) -> bool {
+        self.ended_sessions.lock().unwrap().contains(&(device_id.to_string(), stream_id.to_string(), session_id.to_string()))
     }
-    fn update(&self, device_id: &str, session_id: &str, last_hash: String, last_nonce: u64) {
-        self.last_hash.lock().unwrap().insert(device_id.to_string(), last_hash);
-        self.last_nonce.lock().unwrap().insert((device_id.to_string(), session_id.to_string()), last_nonce);
+    fn end_session(&self, device_id: &str, stream_id: &str, session_id: &str) {
+        self.ended_sessions.lock().unwrap().insert((device_id.to_string(), stream_id.to_string(), session_id.to_string()));
     }
+    fn firmware_version(&self, device_id: &str) -> Option<String> {
+        self.firmware.lock().unwrap().get(device_id).cloned()
+    }
+    fn set_firmware_version(&self, device_id: &str, version: &str) {
+        self.firmware.lock().unwrap().insert(device_id.to_string(), version.to_string());
+    }
+    fn update(&self, e: &LogEntry) {
+        let (device, stream) = (e.device_id.clone(), e.stream().to_string());
+        self.successors.lock().unwrap().insert((device.clone(), stream.clone(), e.previous_entry_hash.clone()), e.entry_hash.clone());
+        self.by_nonce.lock().unwrap().insert((device.clone(), stream.clone(), e.session_id.clone(), e.nonce), e.entry_hash.clone());
+        if let Some(c) = e.boot_counter {
+            self.session_boot_counter.lock().unwrap().insert(key2(&device, &e.session_id), c);
+            let mut max = self.boot_counter.lock().unwrap();
+            let m = max.entry(device.clone()).or_insert(c);
+            *m = (*m).max(c);
+        }
+        if let Some(c) = e.entry_counter { self.entry_counter.lock().unwrap().insert(key2(&device, &stream), c); }
+        self.last_hash.lock().unwrap().insert(key2(&device, &stream), e.entry_hash.clone());
+        self.last_nonce.lock().unwrap().insert((device.clone(), stream, e.session_id.clone()), e.nonce);
+        self.log_size.fetch_add(1, Ordering::SeqCst);
+        *self.device_count.lock().unwrap().entry(device).or_insert(0) += 1;
+    }
+}
+
+/// Simple in-memory device status store suitable for tests and single-process demos.
+#[derive(Default)]
+pub struct InMemoryDeviceStatus {
+    status: Mutex<HashMap<String, DeviceStatus>>,
+    signature_failures: Mutex<HashMap<String, u32>>,
+}
+
+impl InMemoryDeviceStatus {
+    /// Create a new store where every device is active.
+    pub fn new() -> Self { Self::default() }
+}
+
+impl DeviceStatusStore for InMemoryDeviceStatus {
+    fn status(&self, device_id: &str) -> DeviceStatus {
+        self.status.lock().unwrap().get(device_id).copied().unwrap_or_default()
+    }
+    fn set_status(&self, device_id: &str, status: DeviceStatus) {
+        self.status.lock().unwrap().insert(device_id.to_string(), status);
+    }
+    fn record_signature_failure(&self, device_id: &str) -> u32 {
+        let mut failures = self.signature_failures.lock().unwrap();
+        let n = failures.entry(device_id.to_string()).or_insert(0);
+        *n += 1;
+        *n
+    }
+    fn clear_signature_failures(&self, device_id: &str) {
+        self.signature_failures.lock().unwrap().remove(device_id);
+    }
+}
+
+/// Consecutive signature failures after which a device is quarantined, unless configured otherwise.
+pub const DEFAULT_SIGNATURE_FAILURE_LIMIT: u32 = 3;
+
+/// What to do with an entry whose boot counter goes backwards.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RollbackPolicy {
+    /// Reject it with `VerifyError::BootCounterRollback`.
+    #[default]
+    Reject,
+    /// Accept it if otherwise valid and quarantine the device; rejects without a device status store.
+    Quarantine,
 }
 
 /// Verifier coordinates trust, chain state, and ACK signing.
@@ -41,12 +175,39 @@ pub struct Verifier {
     trust: Arc<dyn DeviceTrust>,
     store: Arc<dyn ChainStore>,
     ack_signer: Arc<dyn AckSigner>,
+    statuses: Option<Arc<dyn DeviceStatusStore>>,
+    signature_failure_limit: u32,
+    rollback_policy: RollbackPolicy,
+    schema_policy: Option<SchemaPolicy>,
 }
 
 impl Verifier {
     /// Create a new `Verifier` with the given trust source, chain store, and ACK signer.
+    /// Without a device status store every device is treated as active.
     pub fn new(trust: Arc<dyn DeviceTrust>, store: Arc<dyn ChainStore>, ack_signer: Arc<dyn AckSigner>) -> Self {
-        Self { trust, store, ack_signer }
+        Self { trust, store, ack_signer, statuses: None, signature_failure_limit: DEFAULT_SIGNATURE_FAILURE_LIMIT, rollback_policy: RollbackPolicy::default(), schema_policy: None }
+    }
+
+    /// Consult and update device statuses: retired devices are rejected, quarantined devices'
+    /// entries are ACKed as `quarantined`. An active device is quarantined when it equivocates or
+    /// after `signature_failure_limit` consecutive invalid signatures.
+    pub fn with_device_status(mut self, statuses: Arc<dyn DeviceStatusStore>, signature_failure_limit: u32) -> Self {
+        self.statuses = Some(statuses);
+        self.signature_failure_limit = signature_failure_limit;
+        self
+    }
+
+    /// Choose how boot counter rollbacks are handled (default: reject).
+    pub fn with_rollback_policy(mut self, policy: RollbackPolicy) -> Self {
+        self.rollback_policy = policy;
+        self
+    }
+
+    /// Check `data` payloads against the payload schema of the device's class
+    /// (`DeviceTrust::payload_schema`), rejecting or warning on violations. Off by default.
+    pub fn with_schema_policy(mut self, policy: SchemaPolicy) -> Self {
+        self.schema_policy = Some(policy);
+        self
     }
 
     /// Verify a log entry JSON, update the chain state, and return a signed ACK.
@@ -56,15 +217,150 @@ impl Verifier {
     }
 
     /// Verify a parsed `LogEntry`, enforce chain rules, and return a signed ACK.
+    ///
+    /// The ACK binds the entry's position: its global `log_index` and per-device `device_seq`.
+    /// Like the chain checks, positions assume calls for the same store are serialized.
+    /// Its status is `accepted`, or `quarantined` if the device is quarantined.
     pub fn process_entry(&self, entry: &LogEntry, now_ts: i64) -> Result<Ack, VerifyError> {
+        let mut status = self.device_status(&entry.device_id);
+        if status == DeviceStatus::Retired { return Err(VerifyError::Retired(entry.device_id.clone())); }
+        // Devices send payloads; only stored entries are erased (see `replay_entry`).
+        if entry.is_erased() {
+            return Err(VerifyError::Malformed(format!("version {} requires payload_salt", entry.version)));
+        }
+        let kind = match self.check_entry(entry) {
+            Ok(kind) => kind,
+            Err(err) => {
+                self.note_failure(&entry.device_id, &err);
+                return Err(err);
+            }
+        };
+        if let Some(statuses) = &self.statuses { statuses.clear_signature_failures(&entry.device_id); }
+
+        // 6) Payload schema of the device class, per policy
+        let mut warnings = Vec::new();
+        if let (Some(policy), EntryKind::Data) = (self.schema_policy, &kind) {
+            if let Some(schema) = self.trust.payload_schema(&entry.device_id) {
+                match (schema.check(entry), policy) {
+                    (Ok(()), _) => {}
+                    (Err(err), SchemaPolicy::Warn) => warnings.push(err.to_string()),
+                    (Err(err), SchemaPolicy::Reject) => return Err(err),
+                }
+            }
+        }
+
+        // 7) Boot counter rollback, per policy
+        if let Err(err) = self.check_boot_counter(entry) {
+            match (&self.statuses, self.rollback_policy) {
+                (Some(statuses), RollbackPolicy::Quarantine) => {
+                    if status == DeviceStatus::Active { statuses.set_status(&entry.device_id, DeviceStatus::Quarantined); }
+                    status = DeviceStatus::Quarantined;
+                }
+                _ => return Err(err),
+            }
+        }
+
+        // 8) A tamper alarm quarantines the device, starting with the alarm itself
+        if let (EntryKind::TamperAlarm(_), Some(statuses)) = (&kind, &self.statuses) {
+            if status == DeviceStatus::Active { statuses.set_status(&entry.device_id, DeviceStatus::Quarantined); }
+            status = DeviceStatus::Quarantined;
+        }
+
+        // 9) Accept: assign position, update chain state and ACK
+        let log_index = self.store.log_size();
+        let device_seq = self.store.device_count(&entry.device_id);
+        self.store.update(entry);
+        self.apply_kind(entry, &kind);
+        let status = if status == DeviceStatus::Quarantined { "quarantined" } else { "accepted" };
+        let ack = self.make_ack(entry, status, warnings, now_ts, log_index, device_seq);
+        Ok(ack)
+    }
+
+    /// Status of a device; `Active` without a status store.
+    fn device_status(&self, device_id: &str) -> DeviceStatus {
+        self.statuses.as_ref().map_or(DeviceStatus::Active, |s| s.status(device_id))
+    }
+
+    /// Quarantine an active device whose rejected entry indicates tampering.
+    fn note_failure(&self, device_id: &str, err: &VerifyError) {
+        let Some(statuses) = &self.statuses else { return };
+        let quarantine = match err {
+            VerifyError::Equivocation(_) => true,
+            VerifyError::InvalidSignature => statuses.record_signature_failure(device_id) >= self.signature_failure_limit,
+            _ => false,
+        };
+        if quarantine && statuses.status(device_id) == DeviceStatus::Active {
+            statuses.set_status(device_id, DeviceStatus::Quarantined);
+        }
+    }
+
+    /// Re-verify a previously accepted entry and advance the chain state without signing an ACK.
+    ///
+    /// Runs the same checks as `process_entry` except the boot counter rule, which was applied
+    /// (per policy) when the entry was first accepted; used to rebuild a `ChainStore` from a persisted log.
+    /// Entries whose payload was erased are accepted on their commitment.
+    pub fn replay_entry(&self, entry: &LogEntry) -> Result<(), VerifyError> {
+        let kind = self.check_entry(entry)?;
+        self.store.update(entry);
+        self.apply_kind(entry, &kind);
+        Ok(())
+    }
+
+    /// Advance the chain state with a previously accepted entry that `replay_entry` refused only
+    /// because its device or key is no longer trusted (`DeviceUnknown`, `Revoked`), kind effects
+    /// included. The entry hash must still match the entry and `previous_entry_hash` the stream's
+    /// current head; the signature, nonce, counters and session state cannot be checked without the
+    /// key and are trusted as recorded when the entry was first accepted.
+    pub fn replay_untrusted_entry(&self, entry: &LogEntry) -> Result<(), VerifyError> {
+        if compute_entry_hash(entry) != entry.entry_hash { return Err(VerifyError::HashMismatch); }
+        if self.store.last_hash(&entry.device_id, entry.stream()) != entry.previous_entry_hash {
+            return Err(VerifyError::PreviousHashMismatch);
+        }
+        self.store.update(entry);
+        if let Ok(kind) = kinds::parse(entry) {
+            self.apply_kind(entry, &kind);
+        }
+        Ok(())
+    }
+
+    /// Chain state changes of an accepted entry's kind.
+    fn apply_kind(&self, entry: &LogEntry, kind: &EntryKind) {
+        match kind {
+            EntryKind::KeyRotation(k) => self.store.rotate_key(&entry.device_id, k.verifying_key()),
+            EntryKind::SessionEnd(_) => self.store.end_session(&entry.device_id, entry.stream(), &entry.session_id),
+            EntryKind::FirmwareUpdate(f) => self.store.set_firmware_version(&entry.device_id, &f.to_version),
+            EntryKind::Data | EntryKind::TamperAlarm(_) => {}
+        }
+    }
+
+    /// Hash, trust, signature and chain checks shared by `process_entry` and `replay_entry`.
+    /// Returns the entry's validated kind.
+    fn check_entry(&self, entry: &LogEntry) -> Result<EntryKind, VerifyError> {
+        // 0) Wire version and the fields it requires, payload commitment and encoding, attachment
+        //    list, kind and payload schema; an erased payload is covered by its commitment only
+        check_version(entry)?;
+        if entry.is_erased() {
+            entry.commitment_scheme()?;
+            entry.erased()?;
+        } else {
+            entry.check_commitment()?;
+            entry.decoded_payload()?;
+        }
+        entry.check_attachments()?;
+        let kind = kinds::parse(entry)?;
+
         // 1) Hash check
         let computed = compute_entry_hash(entry);
         if computed != entry.entry_hash { return Err(VerifyError::HashMismatch); }
 
-        // 2) Trust lookup
-        let vk = self.trust
-            .get_key(&entry.device_id, entry.key_id.as_deref())
-            .ok_or_else(|| VerifyError::DeviceUnknown(entry.device_id.clone()))?;
+        // 2) Trust lookup; after a key rotation only the announced key is valid
+        let vk = match self.store.rotated_key(&entry.device_id) {
+            Some(k) if k.key_id == entry.key_id => k,
+            Some(k) => return Err(VerifyError::KeyRotated(k.key_id.unwrap_or_default())),
+            None => self.trust
+                .get_key(&entry.device_id, entry.key_id.as_deref())
+                .ok_or_else(|| VerifyError::DeviceUnknown(entry.device_id.clone()))?,
+        };
         if self.trust.is_revoked(&entry.device_id, entry.key_id.as_deref()) {
             return Err(VerifyError::Revoked(entry.device_id.clone()));
         }
@@ -73,23 +369,22 @@ impl Verifier {
         }
 
         // 3) Signature verify
-        match entry.algo.as_str() {
-            "ed25519" => {
-                if vk.key.len() != 32 { return Err(VerifyError::Malformed("ed25519 pubkey length".into())); }
-                let mut pk_bytes = [0u8; 32];
-                pk_bytes.copy_from_slice(&vk.key);
-                let pk = DalekVerifyingKey::from_bytes(&pk_bytes).map_err(|_| VerifyError::Malformed("bad ed25519 pubkey".into()))?;
-                let sig_bytes = B64.decode(entry.signature.as_bytes()).map_err(|_| VerifyError::Malformed("signature base64".into()))?;
-                let sig = Signature::from_slice(&sig_bytes).map_err(|_| VerifyError::Malformed("signature length".into()))?;
-                let msg = cbor_for_sign(entry);
-                pk.verify(&msg, &sig).map_err(|_| VerifyError::InvalidSignature)?;
-            }
-            other => return Err(VerifyError::UnsupportedAlgo(other.to_string())),
-        }
+        verify_signature(&vk, &entry.algo, &entry.signing_input()?, &entry.signature)?;
+
+        // 4) Equivocation: the signature is valid, but another entry already holds this position
+        let conflict = self.store
+            .successor(&entry.device_id, entry.stream(), entry.previous_entry_hash.as_deref())
+            .filter(|h| *h != entry.entry_hash)
+            .or_else(|| {
+                self.store
+                    .entry_at_nonce(&entry.device_id, entry.stream(), &entry.session_id, entry.nonce)
+                    .filter(|h| *h != entry.entry_hash)
+            });
+        if let Some(h) = conflict { return Err(VerifyError::Equivocation(h)); }
 
-        // 4) Chain rules
-    let last_h = self.store.last_hash(&entry.device_id);
-    let last_n = self.store.last_nonce(&entry.device_id, &entry.session_id);
+        // 5) Chain rules
+        let last_h = self.store.last_hash(&entry.device_id, entry.stream());
+        let last_n = self.store.last_nonce(&entry.device_id, entry.stream(), &entry.session_id);
         match (last_h, &entry.previous_entry_hash) {
             (None, None) => { /* first entry OK */ }
             (Some(h), Some(prev)) if h == *prev => { /* OK */ }
@@ -102,31 +397,145 @@ impl Verifier {
                 return Err(VerifyError::NonceNotMonotonic);
             }
         }
+        if self.store.session_ended(&entry.device_id, entry.stream(), &entry.session_id) {
+            return Err(VerifyError::SessionEnded(entry.session_id.clone()));
+        }
+        // Entry counters only move forward; gaps (entries created but never received) are allowed
+        // here and reported by the caller.
+        if let Some(last) = self.store.entry_counter(&entry.device_id, entry.stream()) {
+            if entry.entry_counter.is_none_or(|c| c <= last) {
+                return Err(VerifyError::EntryCounterRegression(last));
+            }
+        }
+        if let EntryKind::FirmwareUpdate(FirmwareUpdate { from_version: Some(from), .. }) = &kind {
+            if let Some(running) = self.store.firmware_version(&entry.device_id).filter(|v| v != from) {
+                return Err(VerifyError::Malformed(format!("firmware update from {from}, but device runs {running}")));
+            }
+        }
+        Ok(kind)
+    }
 
-        // 5) Accept: update chain state and ACK
-    self.store.update(&entry.device_id, &entry.session_id, entry.entry_hash.clone(), entry.nonce);
-        let ack = self.make_ack(entry, now_ts);
-        Ok(ack)
+    /// Boot counter rule: a session keeps the counter it started with, and a new session must start
+    /// above every counter the device used before. Once a device has sent a counter, entries
+    /// without one are treated as a rollback too (e.g. an old firmware image).
+    fn check_boot_counter(&self, entry: &LogEntry) -> Result<(), VerifyError> {
+        let Some(last) = self.store.boot_counter(&entry.device_id) else { return Ok(()) };
+        let ok = match (entry.boot_counter, self.store.session_boot_counter(&entry.device_id, &entry.session_id)) {
+            (Some(c), Some(session)) => c == session,
+            (Some(c), None) => c > last,
+            (None, _) => false,
+        };
+        if ok { Ok(()) } else { Err(VerifyError::BootCounterRollback(last)) }
     }
 
-    /// Construct and sign an ACK for an accepted entry.
-    fn make_ack(&self, entry: &LogEntry, now_ts: i64) -> Ack {
+    /// Report the current chain state of a device stream, signed with the ACK signer.
+    ///
+    /// Lets a device that lost its local state resume chaining: the next entry of the stream
+    /// links to `last_entry_hash` and continues a session at `last_nonce + 1`. Ended sessions are
+    /// left out, since no entry can continue them.
+    pub fn chain_head(&self, device_id: &str, stream_id: &str, now_ts: i64) -> ChainHead {
+        let mut sessions: Vec<SessionHead> = self.store
+            .session_nonces(device_id, stream_id)
+            .into_iter()
+            .filter(|(session_id, _)| !self.store.session_ended(device_id, stream_id, session_id))
+            .map(|(session_id, last_nonce)| SessionHead { session_id, last_nonce })
+            .collect();
+        sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+        let mut head = ChainHead {
+            device_id: device_id.to_string(),
+            stream_id: stream_id.to_string(),
+            last_entry_hash: self.store.last_hash(device_id, stream_id),
+            sessions,
+            timestamp: now_ts,
+            server_signer_id: self.ack_signer.signer_id().into(),
+            server_signature: String::new(),
+        };
+        let msg = cbor_for_head_sign(&head);
+        head.server_signature = B64.encode(self.ack_signer.sign(&msg));
+        head
+    }
+
+    /// Construct and sign an ACK for an accepted (possibly quarantined) entry.
+    fn make_ack(&self, entry: &LogEntry, status: &str, warnings: Vec<String>, now_ts: i64, log_index: u64, device_seq: u64) -> Ack {
         let server_signer_id = self.ack_signer.signer_id();
         let mut ack = Ack {
             entry_id: entry.entry_hash.clone(),
             new_entry_hash: entry.entry_hash.clone(),
-            status: "accepted".into(),
+            status: status.into(),
             timestamp: now_ts,
             server_signer_id: server_signer_id.into(),
             server_signature: String::new(),
+            log_index: Some(log_index),
+            device_seq: Some(device_seq),
+            warnings,
+            signature_format: entry.signature_format.clone(),
         };
-        let msg = cbor_for_ack_sign(&ack);
+        let msg = ack.signing_input().expect("signature_format checked with the entry signature");
         let sig = self.ack_signer.sign(&msg);
         ack.server_signature = B64.encode(sig);
         ack
     }
 }
 
+/// Check `entry.version` is supported and the optional fields match it: each is required from
+/// the version that introduced it and forbidden before.
+fn check_version(entry: &LogEntry) -> Result<(), VerifyError> {
+    let v = entry.version;
+    if v == 0 || v > LATEST_ENTRY_VERSION {
+        return Err(VerifyError::Malformed(format!("unsupported version {v}")));
+    }
+    for (name, since, present) in [
+        ("boot_counter", 2, entry.boot_counter.is_some()),
+        ("entry_counter", 3, entry.entry_counter.is_some()),
+        ("stream_id", 4, entry.stream_id.is_some()),
+        ("kind", 5, entry.kind.is_some()),
+        ("payload_encoding", 6, entry.payload_encoding.is_some()),
+        ("payload_commitment", 8, entry.payload_commitment.is_some()),
+        ("payload_commitment_scheme", 9, entry.payload_commitment_scheme.is_some()),
+        ("attachments", 10, entry.attachments.is_some()),
+        ("canon", 11, entry.canon.is_some()),
+    ] {
+        match (v >= since, present) {
+            (true, false) => return Err(VerifyError::Malformed(format!("version {v} requires {name}"))),
+            (false, true) => return Err(VerifyError::Malformed(format!("{name} requires version {since}"))),
+            _ => {}
+        }
+    }
+    match (v >= 8, entry.payload_salt.is_some()) {
+        (true, false) if !entry.payload.is_empty() => return Err(VerifyError::Malformed(format!("version {v} requires payload_salt"))),
+        (false, true) => return Err(VerifyError::Malformed("payload_salt requires version 8".into())),
+        _ => {}
+    }
+    if entry.payload_encoding.as_deref() == Some("hpke") && v < 7 {
+        return Err(VerifyError::Malformed("payload_encoding hpke requires version 7".into()));
+    }
+    match entry.canon() {
+        CANON_CBOR => {}
+        CANON_JCS => jcs::check_integers(&jcs_object(entry, true)).map_err(|e| VerifyError::Malformed(format!("canon jcs: {e}")))?,
+        other => return Err(VerifyError::Malformed(format!("unknown canon {other}"))),
+    }
+    if entry.stream_id.as_deref() == Some("") {
+        return Err(VerifyError::Malformed("empty stream_id".into()));
+    }
+    Ok(())
+}
+
+/// Verify a base64 `signature` over `msg` with `vk`, using `algo`.
+pub fn verify_signature(vk: &VerifyingKey, algo: &str, msg: &[u8], signature: &str) -> Result<(), VerifyError> {
+    match algo {
+        "ed25519" => {
+            if vk.key.len() != 32 { return Err(VerifyError::Malformed("ed25519 pubkey length".into())); }
+            let mut pk_bytes = [0u8; 32];
+            pk_bytes.copy_from_slice(&vk.key);
+            let pk = DalekVerifyingKey::from_bytes(&pk_bytes).map_err(|_| VerifyError::Malformed("bad ed25519 pubkey".into()))?;
+            let sig_bytes = B64.decode(signature.as_bytes()).map_err(|_| VerifyError::Malformed("signature base64".into()))?;
+            let sig = Signature::from_slice(&sig_bytes).map_err(|_| VerifyError::Malformed("signature length".into()))?;
+            pk.verify(msg, &sig).map_err(|_| VerifyError::InvalidSignature)
+        }
+        other => Err(VerifyError::UnsupportedAlgo(other.to_string())),
+    }
+}
+
 /// Ed25519 implementation of `AckSigner` suitable for tests and demos.
 pub struct Ed25519AckSigner {
     id: &'static str,
@@ -138,6 +547,11 @@ impl Ed25519AckSigner {
     pub fn from_secret_key(id: &'static str, secret_key: [u8; 32]) -> Self {
         Self { id, sk: SigningKey::from_bytes(&secret_key) }
     }
+
+    /// The public key, to verify what this signer signed.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey { algo: "ed25519".into(), key: self.sk.verifying_key().to_bytes().to_vec(), key_id: Some(self.id.into()) }
+    }
 }
 
 impl AckSigner for Ed25519AckSigner {
@@ -0,0 +1,236 @@
+//! Truncation alerts: entry counter gaps and missing heartbeats, appended to a JSONL file.
+//!
+//! A device configured with a heartbeat must deliver an entry at least every `interval_secs`
+//! (plus `grace_secs`); silence past that raises `missing_heartbeat`, and the next entry raises
+//! `heartbeat_resumed`, which closes the missing period. Version 3 entries carry a cumulative
+//! `entry_counter`; a jump reveals entries the device created that never arrived.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use taric_core::LogEntry;
+
+use crate::api::AppState;
+use crate::index::{DEFAULT_LIMIT, MAX_LIMIT};
+use crate::records::LogLine;
+
+/// Path of the alert log; override with `TARIC_ALERTS_PATH`.
+pub fn alerts_path() -> String {
+    std::env::var("TARIC_ALERTS_PATH").unwrap_or_else(|_| "/fixtures/alerts.jsonl".to_string())
+}
+
+/// Path of the per-device heartbeat configuration; override with `TARIC_HEARTBEATS_PATH`.
+pub fn heartbeats_path() -> String {
+    std::env::var("TARIC_HEARTBEATS_PATH").unwrap_or_else(|_| "/fixtures/heartbeats.json".to_string())
+}
+
+/// How often heartbeats are checked; override with `TARIC_HEARTBEAT_CHECK_SECS`.
+pub fn heartbeat_check_interval() -> Duration {
+    let secs = std::env::var("TARIC_HEARTBEAT_CHECK_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(10);
+    Duration::from_secs(secs)
+}
+
+/// Expected heartbeat of a device.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    /// Maximum time between two entries.
+    pub interval_secs: u64,
+    /// Extra time allowed before alerting.
+    #[serde(default)]
+    pub grace_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertKind {
+    /// The entry's counter skipped `missing` entries since the last accepted one.
+    CounterGap { entry_hash: String, last_counter: u64, entry_counter: u64, missing: u64 },
+    /// No entry arrived by `expected_by`; `last_seen` is when the last one did.
+    MissingHeartbeat { last_seen: Option<i64>, expected_by: i64 },
+    /// First entry after a missing heartbeat: the missing period ran from `silent_since` to `detected_at`.
+    HeartbeatResumed { silent_since: Option<i64> },
+}
+
+impl AlertKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlertKind::CounterGap { .. } => "counter_gap",
+            AlertKind::MissingHeartbeat { .. } => "missing_heartbeat",
+            AlertKind::HeartbeatResumed { .. } => "heartbeat_resumed",
+        }
+    }
+}
+
+/// One line of the alert log; `id` is its position.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub id: u64,
+    pub device_id: String,
+    pub detected_at: i64,
+    #[serde(flatten)]
+    pub kind: AlertKind,
+}
+
+/// Filters and pagination for `GET /alerts`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AlertQuery {
+    pub device_id: Option<String>,
+    /// `counter_gap`, `missing_heartbeat` or `heartbeat_resumed`.
+    pub kind: Option<String>,
+    /// Return alerts with `id >= cursor`; use the `next_cursor` of the previous page.
+    pub cursor: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// Heartbeat state of one device, as returned by `GET /devices/:id/heartbeat`.
+#[derive(Debug, Serialize)]
+pub struct HeartbeatStatus {
+    pub device_id: String,
+    /// Absent when the device has no expected heartbeat.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<HeartbeatConfig>,
+    /// When the server last accepted an entry of the device.
+    pub last_seen: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_by: Option<i64>,
+    /// A `missing_heartbeat` alert is open.
+    pub missing: bool,
+}
+
+pub struct AlertLog {
+    path: String,
+    heartbeats_path: String,
+    items: Vec<Alert>,
+    heartbeats: BTreeMap<String, HeartbeatConfig>,
+    last_seen: HashMap<String, i64>,
+    /// Devices with an open `missing_heartbeat`, with the `last_seen` it reported.
+    silent: HashMap<String, Option<i64>>,
+    /// Devices never seen are expected a heartbeat after this.
+    started_at: i64,
+}
+
+impl AlertLog {
+    /// Load alerts and heartbeat configuration, and take each device's last accepted entry time
+    /// from the record log `lines`. Missing files mean no alerts and no heartbeats.
+    pub fn load(path: String, heartbeats_path: String, lines: &[LogLine], now: i64) -> Result<Self, String> {
+        let heartbeats = match fs::read_to_string(&heartbeats_path) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| format!("{heartbeats_path}: {e}"))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("read {heartbeats_path}: {e}")),
+        };
+        let s = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("read {path}: {e}")),
+        };
+        let mut items = Vec::new();
+        let mut silent = HashMap::new();
+        for (i, line) in s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let a: Alert = serde_json::from_str(line).map_err(|e| format!("{path}:{}: unparseable alert: {e}", i + 1))?;
+            match &a.kind {
+                AlertKind::MissingHeartbeat { last_seen, .. } => { silent.insert(a.device_id.clone(), *last_seen); }
+                AlertKind::HeartbeatResumed { .. } => { silent.remove(&a.device_id); }
+                AlertKind::CounterGap { .. } => {}
+            }
+            items.push(a);
+        }
+        let mut last_seen = HashMap::new();
+        for rec in lines.iter().filter_map(|l| l.parsed.as_ref().ok()).filter(|r| r.is_accepted()) {
+            last_seen.insert(rec.entry.device_id.clone(), rec.recorded_at);
+        }
+        Ok(Self { path, heartbeats_path, items, heartbeats, last_seen, silent, started_at: now })
+    }
+
+    fn raise(&mut self, device_id: &str, now: i64, kind: AlertKind) -> io::Result<()> {
+        let alert = Alert { id: self.items.len() as u64, device_id: device_id.to_string(), detected_at: now, kind };
+        let line = serde_json::to_string(&alert).expect("alert serializes");
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(f, "{line}")?;
+        eprintln!("Alert: {line}");
+        self.items.push(alert);
+        Ok(())
+    }
+
+    /// Note an accepted entry. `last_counter` is the device's entry counter before it.
+    pub fn observe(&mut self, e: &LogEntry, last_counter: Option<u64>, now: i64) -> io::Result<()> {
+        self.last_seen.insert(e.device_id.clone(), now);
+        if let Some(silent_since) = self.silent.remove(&e.device_id) {
+            self.raise(&e.device_id, now, AlertKind::HeartbeatResumed { silent_since })?;
+        }
+        if let (Some(last), Some(c)) = (last_counter, e.entry_counter) {
+            if c > last + 1 {
+                let kind = AlertKind::CounterGap { entry_hash: e.entry_hash.clone(), last_counter: last, entry_counter: c, missing: c - last - 1 };
+                self.raise(&e.device_id, now, kind)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// When the next entry of a device is due, if it has an expected heartbeat.
+    fn expected_by(&self, device_id: &str) -> Option<i64> {
+        let cfg = self.heartbeats.get(device_id)?;
+        // Time the server was down does not count against the device.
+        let base = self.last_seen.get(device_id).map_or(self.started_at, |seen| (*seen).max(self.started_at));
+        Some(base + (cfg.interval_secs + cfg.grace_secs) as i64)
+    }
+
+    /// Raise `missing_heartbeat` for every device past its deadline without an open alert.
+    pub fn check_heartbeats(&mut self, now: i64) -> io::Result<()> {
+        let overdue: Vec<(String, i64)> = self.heartbeats.keys()
+            .filter(|d| !self.silent.contains_key(*d))
+            .filter_map(|d| self.expected_by(d).filter(|by| now > *by).map(|by| (d.clone(), by)))
+            .collect();
+        for (device_id, expected_by) in overdue {
+            let last_seen = self.last_seen.get(&device_id).copied();
+            self.raise(&device_id, now, AlertKind::MissingHeartbeat { last_seen, expected_by })?;
+            self.silent.insert(device_id, last_seen);
+        }
+        Ok(())
+    }
+
+    /// Set (or with `None` remove) the expected heartbeat of a device and persist the configuration.
+    pub fn set_heartbeat(&mut self, device_id: &str, cfg: Option<HeartbeatConfig>) -> io::Result<()> {
+        match cfg {
+            Some(cfg) => { self.heartbeats.insert(device_id.to_string(), cfg); }
+            None => { self.heartbeats.remove(device_id); }
+        }
+        fs::write(&self.heartbeats_path, serde_json::to_string_pretty(&self.heartbeats).expect("heartbeats serialize"))
+    }
+
+    pub fn heartbeat_status(&self, device_id: &str) -> HeartbeatStatus {
+        HeartbeatStatus {
+            device_id: device_id.to_string(),
+            config: self.heartbeats.get(device_id).copied(),
+            last_seen: self.last_seen.get(device_id).copied(),
+            expected_by: self.expected_by(device_id),
+            missing: self.silent.contains_key(device_id),
+        }
+    }
+
+    /// One page of alerts matching `q`, oldest first, and the cursor of the next page if any.
+    pub fn page(&self, q: &AlertQuery) -> (Vec<Alert>, Option<u64>) {
+        let limit = q.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let mut matching = self.items.iter()
+            .skip(q.cursor.unwrap_or(0) as usize)
+            .filter(|a| q.device_id.as_deref().is_none_or(|d| d == a.device_id))
+            .filter(|a| q.kind.as_deref().is_none_or(|k| k == a.kind.name()));
+        let alerts: Vec<Alert> = matching.by_ref().take(limit).cloned().collect();
+        let next = matching.next().map(|a| a.id);
+        (alerts, next)
+    }
+}
+
+/// Check heartbeats every `heartbeat_check_interval()`.
+pub async fn run(state: AppState) {
+    let mut ticker = tokio::time::interval(heartbeat_check_interval());
+    loop {
+        ticker.tick().await;
+        let mut alerts = state.alerts.lock().unwrap();
+        if let Err(e) = alerts.check_heartbeats(chrono::Utc::now().timestamp()) {
+            eprintln!("Failed to persist alert: {e}");
+        }
+    }
+}
@@ -0,0 +1,695 @@
+//! HTTP handlers.
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use std::convert::Infallible;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query as QueryParams, State};
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt as _};
+use taric_core::cose::COSE_SIGN1;
+use taric_core::{Ack, AckSigner, Attachment, ChainHead, Checkpoint, ConsistencyProof, Cosignature, EquivocationProof, InclusionProof, ChainStore, DeviceStatus, DeviceStatusStore, DeviceTrust, Disclosure, LogEntry, PayloadSchema, Tombstone, Verifier, VerifyError, VerifyingKey, DEFAULT_STREAM};
+
+use crate::alerts::{Alert, AlertLog, AlertQuery, HeartbeatConfig, HeartbeatStatus};
+use crate::blobs::{BlobError, BlobStore};
+use crate::checkpoints::{CheckpointLog, CosignError, HistoryQuery};
+use crate::equivocations::{EquivocationLog, EquivocationQuery};
+use crate::index::{Query, MAX_LIMIT};
+use crate::tombstones::TombstoneLog;
+use crate::records::{RecordLog, SeqRecord};
+use crate::status::{rollback_policy, signature_failure_limit};
+use crate::stream::{self, StreamParams};
+use crate::wire::{self, WireAck, WireEntry};
+use crate::trust::{load_class_schema, schema_policy, DeviceFixture, FixtureTrust, DEVICE_FIXTURE_PATH};
+
+/// Shared server state.
+#[derive(Clone)]
+pub struct AppState {
+    pub store: Arc<dyn ChainStore>,
+    pub ack_signer: Arc<dyn AckSigner>,
+    /// Held across verification and append so records land in the order chain state advanced.
+    pub log: Arc<Mutex<RecordLog>>,
+    /// Every appended record, for live streams; sent while `log` is held.
+    pub events: broadcast::Sender<Arc<SeqRecord>>,
+    /// Signed tree heads; lock after `log` when both are needed.
+    pub checkpoints: Arc<Mutex<CheckpointLog>>,
+    /// Proofs of conflicting device entries; lock after `log`.
+    pub equivocations: Arc<Mutex<EquivocationLog>>,
+    /// Active, quarantined or retired, per device.
+    pub device_status: Arc<dyn DeviceStatusStore>,
+    /// Bearer token of the admin endpoints; `None` disables them.
+    pub admin_token: Option<Arc<str>>,
+    /// Counter gap and heartbeat alerts; lock after `log`.
+    pub alerts: Arc<Mutex<AlertLog>>,
+    /// Signed records of erased payloads; lock after `log`.
+    pub tombstones: Arc<Mutex<TombstoneLog>>,
+    /// Attachment blobs by content hash; lock after `log`.
+    pub blobs: Arc<Mutex<BlobStore>>,
+}
+
+#[derive(Clone)]
+struct StaticTrust { key: VerifyingKey, schema: Option<Arc<PayloadSchema>> }
+impl DeviceTrust for StaticTrust {
+    fn get_key(&self, _device_id: &str, _key_id: Option<&str>) -> Option<VerifyingKey> { Some(self.key.clone()) }
+    fn payload_schema(&self, _device_id: &str) -> Option<Arc<PayloadSchema>> { self.schema.clone() }
+}
+
+/// `POST /entries`: verify and chain an entry, record it, and return an ACK. The entry and ACK are
+/// JSON or CBOR envelopes, see `wire`.
+pub async fn post_entry(State(state): State<AppState>, WireEntry { entry: e, reply }: WireEntry) -> WireAck {
+    // Reload device fixture each request so the runner can provide key dynamically
+    let mut schema = None;
+    let vk = if let Ok(s) = fs::read_to_string(DEVICE_FIXTURE_PATH) {
+        let f: DeviceFixture = serde_json::from_str(&s).expect("invalid device fixture JSON");
+        // ensure request device matches fixture device
+        if f.device_id != e.device_id {
+            return WireAck(Ack {
+                entry_id: e.entry_hash.clone(), new_entry_hash: e.entry_hash.clone(),
+                status: format!("error:device_unknown:{}", e.device_id),
+                timestamp: chrono::Utc::now().timestamp(),
+                server_signer_id: "server-key-1".into(), server_signature: String::new(),
+                log_index: None, device_seq: None, warnings: Vec::new(), signature_format: None,
+            }, reply);
+        }
+        if let Some(class) = &f.class {
+            match load_class_schema(class, f.schema_version) {
+                Ok(s) => schema = Some(Arc::new(s)),
+                Err(err) => eprintln!("No payload schema for {}: {err}", f.device_id),
+            }
+        }
+        let key = base64::engine::general_purpose::STANDARD.decode(f.pubkey_base64.as_bytes()).expect("invalid pubkey b64");
+        VerifyingKey { algo: f.algo, key, key_id: Some(f.key_id) }
+    } else {
+        VerifyingKey { algo: "ed25519".to_string(), key: vec![1u8; 32], key_id: Some("001-key1-1".into()) }
+    };
+    let trust = Arc::new(StaticTrust { key: vk, schema });
+    let mut verifier = Verifier::new(trust, state.store.clone(), state.ack_signer.clone())
+        .with_device_status(state.device_status.clone(), signature_failure_limit())
+        .with_rollback_policy(rollback_policy());
+    if let Some(policy) = schema_policy() { verifier = verifier.with_schema_policy(policy); }
+
+    let mut log = state.log.lock().unwrap();
+    let last_counter = state.store.entry_counter(&e.device_id, e.stream());
+    let ack = match verifier.process_entry(&e, chrono::Utc::now().timestamp()) {
+        Ok(ack) => ack,
+        Err(err) => {
+            if let VerifyError::Equivocation(accepted_hash) = &err {
+                record_equivocation(&state, &log, accepted_hash, &e);
+            }
+            let mut ack = Ack {
+                entry_id: e.entry_hash.clone(),
+                new_entry_hash: e.entry_hash.clone(),
+                status: format!("error:{err}"),
+                timestamp: chrono::Utc::now().timestamp(),
+                server_signer_id: state.ack_signer.signer_id().to_string(),
+                server_signature: String::new(),
+                log_index: None,
+                device_seq: None,
+                warnings: Vec::new(),
+                // Unknown formats fail verification; their ACK is signed natively.
+                signature_format: e.signature_format.clone().filter(|f| f == COSE_SIGN1),
+            };
+            let msg = ack.signing_input().expect("native or COSE_Sign1");
+            let sig = state.ack_signer.sign(&msg);
+            ack.server_signature = base64::engine::general_purpose::STANDARD.encode(sig);
+            ack
+        }
+    };
+    if ack.log_index.is_some() {
+        state.blobs.lock().unwrap().attach(&e);
+        let mut alerts = state.alerts.lock().unwrap();
+        if let Err(err) = alerts.observe(&e, last_counter, chrono::Utc::now().timestamp()) {
+            eprintln!("Failed to persist alert for {}: {err}", e.entry_hash);
+        }
+    }
+    match log.append(&e, &ack) {
+        // No receivers is fine: nobody is streaming.
+        Ok(rec) => { let _ = state.events.send(Arc::new(rec)); }
+        Err(err) => eprintln!("Failed to append record for {}: {err}", e.entry_hash),
+    }
+    WireAck(ack, reply)
+}
+
+/// Store a signed proof that `conflicting` and the accepted entry `accepted_hash` conflict,
+/// flagging the device. Called with the log lock held.
+fn record_equivocation(state: &AppState, log: &RecordLog, accepted_hash: &str, conflicting: &LogEntry) {
+    let mut equivocations = state.equivocations.lock().unwrap();
+    if equivocations.contains(&conflicting.entry_hash) { return; }
+    let accepted = match log.accepted_entry(accepted_hash) {
+        Ok(Some(entry)) => entry,
+        Ok(None) => { eprintln!("Equivocation by {}: accepted entry {accepted_hash} not in the record log", conflicting.device_id); return; }
+        Err(err) => { eprintln!("Equivocation by {}: failed to read entry {accepted_hash}: {err}", conflicting.device_id); return; }
+    };
+    eprintln!("Equivocation by {}: {} conflicts with accepted entry {accepted_hash}; device flagged", conflicting.device_id, conflicting.entry_hash);
+    let proof = EquivocationProof::sign(state.ack_signer.as_ref(), accepted, conflicting.clone(), chrono::Utc::now().timestamp());
+    if let Err(err) = equivocations.append(proof) {
+        eprintln!("Failed to persist equivocation proof for {}: {err}", conflicting.entry_hash);
+    }
+}
+
+/// One page of query results.
+#[derive(Serialize)]
+pub struct Page {
+    pub records: Vec<SeqRecord>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// One page of `GET /entries` results.
+fn search_page(state: &AppState, q: &Query) -> Result<Page, (StatusCode, String)> {
+    let (mut records, next_cursor) = state.log.lock().unwrap().search(q).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if q.decode { records = records.into_iter().map(SeqRecord::decoded).collect(); }
+    let blobs = state.blobs.lock().unwrap();
+    let records = records.into_iter().map(|rec| rec.with_attachment_status(&blobs, false)).collect();
+    Ok(Page { records, next_cursor })
+}
+
+/// `GET /entries`: search stored records with filters. With `cursor` or `limit`, one page
+/// (`Page`); without either, every matching record as a JSON array, the response before
+/// pagination was added.
+pub async fn search_entries(State(state): State<AppState>, QueryParams(mut q): QueryParams<Query>) -> Result<Response, (StatusCode, String)> {
+    if q.cursor.is_some() || q.limit.is_some() {
+        return search_page(&state, &q).map(|page| Json(page).into_response());
+    }
+    // One page per lock, so appends are not held up while a large log is read.
+    q.limit = Some(MAX_LIMIT);
+    let mut records = Vec::new();
+    loop {
+        let page = search_page(&state, &q)?;
+        records.extend(page.records);
+        match page.next_cursor {
+            Some(next) => q.cursor = Some(next),
+            None => return Ok(Json(records).into_response()),
+        }
+    }
+}
+
+/// `GET /entries/export`: every record matching the `GET /entries` filters from `cursor` on, as
+/// newline-delimited JSON with `payload_decoded` set, and `attachment_status` after re-hashing
+/// the stored blobs. `limit` is ignored. With `disclose`, payloads are withheld and the named
+/// fields revealed with proofs instead; records before wire version 8 are left out.
+pub async fn export_entries(State(state): State<AppState>, QueryParams(mut q): QueryParams<Query>) -> impl IntoResponse {
+    let (tx, rx) = mpsc::channel(4);
+    q.limit = Some(MAX_LIMIT);
+    let disclose = q.disclose.clone().map(|names| names.split(',').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect::<Vec<_>>());
+    tokio::spawn(async move {
+        loop {
+            // One page per lock, so appends are not held up by a slow reader.
+            let page = state.log.lock().unwrap().search(&q);
+            let (records, next) = match page {
+                Ok(page) => page,
+                Err(e) => {
+                    eprintln!("Export failed: {e}");
+                    return;
+                }
+            };
+            let mut chunk = String::new();
+            let records: Vec<_> = {
+                let blobs = state.blobs.lock().unwrap();
+                records.into_iter().map(|rec| rec.with_attachment_status(&blobs, true)).collect()
+            };
+            for rec in records {
+                let rec = match &disclose {
+                    Some(names) => match rec.disclosed(&names.iter().map(String::as_str).collect::<Vec<_>>()) {
+                        Some(rec) => rec,
+                        None => continue,
+                    },
+                    None => rec.decoded(),
+                };
+                chunk.push_str(&serde_json::to_string(&rec).expect("record serializes"));
+                chunk.push('\n');
+            }
+            if tx.send(Ok::<_, Infallible>(chunk)).await.is_err() { return; }
+            match next {
+                Some(cursor) => q.cursor = Some(cursor),
+                None => return,
+            }
+        }
+    });
+    ([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(ReceiverStream::new(rx)))
+}
+
+/// `GET /devices/:id/entries`: `GET /entries` restricted to one device.
+pub async fn device_entries(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    QueryParams(mut q): QueryParams<Query>,
+) -> Result<Response, (StatusCode, String)> {
+    q.device_id = Some(device_id);
+    search_entries(State(state), QueryParams(q)).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeadQuery {
+    #[serde(default = "default_stream")]
+    pub stream_id: String,
+}
+
+fn default_stream() -> String { DEFAULT_STREAM.to_string() }
+
+/// `GET /devices/:id/head`: signed chain state of one stream so a device can resume chaining after losing it.
+pub async fn device_head(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    QueryParams(q): QueryParams<HeadQuery>,
+) -> Json<ChainHead> {
+    // Chain heads never consult device trust.
+    let verifier = Verifier::new(Arc::new(FixtureTrust::default()), state.store.clone(), state.ack_signer.clone());
+    // Hold the log lock so the hash and nonces come from the same point in the chain.
+    let _log = state.log.lock().unwrap();
+    Json(verifier.chain_head(&device_id, &q.stream_id, chrono::Utc::now().timestamp()))
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamList {
+    pub device_id: String,
+    pub streams: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FirmwareBody {
+    pub device_id: String,
+    /// From the device's latest accepted `firmware_update` entry.
+    pub firmware_version: Option<String>,
+}
+
+/// `GET /devices/:id/firmware`: firmware version the device last reported.
+pub async fn device_firmware(State(state): State<AppState>, Path(device_id): Path<String>) -> Json<FirmwareBody> {
+    let firmware_version = state.store.firmware_version(&device_id);
+    Json(FirmwareBody { device_id, firmware_version })
+}
+
+/// `GET /devices/:id/streams`: streams of a device with at least one accepted entry.
+pub async fn device_streams(State(state): State<AppState>, Path(device_id): Path<String>) -> Json<StreamList> {
+    let streams = state.store.streams(&device_id);
+    Json(StreamList { device_id, streams })
+}
+
+/// `GET /entries/stream`: Server-Sent Events, one `record` event per record with `id` set to its `seq`.
+/// A reconnecting client's `Last-Event-ID` takes precedence over `cursor`.
+pub async fn stream_sse(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    QueryParams(mut params): QueryParams<StreamParams>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    if let Some(last) = headers.get("last-event-id").and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok()) {
+        params.cursor = Some(last + 1);
+    }
+    let events = ReceiverStream::new(stream::subscribe(state, params)).map(|rec| {
+        let data = serde_json::to_string(&rec).expect("record serializes");
+        Ok(Event::default().event("record").id(rec.seq.to_string()).data(data))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// `GET /entries/ws`: WebSocket variant of `/entries/stream`; each text message is one record.
+pub async fn stream_ws(
+    State(state): State<AppState>,
+    QueryParams(params): QueryParams<StreamParams>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| forward_ws(socket, state, params))
+}
+
+async fn forward_ws(mut socket: WebSocket, state: AppState, params: StreamParams) {
+    let mut records = stream::subscribe(state, params);
+    loop {
+        tokio::select! {
+            rec = records.recv() => {
+                let Some(rec) = rec else { return };
+                let text = serde_json::to_string(&rec).expect("record serializes");
+                if socket.send(Message::Text(text)).await.is_err() { return; }
+            }
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Size and root of the Merkle tree over accepted entries.
+#[derive(Serialize)]
+pub struct LogRoot {
+    pub tree_size: u64,
+    /// Hex-encoded RFC 6962 root hash.
+    pub root_hash: String,
+}
+
+/// `GET /log`: current size and root hash of the global log (unsigned).
+pub async fn log_root(State(state): State<AppState>) -> Json<LogRoot> {
+    let log = state.log.lock().unwrap();
+    Json(LogRoot { tree_size: log.tree().len(), root_hash: hex::encode(log.tree().root()) })
+}
+
+/// One page of the checkpoint history.
+#[derive(Serialize)]
+pub struct CheckpointPage {
+    pub checkpoints: Vec<Checkpoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// `GET /checkpoints`: checkpoint history, oldest first.
+pub async fn list_checkpoints(State(state): State<AppState>, QueryParams(q): QueryParams<HistoryQuery>) -> Json<CheckpointPage> {
+    let (checkpoints, next_cursor) = state.checkpoints.lock().unwrap().page(&q);
+    Json(CheckpointPage { checkpoints, next_cursor })
+}
+
+/// `GET /checkpoints/latest`: most recent checkpoint.
+pub async fn latest_checkpoint(State(state): State<AppState>) -> Result<Json<Checkpoint>, StatusCode> {
+    state.checkpoints.lock().unwrap().latest().cloned().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// `GET /checkpoints/:tree_size`: the checkpoint signed at that tree size.
+pub async fn checkpoint_at(State(state): State<AppState>, Path(tree_size): Path<u64>) -> Result<Json<Checkpoint>, StatusCode> {
+    state.checkpoints.lock().unwrap().get(tree_size).cloned().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// `POST /checkpoints/:tree_size/cosignatures`: attach a witness co-signature to a checkpoint.
+/// Returns the checkpoint with all its co-signatures.
+pub async fn add_cosignature(
+    State(state): State<AppState>,
+    Path(tree_size): Path<u64>,
+    Json(cosignature): Json<Cosignature>,
+) -> Result<Json<Checkpoint>, (StatusCode, String)> {
+    let mut checkpoints = state.checkpoints.lock().unwrap();
+    checkpoints.add_cosignature(tree_size, cosignature).map(Json).map_err(|e| {
+        let code = match e {
+            CosignError::UnknownCheckpoint => StatusCode::NOT_FOUND,
+            CosignError::UnknownWitness(_) => StatusCode::FORBIDDEN,
+            CosignError::Invalid(_) => StatusCode::BAD_REQUEST,
+            CosignError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (code, e.to_string())
+    })
+}
+
+#[derive(Deserialize)]
+pub struct InclusionParams {
+    pub entry_hash: String,
+    /// Size of the checkpoint to prove against; defaults to the latest checkpoint.
+    pub tree_size: Option<u64>,
+}
+
+/// Inclusion proof together with the checkpoint whose root it leads to.
+#[derive(Serialize)]
+pub struct InclusionResponse {
+    pub checkpoint: Checkpoint,
+    pub proof: InclusionProof,
+}
+
+/// `GET /proofs/inclusion`: prove an accepted entry is in the log committed to by a checkpoint.
+pub async fn inclusion_proof(
+    State(state): State<AppState>,
+    QueryParams(p): QueryParams<InclusionParams>,
+) -> Result<Json<InclusionResponse>, (StatusCode, String)> {
+    let log = state.log.lock().unwrap();
+    let checkpoints = state.checkpoints.lock().unwrap();
+    let checkpoint = match p.tree_size {
+        Some(size) => checkpoints.get(size),
+        None => checkpoints.latest(),
+    }
+    .cloned()
+    .ok_or((StatusCode::NOT_FOUND, "no checkpoint at that tree size".to_string()))?;
+    let index = log.log_index_of(&p.entry_hash).ok_or((StatusCode::NOT_FOUND, "no accepted entry with that hash".to_string()))?;
+    let path = log.tree().inclusion_proof(index, checkpoint.tree_size)
+        .ok_or((StatusCode::NOT_FOUND, format!("entry has log_index {index}, not covered by checkpoint")))?;
+    let proof = InclusionProof::new(index, checkpoint.tree_size, &path);
+    Ok(Json(InclusionResponse { checkpoint, proof }))
+}
+
+#[derive(Deserialize)]
+pub struct ConsistencyParams {
+    pub first: u64,
+    /// Defaults to the latest checkpoint.
+    pub second: Option<u64>,
+}
+
+/// Consistency proof together with the two checkpoints it links.
+#[derive(Serialize)]
+pub struct ConsistencyResponse {
+    pub first: Checkpoint,
+    pub second: Checkpoint,
+    pub proof: ConsistencyProof,
+}
+
+/// `GET /proofs/consistency`: prove the log at checkpoint `first` is a prefix of the log at `second`.
+pub async fn consistency_proof(
+    State(state): State<AppState>,
+    QueryParams(p): QueryParams<ConsistencyParams>,
+) -> Result<Json<ConsistencyResponse>, (StatusCode, String)> {
+    let log = state.log.lock().unwrap();
+    let checkpoints = state.checkpoints.lock().unwrap();
+    let not_found = |what: &str| (StatusCode::NOT_FOUND, format!("no checkpoint at {what} tree size"));
+    let first = checkpoints.get(p.first).cloned().ok_or_else(|| not_found("first"))?;
+    let second = match p.second {
+        Some(size) => checkpoints.get(size),
+        None => checkpoints.latest(),
+    }
+    .cloned()
+    .ok_or_else(|| not_found("second"))?;
+    let path = log.tree().consistency_proof(first.tree_size, second.tree_size)
+        .ok_or((StatusCode::BAD_REQUEST, "first checkpoint is newer than second".to_string()))?;
+    let proof = ConsistencyProof::new(first.tree_size, second.tree_size, &path);
+    Ok(Json(ConsistencyResponse { first, second, proof }))
+}
+
+#[derive(Deserialize)]
+pub struct EraseRequest {
+    /// Why the payload is erased, e.g. a request reference; recorded in the tombstone.
+    pub reason: String,
+}
+
+/// `POST /entries/:hash/erase`: erase the payload of an accepted version 8 `data` entry from
+/// every record and equivocation proof of it, and return the signed tombstone (admin). Erasing
+/// again returns the same tombstone.
+pub async fn erase_entry(
+    State(state): State<AppState>,
+    Path(entry_hash): Path<String>,
+    headers: HeaderMap,
+    Json(req): Json<EraseRequest>,
+) -> Result<Json<Tombstone>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let mut log = state.log.lock().unwrap();
+    let mut tombstones = state.tombstones.lock().unwrap();
+    let internal = |e: std::io::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let tombstone = match tombstones.get(&entry_hash) {
+        Some(t) => t.clone(),
+        None => {
+            let entry = log.accepted_entry(&entry_hash)
+                .map_err(internal)?
+                .ok_or((StatusCode::NOT_FOUND, "no accepted entry with that hash".to_string()))?;
+            let t = Tombstone::sign(state.ack_signer.as_ref(), &entry, &req.reason, chrono::Utc::now().timestamp())
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+            // The tombstone is stored first, so an interrupted erasure is completed by a retry.
+            tombstones.append(t.clone()).map_err(internal)?;
+            t
+        }
+    };
+    log.erase(&entry_hash).map_err(internal)?;
+    state.equivocations.lock().unwrap().erase(&entry_hash).map_err(internal)?;
+    Ok(Json(tombstone))
+}
+
+#[derive(Deserialize)]
+pub struct DisclosureQuery {
+    /// Comma-separated names of the payload fields to reveal.
+    pub fields: String,
+}
+
+/// `GET /entries/:hash/disclosure?fields=a,b`: the accepted entry without its payload, and the
+/// named payload fields with their proofs. The entry must use the `fields` commitment scheme.
+pub async fn entry_disclosure(
+    State(state): State<AppState>,
+    Path(entry_hash): Path<String>,
+    QueryParams(q): QueryParams<DisclosureQuery>,
+) -> Result<Json<Disclosure>, (StatusCode, String)> {
+    let entry = state.log.lock().unwrap()
+        .accepted_entry(&entry_hash)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "no accepted entry with that hash".to_string()))?;
+    let names: Vec<&str> = q.fields.split(',').map(str::trim).filter(|n| !n.is_empty()).collect();
+    entry.disclose(&names).map(Json).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+/// `GET /entries/:hash/cose`: the accepted entry as its COSE_Sign1 message, for entries signed
+/// as one.
+pub async fn entry_cose(State(state): State<AppState>, Path(entry_hash): Path<String>) -> Result<Response, (StatusCode, String)> {
+    let entry = state.log.lock().unwrap()
+        .accepted_entry(&entry_hash)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "no accepted entry with that hash".to_string()))?;
+    entry.to_cose_sign1().map(wire::cose_sign1).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+/// `PUT /blobs/:sha256`: upload the blob of an attachment of an accepted entry. It must hash to
+/// `sha256` and have the attached size; 201 when stored, 200 if it already was.
+pub async fn put_blob(State(state): State<AppState>, Path(sha256): Path<String>, body: Bytes) -> Result<StatusCode, (StatusCode, String)> {
+    match state.blobs.lock().unwrap().put(&sha256, &body) {
+        Ok(true) => Ok(StatusCode::CREATED),
+        Ok(false) => Ok(StatusCode::OK),
+        Err(e) => {
+            let code = match e {
+                BlobError::Unreferenced => StatusCode::NOT_FOUND,
+                BlobError::Invalid(_) => StatusCode::BAD_REQUEST,
+                BlobError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            Err((code, e.to_string()))
+        }
+    }
+}
+
+/// `GET /blobs/:sha256`: a stored attachment blob, checked against its hash before it is sent.
+pub async fn get_blob(State(state): State<AppState>, Path(sha256): Path<String>) -> Result<impl IntoResponse, (StatusCode, String)> {
+    match state.blobs.lock().unwrap().get(&sha256) {
+        Ok(Some(blob)) => Ok(([(header::CONTENT_TYPE, "application/octet-stream")], blob)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "no stored blob with that hash".to_string())),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+#[derive(Serialize)]
+pub struct MissingBlobs {
+    pub missing: Vec<Attachment>,
+}
+
+/// `GET /blobs/missing`: attachments of accepted entries whose blob has not been uploaded.
+pub async fn missing_blobs(State(state): State<AppState>) -> Json<MissingBlobs> {
+    Json(MissingBlobs { missing: state.blobs.lock().unwrap().missing() })
+}
+
+/// `GET /entries/:hash/tombstone`: the tombstone of an erased entry.
+pub async fn entry_tombstone(State(state): State<AppState>, Path(entry_hash): Path<String>) -> Result<Json<Tombstone>, StatusCode> {
+    state.tombstones.lock().unwrap().get(&entry_hash).cloned().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Equivocation proofs and the devices they flag.
+#[derive(Serialize)]
+pub struct EquivocationPage {
+    pub flagged_devices: Vec<String>,
+    pub proofs: Vec<EquivocationProof>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// `GET /equivocations`: stored equivocation proofs, oldest first, optionally for one device.
+pub async fn list_equivocations(State(state): State<AppState>, QueryParams(q): QueryParams<EquivocationQuery>) -> Json<EquivocationPage> {
+    let equivocations = state.equivocations.lock().unwrap();
+    let (proofs, next_cursor) = equivocations.page(&q);
+    Json(EquivocationPage { flagged_devices: equivocations.flagged(q.device_id.as_deref()), proofs, next_cursor })
+}
+
+/// `GET /devices/:id/equivocations`: `GET /equivocations` restricted to one device.
+pub async fn device_equivocations(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    QueryParams(mut q): QueryParams<EquivocationQuery>,
+) -> Json<EquivocationPage> {
+    q.device_id = Some(device_id);
+    list_equivocations(State(state), QueryParams(q)).await
+}
+
+/// A device's status, as returned and accepted by `/devices/:id/status`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusBody {
+    #[serde(default)]
+    pub device_id: String,
+    pub status: DeviceStatus,
+}
+
+/// `GET /devices/:id/status`: whether the device is active, quarantined or retired.
+pub async fn device_status(State(state): State<AppState>, Path(device_id): Path<String>) -> Json<StatusBody> {
+    let status = state.device_status.status(&device_id);
+    Json(StatusBody { device_id, status })
+}
+
+/// Admin endpoints require `Authorization: Bearer <token>` with the configured admin token (401
+/// otherwise), and are refused with 503 when none is configured.
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(token) = state.admin_token.as_deref() else {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "admin endpoints are disabled: TARIC_ADMIN_TOKEN is not set".into()));
+    };
+    let given = headers.get("authorization").and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("Bearer "));
+    if given == Some(token) { Ok(()) } else { Err((StatusCode::UNAUTHORIZED, "admin token required".into())) }
+}
+
+/// `PUT /devices/:id/status`: set a device's status (admin).
+pub async fn set_device_status(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<StatusBody>,
+) -> Result<Json<StatusBody>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    // Serialize with entry processing so a status change applies between entries.
+    let _log = state.log.lock().unwrap();
+    state.device_status.set_status(&device_id, body.status);
+    Ok(Json(StatusBody { device_id, status: body.status }))
+}
+
+/// One page of alerts.
+#[derive(Serialize)]
+pub struct AlertPage {
+    pub alerts: Vec<Alert>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// `GET /alerts`: counter gap and heartbeat alerts, oldest first.
+pub async fn list_alerts(State(state): State<AppState>, QueryParams(q): QueryParams<AlertQuery>) -> Json<AlertPage> {
+    let (alerts, next_cursor) = state.alerts.lock().unwrap().page(&q);
+    Json(AlertPage { alerts, next_cursor })
+}
+
+/// `GET /devices/:id/alerts`: `GET /alerts` restricted to one device.
+pub async fn device_alerts(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    QueryParams(mut q): QueryParams<AlertQuery>,
+) -> Json<AlertPage> {
+    q.device_id = Some(device_id);
+    list_alerts(State(state), QueryParams(q)).await
+}
+
+/// `GET /devices/:id/heartbeat`: expected heartbeat, last entry time and whether one is missing.
+pub async fn device_heartbeat(State(state): State<AppState>, Path(device_id): Path<String>) -> Json<HeartbeatStatus> {
+    Json(state.alerts.lock().unwrap().heartbeat_status(&device_id))
+}
+
+/// `PUT /devices/:id/heartbeat`: set the expected heartbeat of a device (admin).
+pub async fn set_device_heartbeat(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    headers: HeaderMap,
+    Json(cfg): Json<HeartbeatConfig>,
+) -> Result<Json<HeartbeatStatus>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    if cfg.interval_secs == 0 { return Err((StatusCode::BAD_REQUEST, "interval_secs must be positive".into())); }
+    let mut alerts = state.alerts.lock().unwrap();
+    alerts.set_heartbeat(&device_id, Some(cfg)).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(alerts.heartbeat_status(&device_id)))
+}
+
+/// `DELETE /devices/:id/heartbeat`: stop expecting a heartbeat from a device (admin).
+pub async fn delete_device_heartbeat(
+    State(state): State<AppState>,
+    Path(device_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<HeartbeatStatus>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let mut alerts = state.alerts.lock().unwrap();
+    alerts.set_heartbeat(&device_id, None).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(alerts.heartbeat_status(&device_id)))
+}
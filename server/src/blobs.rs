@@ -0,0 +1,160 @@
+//! Content-addressed blob store for entry attachments: one file per blob, named by its hex
+//! SHA-256. Only blobs attached to an accepted entry are taken, and each is checked against the
+//! attachment's hash and size before it is stored.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+use serde::Serialize;
+use taric_core::attachments::is_blob_hash;
+use taric_core::{Attachment, LogEntry, VerifyError};
+
+use crate::records::LogLine;
+
+/// Directory of stored blobs; override with `TARIC_BLOBS_PATH`.
+pub fn blobs_path() -> String {
+    std::env::var("TARIC_BLOBS_PATH").unwrap_or_else(|_| "/fixtures/blobs".to_string())
+}
+
+/// Largest blob accepted for upload; override with `TARIC_MAX_BLOB_BYTES`.
+pub fn max_blob_bytes() -> usize {
+    std::env::var("TARIC_MAX_BLOB_BYTES").ok().and_then(|s| s.parse().ok()).unwrap_or(64 * 1024 * 1024)
+}
+
+/// What the store holds for an attachment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlobState {
+    Stored,
+    Missing,
+    /// A file is stored under the hash but its content does not match (checked on export).
+    Corrupt,
+}
+
+/// An attachment of a record and what the store holds for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentStatus {
+    pub sha256: String,
+    pub size: u64,
+    pub state: BlobState,
+}
+
+/// Why an uploaded blob was refused.
+#[derive(Debug)]
+pub enum BlobError {
+    /// No accepted entry attaches a blob with this hash.
+    Unreferenced,
+    Invalid(VerifyError),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for BlobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlobError::Unreferenced => write!(f, "no accepted entry attaches that blob"),
+            BlobError::Invalid(e) => write!(f, "{e}"),
+            BlobError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Stored blobs and the attachments of accepted entries.
+pub struct BlobStore {
+    dir: PathBuf,
+    /// Hash and size of every blob attached to an accepted entry. Entries may attach one hash with
+    /// different sizes; each pair is kept, and only the one matching the blob can be satisfied.
+    attached: BTreeSet<(String, u64)>,
+}
+
+impl BlobStore {
+    /// Open the store, creating its directory, and collect the attachments of the accepted
+    /// records in `lines`.
+    pub fn load(dir: String, lines: &[LogLine]) -> Result<Self, String> {
+        fs::create_dir_all(&dir).map_err(|e| format!("create {dir}: {e}"))?;
+        let mut store = Self { dir: dir.into(), attached: BTreeSet::new() };
+        for rec in lines.iter().filter_map(|l| l.parsed.as_ref().ok()).filter(|r| r.is_accepted()) {
+            store.attach(&rec.entry);
+        }
+        Ok(store)
+    }
+
+    /// Record the attachments of an accepted entry, so their blobs can be uploaded.
+    pub fn attach(&mut self, entry: &LogEntry) {
+        for a in entry.attachments() {
+            self.attached.insert((a.sha256.clone(), a.size));
+        }
+    }
+
+    fn path_of(&self, sha256: &str) -> PathBuf { self.dir.join(sha256) }
+
+    /// The attachment with hash `sha256` a blob of `len` bytes is checked against: the one of that
+    /// size if any, otherwise the first recorded, so the check reports the size mismatch.
+    fn attachment_for(&self, sha256: &str, len: usize) -> Option<Attachment> {
+        let sizes: Vec<u64> = self.attached.range((sha256.to_string(), 0)..=(sha256.to_string(), u64::MAX)).map(|(_, size)| *size).collect();
+        let size = sizes.iter().copied().find(|&s| s == len as u64).or(sizes.first().copied())?;
+        Some(Attachment { sha256: sha256.into(), size })
+    }
+
+    /// Check `blob` against the attachment with hash `sha256` and store it. Returns whether it
+    /// was new; a blob already stored intact is not written again, a corrupt one is replaced.
+    pub fn put(&self, sha256: &str, blob: &[u8]) -> Result<bool, BlobError> {
+        let attachment = self.attachment_for(sha256, blob.len()).ok_or(BlobError::Unreferenced)?;
+        attachment.check_blob(blob).map_err(BlobError::Invalid)?;
+        if self.state(&attachment, true) == BlobState::Stored { return Ok(false); }
+        // Written aside and renamed, so a stored file is always complete.
+        let tmp = self.dir.join(format!("{sha256}.tmp"));
+        let mut f = File::create(&tmp).map_err(BlobError::Io)?;
+        f.write_all(blob).and_then(|_| f.sync_data()).map_err(BlobError::Io)?;
+        fs::rename(&tmp, self.path_of(sha256)).map_err(BlobError::Io)?;
+        Ok(true)
+    }
+
+    /// The stored blob with hash `sha256`, if it is attached to an accepted entry and its
+    /// content matches.
+    pub fn get(&self, sha256: &str) -> Result<Option<Vec<u8>>, BlobError> {
+        let Some(first) = self.attachment_for(sha256, 0) else { return Ok(None) };
+        let mut blob = Vec::new();
+        match File::open(self.path_of(sha256)) {
+            Ok(mut f) => f.read_to_end(&mut blob).map_err(BlobError::Io)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(BlobError::Io(e)),
+        };
+        self.attachment_for(sha256, blob.len()).unwrap_or(first).check_blob(&blob).map_err(BlobError::Invalid)?;
+        Ok(Some(blob))
+    }
+
+    /// What the store holds for `a`. With `verify`, a stored blob's content is hashed;
+    /// otherwise only its size is checked.
+    pub fn state(&self, a: &Attachment, verify: bool) -> BlobState {
+        if !is_blob_hash(&a.sha256) { return BlobState::Missing; }
+        let path = self.path_of(&a.sha256);
+        match fs::metadata(&path) {
+            Err(_) => BlobState::Missing,
+            Ok(m) if m.len() != a.size => BlobState::Corrupt,
+            Ok(_) if !verify => BlobState::Stored,
+            Ok(_) => match fs::read(&path) {
+                Ok(blob) if a.check_blob(&blob).is_ok() => BlobState::Stored,
+                _ => BlobState::Corrupt,
+            },
+        }
+    }
+
+    /// The status of each attachment of `entry`; see `state`.
+    pub fn statuses(&self, entry: &LogEntry, verify: bool) -> Vec<AttachmentStatus> {
+        entry.attachments()
+            .iter()
+            .map(|a| AttachmentStatus { sha256: a.sha256.clone(), size: a.size, state: self.state(a, verify) })
+            .collect()
+    }
+
+    /// Attachments of accepted entries whose blob has not been stored, by hash.
+    pub fn missing(&self) -> Vec<Attachment> {
+        self.attached
+            .iter()
+            .map(|(sha256, size)| Attachment { sha256: sha256.clone(), size: *size })
+            .filter(|a| !fs::metadata(self.path_of(&a.sha256)).is_ok_and(|m| m.len() == a.size))
+            .collect()
+    }
+}
@@ -0,0 +1,193 @@
+//! Checkpoint history: signed tree heads of the global log, appended to a JSONL file,
+//! plus the witness co-signatures collected for them (kept in a second JSONL file).
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::Duration;
+
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use taric_core::{Checkpoint, Cosignature, MerkleTree, VerifyError, VerifyingKey};
+
+use crate::api::AppState;
+use crate::index::{DEFAULT_LIMIT, MAX_LIMIT};
+
+/// Path of the checkpoint history; override with `TARIC_CHECKPOINTS_PATH`.
+pub fn checkpoints_path() -> String {
+    std::env::var("TARIC_CHECKPOINTS_PATH").unwrap_or_else(|_| "/fixtures/checkpoints.jsonl".to_string())
+}
+
+/// Path of the collected co-signatures; override with `TARIC_COSIGNATURES_PATH`.
+pub fn cosignatures_path() -> String {
+    std::env::var("TARIC_COSIGNATURES_PATH").unwrap_or_else(|_| "/fixtures/cosignatures.jsonl".to_string())
+}
+
+/// Path of the witness public keys; override with `TARIC_WITNESS_KEYS_PATH`.
+pub fn witness_keys_path() -> String {
+    std::env::var("TARIC_WITNESS_KEYS_PATH").unwrap_or_else(|_| "/fixtures/witnesses.json".to_string())
+}
+
+#[derive(Deserialize)]
+struct WitnessFixture { witness_id: String, algo: String, pubkey_base64: String }
+
+/// Load the witnesses allowed to co-sign, keyed by `witness_id`. A missing file means no witnesses.
+pub fn load_witness_keys() -> HashMap<String, VerifyingKey> {
+    let path = witness_keys_path();
+    let fixtures: Vec<WitnessFixture> = match fs::read_to_string(&path) {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| { eprintln!("Invalid witness keys {path}: {e}"); Vec::new() }),
+        Err(_) => Vec::new(),
+    };
+    let mut keys = HashMap::new();
+    for f in fixtures {
+        match base64::engine::general_purpose::STANDARD.decode(f.pubkey_base64.as_bytes()) {
+            Ok(key) => { keys.insert(f.witness_id, VerifyingKey { algo: f.algo, key, key_id: None }); }
+            Err(_) => eprintln!("Ignoring witness {} with invalid pubkey", f.witness_id),
+        }
+    }
+    keys
+}
+
+/// One line of the co-signature file.
+#[derive(Serialize, Deserialize)]
+struct CosignatureRecord { tree_size: u64, cosignature: Cosignature }
+
+/// Why a submitted co-signature was refused.
+#[derive(Debug)]
+pub enum CosignError {
+    UnknownCheckpoint,
+    UnknownWitness(String),
+    Invalid(VerifyError),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for CosignError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CosignError::UnknownCheckpoint => write!(f, "no checkpoint at that tree size"),
+            CosignError::UnknownWitness(id) => write!(f, "unknown witness: {id}"),
+            CosignError::Invalid(e) => write!(f, "{e}"),
+            CosignError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// How often to sign a checkpoint when the log has grown; override with `TARIC_CHECKPOINT_INTERVAL_SECS`.
+pub fn checkpoint_interval() -> Duration {
+    let secs = std::env::var("TARIC_CHECKPOINT_INTERVAL_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(60);
+    Duration::from_secs(secs)
+}
+
+/// Pagination over the checkpoint history (`GET /checkpoints` query string).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryQuery {
+    /// Position in the history to start from; use the `next_cursor` of the previous page.
+    pub cursor: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// Checkpoints in signing order; tree sizes strictly increase.
+pub struct CheckpointLog {
+    path: String,
+    cosignatures_path: String,
+    witnesses: HashMap<String, VerifyingKey>,
+    items: Vec<Checkpoint>,
+}
+
+impl CheckpointLog {
+    /// Load the history and check every checkpoint against `server_key` and `tree`, the log rebuilt
+    /// from the record log. A checkpoint the tree cannot reproduce means the record log lost or
+    /// changed entries; one the server key did not sign means the history itself was altered.
+    /// Stored co-signatures are attached if they still verify against `witnesses`.
+    pub fn load(path: String, cosignatures_path: String, witnesses: HashMap<String, VerifyingKey>, server_key: &VerifyingKey, tree: &MerkleTree) -> Result<Self, String> {
+        let s = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("read {path}: {e}")),
+        };
+        let mut items: Vec<Checkpoint> = Vec::new();
+        for (i, line) in s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let cp: Checkpoint = serde_json::from_str(line).map_err(|e| format!("{path}:{}: unparseable checkpoint: {e}", i + 1))?;
+            if items.last().is_some_and(|prev| prev.tree_size >= cp.tree_size) {
+                return Err(format!("{path}:{}: tree_size {} does not increase", i + 1, cp.tree_size));
+            }
+            cp.verify(server_key).map_err(|e| format!("{path}:{}: checkpoint at tree_size {}: {e}", i + 1, cp.tree_size))?;
+            let root = tree.root_at(cp.tree_size)
+                .ok_or_else(|| format!("{path}:{}: checkpoint covers {} entries but the log has {}", i + 1, cp.tree_size, tree.len()))?;
+            if hex::encode(root) != cp.root_hash {
+                return Err(format!("{path}:{}: root_hash does not match the log at tree_size {}", i + 1, cp.tree_size));
+            }
+            items.push(cp);
+        }
+        let mut log = Self { path, cosignatures_path, witnesses, items };
+        let s = fs::read_to_string(&log.cosignatures_path).unwrap_or_default();
+        for (i, line) in s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let attached = serde_json::from_str::<CosignatureRecord>(line)
+                .map_err(|e| e.to_string())
+                .and_then(|r| log.attach(r.tree_size, r.cosignature).map_err(|e| e.to_string()));
+            if let Err(e) = attached { eprintln!("{}:{}: ignoring co-signature: {e}", log.cosignatures_path, i + 1); }
+        }
+        Ok(log)
+    }
+
+    /// Verify a witness co-signature of the checkpoint at `tree_size`, attach it and persist it.
+    /// A repeated co-signature from the same witness is accepted but not stored twice.
+    pub fn add_cosignature(&mut self, tree_size: u64, cosignature: Cosignature) -> Result<Checkpoint, CosignError> {
+        if self.attach(tree_size, cosignature.clone())? {
+            let rec = CosignatureRecord { tree_size, cosignature };
+            let mut f = OpenOptions::new().create(true).append(true).open(&self.cosignatures_path).map_err(CosignError::Io)?;
+            writeln!(f, "{}", serde_json::to_string(&rec).expect("co-signature serializes")).map_err(CosignError::Io)?;
+        }
+        Ok(self.get(tree_size).cloned().expect("checkpoint exists"))
+    }
+
+    /// Verify and attach in memory; returns false if the witness had already co-signed.
+    fn attach(&mut self, tree_size: u64, cosignature: Cosignature) -> Result<bool, CosignError> {
+        let i = self.items.binary_search_by_key(&tree_size, |c| c.tree_size).map_err(|_| CosignError::UnknownCheckpoint)?;
+        let key = self.witnesses.get(&cosignature.witness_id).ok_or_else(|| CosignError::UnknownWitness(cosignature.witness_id.clone()))?;
+        let cp = &mut self.items[i];
+        cosignature.verify(cp, key).map_err(CosignError::Invalid)?;
+        if cp.cosignatures.iter().any(|c| c.witness_id == cosignature.witness_id) { return Ok(false); }
+        cp.cosignatures.push(cosignature);
+        Ok(true)
+    }
+
+    pub fn latest(&self) -> Option<&Checkpoint> { self.items.last() }
+
+    /// The checkpoint signed at exactly `tree_size`, if any.
+    pub fn get(&self, tree_size: u64) -> Option<&Checkpoint> {
+        self.items.binary_search_by_key(&tree_size, |c| c.tree_size).ok().map(|i| &self.items[i])
+    }
+
+    /// One page of the history, oldest first, and the cursor of the next page if any.
+    pub fn page(&self, q: &HistoryQuery) -> (Vec<Checkpoint>, Option<u64>) {
+        let start = (q.cursor.unwrap_or(0) as usize).min(self.items.len());
+        let limit = q.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let end = (start + limit).min(self.items.len());
+        let next = (end < self.items.len()).then_some(end as u64);
+        (self.items[start..end].to_vec(), next)
+    }
+
+    pub fn append(&mut self, cp: Checkpoint) -> io::Result<()> {
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(&cp).expect("checkpoint serializes"))?;
+        self.items.push(cp);
+        Ok(())
+    }
+}
+
+/// Sign a checkpoint whenever the log has grown since the last one, every `checkpoint_interval()`.
+pub async fn run(state: AppState) {
+    let mut ticker = tokio::time::interval(checkpoint_interval());
+    loop {
+        ticker.tick().await;
+        let log = state.log.lock().unwrap();
+        let mut checkpoints = state.checkpoints.lock().unwrap();
+        let tree = log.tree();
+        if tree.len() <= checkpoints.latest().map_or(0, |c| c.tree_size) { continue; }
+        let cp = Checkpoint::sign(state.ack_signer.as_ref(), tree.len(), &tree.root(), chrono::Utc::now().timestamp());
+        if let Err(e) = checkpoints.append(cp) {
+            eprintln!("Failed to persist checkpoint: {e}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Equivocation evidence: signed proofs that a device signed conflicting entries, appended to a
+//! JSONL file. A device with at least one proof is flagged.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+
+use serde::Deserialize;
+use taric_core::EquivocationProof;
+
+use crate::index::{DEFAULT_LIMIT, MAX_LIMIT};
+
+/// Path of the stored proofs; override with `TARIC_EQUIVOCATIONS_PATH`.
+pub fn equivocations_path() -> String {
+    std::env::var("TARIC_EQUIVOCATIONS_PATH").unwrap_or_else(|_| "/fixtures/equivocations.jsonl".to_string())
+}
+
+/// Filter and pagination for `GET /equivocations`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EquivocationQuery {
+    pub device_id: Option<String>,
+    /// Position in the proof list to start from; use the `next_cursor` of the previous page.
+    pub cursor: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// Proofs in detection order.
+pub struct EquivocationLog {
+    path: String,
+    items: Vec<EquivocationProof>,
+    flagged: BTreeSet<String>,
+}
+
+impl EquivocationLog {
+    /// Load stored proofs; a missing file means none.
+    pub fn load(path: String) -> Result<Self, String> {
+        let s = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("read {path}: {e}")),
+        };
+        let mut log = Self { path, items: Vec::new(), flagged: BTreeSet::new() };
+        for (i, line) in s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let p: EquivocationProof = serde_json::from_str(line).map_err(|e| format!("{}:{}: unparseable proof: {e}", log.path, i + 1))?;
+            log.flagged.insert(p.device_id.clone());
+            log.items.push(p);
+        }
+        Ok(log)
+    }
+
+    /// Whether a proof for this conflicting entry is already stored.
+    pub fn contains(&self, conflicting_entry_hash: &str) -> bool {
+        self.items.iter().any(|p| p.conflicting.entry_hash == conflicting_entry_hash)
+    }
+
+    /// Persist a proof and flag its device.
+    pub fn append(&mut self, proof: EquivocationProof) -> io::Result<()> {
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(&proof).expect("proof serializes"))?;
+        self.flagged.insert(proof.device_id.clone());
+        self.items.push(proof);
+        Ok(())
+    }
+
+    /// Erase the payload of the entry with this hash from every proof holding it (see
+    /// `LogEntry::erased`); the proofs still verify. The file is rewritten through a temporary
+    /// file, so a crash leaves either version. Returns the number of proofs rewritten.
+    pub fn erase(&mut self, entry_hash: &str) -> io::Result<usize> {
+        let mut erased = 0;
+        for p in &mut self.items {
+            for e in [&mut p.accepted, &mut p.conflicting] {
+                if e.entry_hash != entry_hash || e.is_erased() { continue; }
+                *e = e.erased().map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+                erased += 1;
+            }
+        }
+        if erased == 0 { return Ok(0); }
+        let tmp = format!("{}.tmp", self.path);
+        let mut f = File::create(&tmp)?;
+        for p in &self.items {
+            writeln!(f, "{}", serde_json::to_string(p).expect("proof serializes"))?;
+        }
+        f.sync_data()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(erased)
+    }
+
+    /// Flagged devices, sorted; restricted to `device_id` if given.
+    pub fn flagged(&self, device_id: Option<&str>) -> Vec<String> {
+        self.flagged.iter().filter(|d| device_id.is_none_or(|id| id == d.as_str())).cloned().collect()
+    }
+
+    /// One page of proofs matching `q`, oldest first, and the cursor of the next page if any.
+    pub fn page(&self, q: &EquivocationQuery) -> (Vec<EquivocationProof>, Option<u64>) {
+        let limit = q.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let mut matching = self.items.iter()
+            .enumerate()
+            .skip(q.cursor.unwrap_or(0) as usize)
+            .filter(|(_, p)| q.device_id.as_deref().is_none_or(|d| d == p.device_id));
+        let proofs: Vec<EquivocationProof> = matching.by_ref().take(limit).map(|(_, p)| p.clone()).collect();
+        let next = matching.next().map(|(i, _)| i as u64);
+        (proofs, next)
+    }
+}
@@ -0,0 +1,167 @@
+//! In-memory index over the record log, so queries never scan the file.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::records::Record;
+
+/// Default and maximum page sizes for `search`.
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusFilter { Accepted, Quarantined, Rejected }
+
+/// Filters and pagination for record queries (`GET /entries` query string).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Query {
+    pub device_id: Option<String>,
+    pub session_id: Option<String>,
+    /// Entries before wire version 4 belong to the `default` stream.
+    pub stream_id: Option<String>,
+    /// Entries before wire version 5 are of kind `data`.
+    pub kind: Option<String>,
+    pub status: Option<StatusFilter>,
+    /// Inclusive lower bound on the entry `timestamp`.
+    pub from: Option<i64>,
+    /// Inclusive upper bound on the entry `timestamp`.
+    pub to: Option<i64>,
+    pub entry_hash: Option<String>,
+    /// Return records with `seq >= cursor`; use the `next_cursor` of the previous page.
+    pub cursor: Option<u64>,
+    pub limit: Option<usize>,
+    /// Render each payload according to its encoding (`payload_decoded`).
+    #[serde(default)]
+    pub decode: bool,
+    /// Export only: withhold payloads and reveal these comma-separated fields (`disclosed_fields`).
+    pub disclose: Option<String>,
+}
+
+/// Per-record metadata kept in memory; `seq` is the position in `RecordIndex::records`.
+struct RecordMeta {
+    offset: u64,
+    len: usize,
+    device_id: Arc<str>,
+    session_id: Arc<str>,
+    stream_id: Arc<str>,
+    kind: Arc<str>,
+    accepted: bool,
+    quarantined: bool,
+    timestamp: i64,
+    log_index: Option<u64>,
+}
+
+/// Posting lists (ascending `seq`) per device, session and entry hash.
+#[derive(Default)]
+pub struct RecordIndex {
+    records: Vec<RecordMeta>,
+    by_device: HashMap<Arc<str>, Vec<u64>>,
+    by_session: HashMap<Arc<str>, Vec<u64>>,
+    by_hash: HashMap<String, Vec<u64>>,
+    /// Shared stream and kind names; there are few, so no posting lists.
+    names: HashSet<Arc<str>>,
+}
+
+/// Append `seq` to the posting list for `key`, returning the shared key for the metadata.
+fn post(map: &mut HashMap<Arc<str>, Vec<u64>>, key: &str, seq: u64) -> Arc<str> {
+    if let Some(list) = map.get_mut(key) {
+        list.push(seq);
+        return map.get_key_value(key).expect("key present").0.clone();
+    }
+    let k: Arc<str> = Arc::from(key);
+    map.insert(k.clone(), vec![seq]);
+    k
+}
+
+impl RecordIndex {
+    /// Index a record stored at `offset..offset+len`. Returns its `seq`.
+    pub fn insert(&mut self, rec: &Record, offset: u64, len: usize) -> u64 {
+        let seq = self.records.len() as u64;
+        let device_id = post(&mut self.by_device, &rec.entry.device_id, seq);
+        let session_id = post(&mut self.by_session, &rec.entry.session_id, seq);
+        self.by_hash.entry(rec.entry.entry_hash.clone()).or_default().push(seq);
+        let stream_id = self.intern(rec.entry.stream());
+        let kind = self.intern(rec.entry.kind());
+        self.records.push(RecordMeta {
+            offset,
+            len,
+            device_id,
+            session_id,
+            stream_id,
+            kind,
+            accepted: rec.is_accepted(),
+            quarantined: rec.is_quarantined(),
+            timestamp: rec.entry.timestamp,
+            log_index: rec.log_index,
+        });
+        seq
+    }
+
+    fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(n) = self.names.get(name) { return n.clone(); }
+        let n: Arc<str> = Arc::from(name);
+        self.names.insert(n.clone());
+        n
+    }
+
+    /// Number of indexed records; the `seq` of the next record.
+    pub fn len(&self) -> u64 { self.records.len() as u64 }
+
+    /// File location of a record.
+    pub fn location(&self, seq: u64) -> (u64, usize) {
+        let m = &self.records[seq as usize];
+        (m.offset, m.len)
+    }
+
+    /// Every record of the entry with this hash, accepted or not.
+    pub fn seqs_of(&self, entry_hash: &str) -> &[u64] { self.by_hash.get(entry_hash).map_or(&[], Vec::as_slice) }
+
+    /// Leaf index in the global log of the accepted entry with this hash.
+    pub fn log_index_of(&self, entry_hash: &str) -> Option<u64> {
+        self.by_hash.get(entry_hash)?.iter().find_map(|seq| self.records[*seq as usize].log_index)
+    }
+
+    /// Matching `seq`s for one page of `q`, and the cursor of the next page if there is one.
+    pub fn search(&self, q: &Query) -> (Vec<u64>, Option<u64>) {
+        let cursor = q.cursor.unwrap_or(0);
+        let limit = q.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+        // Walk the most selective posting list available, then check the remaining filters.
+        let empty: &[u64] = &[];
+        let list: Option<&[u64]> = if let Some(h) = &q.entry_hash {
+            Some(self.by_hash.get(h.as_str()).map_or(empty, Vec::as_slice))
+        } else if let Some(s) = &q.session_id {
+            Some(self.by_session.get(s.as_str()).map_or(empty, Vec::as_slice))
+        } else if let Some(d) = &q.device_id {
+            Some(self.by_device.get(d.as_str()).map_or(empty, Vec::as_slice))
+        } else {
+            None
+        };
+        let candidates: Box<dyn Iterator<Item = u64> + '_> = match list {
+            Some(l) => Box::new(l[l.partition_point(|s| *s < cursor)..].iter().copied()),
+            None => Box::new(cursor..self.len()),
+        };
+
+        let mut page: Vec<u64> = candidates.filter(|seq| self.matches(*seq, q)).take(limit + 1).collect();
+        let next = if page.len() > limit { page.pop(); page.last().map(|s| s + 1) } else { None };
+        (page, next)
+    }
+
+    fn matches(&self, seq: u64, q: &Query) -> bool {
+        let m = &self.records[seq as usize];
+        q.device_id.as_deref().is_none_or(|d| &*m.device_id == d)
+            && q.session_id.as_deref().is_none_or(|s| &*m.session_id == s)
+            && q.stream_id.as_deref().is_none_or(|s| &*m.stream_id == s)
+            && q.kind.as_deref().is_none_or(|k| &*m.kind == k)
+            && q.status.is_none_or(|st| match st {
+                StatusFilter::Accepted => m.accepted,
+                StatusFilter::Quarantined => m.quarantined,
+                StatusFilter::Rejected => !m.accepted,
+            })
+            && q.from.is_none_or(|f| m.timestamp >= f)
+            && q.to.is_none_or(|t| m.timestamp <= t)
+    }
+}
@@ -1,25 +1,43 @@
+mod alerts;
+mod api;
+mod blobs;
+mod checkpoints;
+mod equivocations;
+mod index;
+mod records;
+mod recovery;
+mod status;
+mod stream;
+mod tombstones;
+mod trust;
+mod wire;
+
+#[cfg(test)]
+mod tests;
+
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use axum::{routing::{get, post}, Json, Router};
+use axum::{extract::DefaultBodyLimit, routing::{get, post}, Router};
 use serde::Deserialize;
 use base64::Engine as _;
-use taric_core::{AckSigner, ChainStore, DeviceTrust, InMemoryChainStore, LogEntry, Verifier, VerifyingKey, Ed25519AckSigner};
+use taric_core::{AckSigner, ChainStore, DeviceTrust, InMemoryChainStore, Ed25519AckSigner};
 use std::fs;
-use std::fs::OpenOptions;
-use std::io::Write;
-use serde_json::json;
 
-#[derive(Clone)]
-struct StaticTrust { key: VerifyingKey }
-impl DeviceTrust for StaticTrust {
-    fn get_key(&self, _device_id: &str, _key_id: Option<&str>) -> Option<VerifyingKey> { Some(self.key.clone()) }
-}
+use alerts::{alerts_path, heartbeats_path, AlertLog};
+use api::AppState;
+use blobs::{blobs_path, max_blob_bytes, BlobStore};
+use checkpoints::{checkpoints_path, cosignatures_path, load_witness_keys, CheckpointLog};
+use equivocations::{equivocations_path, EquivocationLog};
+use records::{entries_path, LogLine, RecordLog};
+use status::{admin_token, device_status_path, FileDeviceStatus};
+use tombstones::{tombstones_path, TombstoneLog};
+use trust::FixtureTrust;
 
 #[derive(Deserialize)]
 struct ServerKeyFixture { signer_id: String, algo: String, secret_key_base64: String }
 
-fn load_server_signer() -> Arc<dyn AckSigner> {
+fn load_server_signer() -> Ed25519AckSigner {
     let path = std::env::var("TARIC_SERVER_KEY_PATH").unwrap_or_else(|_| "/fixtures/server/server_key.json".to_string());
     match fs::read_to_string(&path) {
         Ok(s) => {
@@ -32,116 +50,129 @@ fn load_server_signer() -> Arc<dyn AckSigner> {
                     };
                     let mut arr = [0u8; 32];
                     if sk_bytes.len() == 32 { arr.copy_from_slice(&sk_bytes); } else { eprintln!("Server secret key must be 32 bytes (seed)"); }
-                    Arc::new(Ed25519AckSigner::from_secret_key(Box::leak(f.signer_id.into_boxed_str()), arr)) as Arc<dyn AckSigner>
+                    Ed25519AckSigner::from_secret_key(Box::leak(f.signer_id.into_boxed_str()), arr)
                 }
-                Err(e) => { eprintln!("Invalid server key JSON: {e}"); Arc::new(Ed25519AckSigner::from_secret_key("server-dev", [7u8;32])) }
+                Err(e) => { eprintln!("Invalid server key JSON: {e}"); Ed25519AckSigner::from_secret_key("server-dev", [7u8;32]) }
             }
         }
         Err(_) => {
             eprintln!("Server key not found at {path}; using ephemeral dev key. Set TARIC_SERVER_KEY_PATH or create fixtures/server/server_key.json");
-            Arc::new(Ed25519AckSigner::from_secret_key("server-dev", [7u8;32]))
+            Ed25519AckSigner::from_secret_key("server-dev", [7u8;32])
         }
     }
 }
 
-
-#[derive(Deserialize)]
-#[derive(Debug, Clone)]
-struct DeviceFixture { device_id: String, algo: String, key_id: String, pubkey_base64: String }
+/// Rebuild chain state from the record log and open it for appends; exits if the log has been tampered with.
+/// Also returns the lines read, for state derived from the records.
+fn recover_chain_state(store: Arc<dyn ChainStore>, ack_signer: Arc<dyn AckSigner>) -> (RecordLog, Vec<LogLine>) {
+    let path = entries_path();
+    let lines = match records::load(&path) {
+        Ok(l) => l,
+        Err(e) => { eprintln!("Failed to read record log {path}: {e}"); std::process::exit(1); }
+    };
+    let trust: Arc<dyn DeviceTrust> = Arc::new(FixtureTrust::load());
+    let report = recovery::recover(&lines, trust, store, ack_signer);
+    for v in &report.violations {
+        let kind = if v.tampering { "integrity violation" } else { "warning" };
+        eprintln!("{path}:{}: {kind}: {} (entry {})", v.line, v.reason, v.entry_hash.as_deref().unwrap_or("-"));
+    }
+    println!("Recovered chain state from {path}: {} accepted entries replayed, {} violations", report.replayed, report.violations.len());
+    if report.is_tampered() {
+        eprintln!("Record log {path} failed integrity checks; refusing to serve");
+        std::process::exit(1);
+    }
+    match RecordLog::from_lines(path.clone(), &lines) {
+        Ok(log) => (log, lines),
+        Err(e) => { eprintln!("Failed to open record log {path}: {e}"); std::process::exit(1); }
+    }
+}
 
 #[tokio::main]
 async fn main() {
     let store: Arc<dyn ChainStore> = Arc::new(InMemoryChainStore::new());
-    let ack_signer: Arc<dyn AckSigner> = load_server_signer();
-    let store_cloned = store.clone();
-    let ack_signer_cloned = ack_signer.clone();
+    let server_signer = load_server_signer();
+    let server_key = server_signer.verifying_key();
+    let ack_signer: Arc<dyn AckSigner> = Arc::new(server_signer);
+    let (log, lines) = recover_chain_state(store.clone(), ack_signer.clone());
+    let checkpoints = match CheckpointLog::load(checkpoints_path(), cosignatures_path(), load_witness_keys(), &server_key, log.tree()) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Checkpoint history does not match the record log and server key: {e}; refusing to serve");
+            std::process::exit(1);
+        }
+    };
+    let equivocations = match EquivocationLog::load(equivocations_path()) {
+        Ok(e) => e,
+        Err(e) => { eprintln!("Failed to load equivocation proofs: {e}"); std::process::exit(1); }
+    };
+    let device_status = match FileDeviceStatus::load(device_status_path()) {
+        Ok(s) => s,
+        Err(e) => { eprintln!("Failed to load device status: {e}"); std::process::exit(1); }
+    };
+    let alerts = match AlertLog::load(alerts_path(), heartbeats_path(), &lines, chrono::Utc::now().timestamp()) {
+        Ok(a) => a,
+        Err(e) => { eprintln!("Failed to load alerts: {e}"); std::process::exit(1); }
+    };
+    let tombstones = match TombstoneLog::load(tombstones_path()) {
+        Ok(t) => t,
+        Err(e) => { eprintln!("Failed to load tombstones: {e}"); std::process::exit(1); }
+    };
+    let blobs = match BlobStore::load(blobs_path(), &lines) {
+        Ok(b) => b,
+        Err(e) => { eprintln!("Failed to open blob store: {e}"); std::process::exit(1); }
+    };
+    drop(lines);
+    let state = AppState {
+        store,
+        ack_signer,
+        log: Arc::new(Mutex::new(log)),
+        events: stream::channel(),
+        checkpoints: Arc::new(Mutex::new(checkpoints)),
+        equivocations: Arc::new(Mutex::new(equivocations)),
+        device_status: Arc::new(device_status),
+        admin_token: admin_token().map(Arc::from),
+        alerts: Arc::new(Mutex::new(alerts)),
+        tombstones: Arc::new(Mutex::new(tombstones)),
+        blobs: Arc::new(Mutex::new(blobs)),
+    };
+    if state.admin_token.is_none() {
+        eprintln!("TARIC_ADMIN_TOKEN is not set; admin endpoints are disabled");
+    }
+    tokio::spawn(checkpoints::run(state.clone()));
+    tokio::spawn(alerts::run(state.clone()));
 
     let app = Router::new()
         .route("/health", get(|| async { "ok" }))
-        .route("/entries", get({
-            move || async move {
-                let path = "/fixtures/entries.jsonl";
-                let body = if let Ok(s) = fs::read_to_string(path) {
-                    // Convert JSONL to JSON array
-                    let mut arr = Vec::new();
-                    for line in s.lines() {
-                        if line.trim().is_empty() { continue; }
-                        if let Ok(v) = serde_json::from_str::<serde_json::Value>(line) { arr.push(v); }
-                    }
-                    serde_json::to_string(&arr).unwrap_or_else(|_| "[]".to_string())
-                } else {
-                    "[]".to_string()
-                };
-                axum::response::Response::builder()
-                    .header(axum::http::header::CONTENT_TYPE, "application/json")
-                    .body(axum::body::Body::from(body))
-                    .unwrap()
-            }
-        }))
-        .route("/entries", post({
-            move |Json(e): Json<LogEntry>| {
-                let store = store_cloned.clone();
-                let ack_signer = ack_signer_cloned.clone();
-                async move {
-                    // Reload device fixture each request so the runner can provide key dynamically
-                    let fixture_path = "/fixtures/devices/device.json";
-                    let vk = if let Ok(s) = fs::read_to_string(fixture_path) {
-                        let f: DeviceFixture = serde_json::from_str(&s).expect("invalid device fixture JSON");
-                        // ensure request device matches fixture device
-                        if f.device_id != e.device_id {
-                            return axum::response::Json(taric_core::Ack {
-                                entry_id: e.entry_hash.clone(), new_entry_hash: e.entry_hash.clone(),
-                                status: format!("error:device_unknown:{}", e.device_id),
-                                timestamp: chrono::Utc::now().timestamp(),
-                                server_signer_id: "server-key-1".into(), server_signature: String::new()
-                            });
-                        }
-                        let key = base64::engine::general_purpose::STANDARD.decode(f.pubkey_base64.as_bytes()).expect("invalid pubkey b64");
-                        VerifyingKey { algo: f.algo, key, key_id: Some(f.key_id) }
-                    } else {
-                        VerifyingKey { algo: "ed25519".to_string(), key: vec![1u8; 32], key_id: Some("001-key1-1".into()) }
-                    };
-                    let trust = Arc::new(StaticTrust { key: vk });
-                    let ack_signer_for_verifier = ack_signer.clone();
-                    let verifier = Verifier::new(trust, store, ack_signer_for_verifier);
-                    match verifier.process_entry(&e, chrono::Utc::now().timestamp()) {
-                        Ok(ack) => {
-                            append_entry_jsonl(&e, &ack.status);
-                            axum::response::Json(ack)
-                        },
-                        Err(err) => {
-                            let mut ack = taric_core::Ack {
-                                entry_id: e.entry_hash.clone(),
-                                new_entry_hash: e.entry_hash.clone(),
-                                status: format!("error:{err}"),
-                                timestamp: chrono::Utc::now().timestamp(),
-                                server_signer_id: ack_signer.signer_id().to_string(),
-                                server_signature: String::new(),
-                            };
-                            let msg = taric_core::cbor_for_ack_sign(&ack);
-                            let sig = ack_signer.sign(&msg);
-                            ack.server_signature = base64::engine::general_purpose::STANDARD.encode(sig);
-                            append_entry_jsonl(&e, &ack.status);
-                            axum::response::Json(ack)
-                        }
-                    }
-                }
-            }
-        }));
+        .route("/entries", get(api::search_entries).post(api::post_entry))
+        .route("/entries/stream", get(api::stream_sse))
+        .route("/entries/ws", get(api::stream_ws))
+        .route("/entries/export", get(api::export_entries))
+        .route("/entries/:hash/erase", post(api::erase_entry))
+        .route("/entries/:hash/tombstone", get(api::entry_tombstone))
+        .route("/entries/:hash/disclosure", get(api::entry_disclosure))
+        .route("/entries/:hash/cose", get(api::entry_cose))
+        .route("/blobs/missing", get(api::missing_blobs))
+        .route("/blobs/:sha256", get(api::get_blob).put(api::put_blob).layer(DefaultBodyLimit::max(max_blob_bytes())))
+        .route("/devices/:id/entries", get(api::device_entries))
+        .route("/devices/:id/head", get(api::device_head))
+        .route("/devices/:id/streams", get(api::device_streams))
+        .route("/devices/:id/firmware", get(api::device_firmware))
+        .route("/devices/:id/equivocations", get(api::device_equivocations))
+        .route("/devices/:id/status", get(api::device_status).put(api::set_device_status))
+        .route("/devices/:id/alerts", get(api::device_alerts))
+        .route("/devices/:id/heartbeat", get(api::device_heartbeat).put(api::set_device_heartbeat).delete(api::delete_device_heartbeat))
+        .route("/log", get(api::log_root))
+        .route("/checkpoints", get(api::list_checkpoints))
+        .route("/checkpoints/latest", get(api::latest_checkpoint))
+        .route("/checkpoints/:tree_size", get(api::checkpoint_at))
+        .route("/checkpoints/:tree_size/cosignatures", post(api::add_cosignature))
+        .route("/proofs/inclusion", get(api::inclusion_proof))
+        .route("/proofs/consistency", get(api::consistency_proof))
+        .route("/equivocations", get(api::list_equivocations))
+        .route("/alerts", get(api::list_alerts))
+        .with_state(state);
 
     let addr: SocketAddr = "0.0.0.0:8080".parse().unwrap();
     println!("taric-server listening on {addr}");
     axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app).await.unwrap();
 }
-
-fn append_entry_jsonl(e: &LogEntry, status: &str) {
-    let path = "/fixtures/entries.jsonl";
-    let rec = json!({
-        "status": status,
-        "entry": e,
-        "recorded_at": chrono::Utc::now().timestamp(),
-    });
-    if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(path) {
-        let _ = writeln!(f, "{}", rec.to_string());
-    }
-}
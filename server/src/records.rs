@@ -0,0 +1,264 @@
+//! Append-only JSONL log of every entry the server received, accepted or not.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use serde::{Deserialize, Serialize};
+use taric_core::merkle::entry_leaf_hash;
+use taric_core::{Ack, CommitmentScheme, DisclosedField, LogEntry, MerkleTree};
+
+use crate::blobs::{AttachmentStatus, BlobStore};
+use crate::index::{Query, RecordIndex, StatusFilter};
+
+/// One line of the record log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub status: String,
+    pub entry: LogEntry,
+    pub recorded_at: i64,
+    /// Leaf index in the global Merkle log; set on accepted records only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<u64>,
+    /// ACK returned to the device; absent on records written before ACKs were stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack: Option<Ack>,
+}
+
+impl Record {
+    /// Whether the entry joined its chain and the global log (including quarantined entries).
+    pub fn is_accepted(&self) -> bool { self.status == "accepted" || self.is_quarantined() }
+    /// Whether the entry was accepted while its device was quarantined.
+    pub fn is_quarantined(&self) -> bool { self.status == "quarantined" }
+}
+
+/// A record together with its position (`seq`) in the log, as returned by queries and streams.
+#[derive(Debug, Clone, Serialize)]
+pub struct SeqRecord {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub record: Record,
+    /// The payload rendered according to its encoding; set on request (`decode=true`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_decoded: Option<serde_json::Value>,
+    /// Payload fields revealed with their proofs, on export with `disclose`; the entry's payload is withheld.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disclosed_fields: Option<Vec<DisclosedField>>,
+    /// Whether the blob of each attachment is stored, for entries with attachments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attachment_status: Option<Vec<AttachmentStatus>>,
+}
+
+impl SeqRecord {
+    /// Set `payload_decoded`: utf8 text as a string, base64 bytes as hex, CBOR as JSON;
+    /// null if the payload does not match its encoding (a rejected entry).
+    pub fn decoded(mut self) -> Self {
+        let rendered = self.record.entry.decoded_payload().map_or(serde_json::Value::Null, |p| p.to_json());
+        self.payload_decoded = Some(rendered);
+        self
+    }
+
+    /// Set `attachment_status` if the entry has attachments; see `BlobStore::statuses`.
+    pub fn with_attachment_status(mut self, blobs: &BlobStore, verify: bool) -> Self {
+        if !self.record.entry.attachments().is_empty() {
+            self.attachment_status = Some(blobs.statuses(&self.record.entry, verify));
+        }
+        self
+    }
+
+    /// Withhold the payload and reveal those of the fields `names` it has, with proofs (see
+    /// `LogEntry::disclose`); entries not committed as `fields` reveal none. `None` before wire
+    /// version 8, where the payload cannot be withheld.
+    pub fn disclosed(mut self, names: &[&str]) -> Option<Self> {
+        let entry = &self.record.entry;
+        let redacted = entry.redacted()?;
+        let present: Vec<&str> = match serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&entry.payload) {
+            Ok(fields) => names.iter().copied().filter(|n| fields.contains_key(*n)).collect(),
+            Err(_) => Vec::new(),
+        };
+        let fields = match entry.commitment_scheme() {
+            Ok(CommitmentScheme::Fields) => entry.disclose(&present).map_or_else(|_| Vec::new(), |d| d.fields),
+            _ => Vec::new(),
+        };
+        self.record.entry = redacted;
+        self.payload_decoded = None;
+        self.disclosed_fields = Some(fields);
+        Some(self)
+    }
+}
+
+/// One non-empty line read back from the log file.
+pub struct LogLine {
+    /// 1-based line number.
+    pub line: usize,
+    /// Byte offset and length of the line, excluding the newline.
+    pub offset: u64,
+    pub len: usize,
+    pub parsed: Result<Record, String>,
+}
+
+/// Path of the record log; override with `TARIC_ENTRIES_PATH`.
+pub fn entries_path() -> String {
+    std::env::var("TARIC_ENTRIES_PATH").unwrap_or_else(|_| "/fixtures/entries.jsonl".to_string())
+}
+
+/// Read the record log. Lines that are not valid records carry their parse error.
+/// A missing file is an empty log.
+pub fn load(path: &str) -> io::Result<Vec<LogLine>> {
+    let s = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut out = Vec::new();
+    let mut offset = 0u64;
+    for (i, raw) in s.split_inclusive('\n').enumerate() {
+        let line = raw.trim_end_matches(['\n', '\r']);
+        if !line.trim().is_empty() {
+            let parsed = serde_json::from_str::<Record>(line).map_err(|e| e.to_string());
+            out.push(LogLine { line: i + 1, offset, len: line.len(), parsed });
+        }
+        offset += raw.len() as u64;
+    }
+    Ok(out)
+}
+
+/// The record log file plus its in-memory index and the Merkle tree over accepted entries.
+/// Appends and queries go through here.
+pub struct RecordLog {
+    path: String,
+    index: RecordIndex,
+    tree: MerkleTree,
+    /// Current file length, i.e. the offset of the next appended line.
+    end: u64,
+    /// The file does not end with a newline (torn final write).
+    needs_newline: bool,
+}
+
+impl RecordLog {
+    /// Build the log from lines previously returned by `load`. Unparseable lines are not indexed.
+    /// Accepted records become Merkle leaves in log order.
+    pub fn from_lines(path: String, lines: &[LogLine]) -> io::Result<Self> {
+        let mut index = RecordIndex::default();
+        let mut tree = MerkleTree::new();
+        for l in lines {
+            let Ok(rec) = &l.parsed else { continue };
+            if rec.is_accepted() {
+                let log_index = tree.push_entry_hash(&rec.entry.entry_hash).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                // Records written before log indices were stored get theirs from log order.
+                let rec = Record { log_index: Some(log_index), ..rec.clone() };
+                index.insert(&rec, l.offset, l.len);
+            } else {
+                index.insert(rec, l.offset, l.len);
+            }
+        }
+        let (end, needs_newline) = match fs::read(&path) {
+            Ok(bytes) => (bytes.len() as u64, bytes.last().is_some_and(|b| *b != b'\n')),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (0, false),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, index, tree, end, needs_newline })
+    }
+
+    /// Append a record for `e` and the ACK it received, and index it.
+    /// Accepted entries are also appended to the Merkle tree.
+    pub fn append(&mut self, e: &LogEntry, ack: &Ack) -> io::Result<SeqRecord> {
+        let mut rec = Record {
+            status: ack.status.clone(),
+            entry: e.clone(),
+            recorded_at: chrono::Utc::now().timestamp(),
+            log_index: None,
+            ack: Some(ack.clone()),
+        };
+        let leaf = if rec.is_accepted() {
+            // Check the hash before writing so the file and the tree cannot diverge.
+            let leaf = entry_leaf_hash(&e.entry_hash).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if ack.log_index != Some(self.tree.len()) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("ACK log_index {:?} but the log has {} entries", ack.log_index, self.tree.len())));
+            }
+            rec.log_index = ack.log_index;
+            Some(leaf)
+        } else {
+            None
+        };
+        let len = self.write_line(&rec)?;
+        if let Some(leaf) = leaf { self.tree.push_leaf_hash(leaf); }
+        Ok(self.index_line(rec, len))
+    }
+
+    /// Write `rec` as one line at the end of the file; returns the line length.
+    fn write_line(&mut self, rec: &Record) -> io::Result<usize> {
+        let line = serde_json::to_string(rec).expect("record serializes");
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        if self.needs_newline {
+            f.write_all(b"\n")?;
+            self.end += 1;
+            self.needs_newline = false;
+        }
+        f.write_all(line.as_bytes())?;
+        f.write_all(b"\n")?;
+        Ok(line.len())
+    }
+
+    fn index_line(&mut self, rec: Record, len: usize) -> SeqRecord {
+        let seq = self.index.insert(&rec, self.end, len);
+        self.end += len as u64 + 1;
+        SeqRecord { seq, record: rec, payload_decoded: None, disclosed_fields: None, attachment_status: None }
+    }
+
+    /// Leaf index in the global log of the accepted entry with this hash.
+    pub fn log_index_of(&self, entry_hash: &str) -> Option<u64> { self.index.log_index_of(entry_hash) }
+
+    /// The accepted entry with this hash, if any.
+    pub fn accepted_entry(&self, entry_hash: &str) -> io::Result<Option<LogEntry>> {
+        let q = Query { entry_hash: Some(entry_hash.to_string()), status: Some(StatusFilter::Accepted), limit: Some(1), ..Query::default() };
+        Ok(self.search(&q)?.0.into_iter().next().map(|rec| rec.record.entry))
+    }
+
+    /// Erase the payload of every record of the entry with this hash (see `LogEntry::erased`).
+    /// Lines are rewritten in place, padded with spaces to their old length, so the index and
+    /// later lines stay valid. Returns the number of records rewritten.
+    pub fn erase(&mut self, entry_hash: &str) -> io::Result<usize> {
+        let mut f = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        let seqs = self.index.seqs_of(entry_hash);
+        for seq in seqs {
+            let (offset, len) = self.index.location(*seq);
+            let mut rec = read_record(&mut f, offset, len)?;
+            rec.entry = rec.entry.erased().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+            let line = serde_json::to_string(&rec).expect("record serializes");
+            if line.len() > len {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("erased record {seq} is longer than the original")));
+            }
+            f.seek(SeekFrom::Start(offset))?;
+            f.write_all(format!("{line:len$}").as_bytes())?;
+        }
+        f.sync_data()?;
+        Ok(seqs.len())
+    }
+
+    /// Merkle tree over accepted entries.
+    pub fn tree(&self) -> &MerkleTree { &self.tree }
+
+    /// Number of records in the log; the `seq` of the next append.
+    pub fn len(&self) -> u64 { self.index.len() }
+
+    /// Run `q` against the index and read the matching records from disk.
+    /// Returns the page and the cursor for the next page, if any.
+    pub fn search(&self, q: &Query) -> io::Result<(Vec<SeqRecord>, Option<u64>)> {
+        let (seqs, next) = self.index.search(q);
+        if seqs.is_empty() { return Ok((Vec::new(), next)); }
+        let mut f = File::open(&self.path)?;
+        let mut out = Vec::with_capacity(seqs.len());
+        for seq in seqs {
+            let (offset, len) = self.index.location(seq);
+            out.push(SeqRecord { seq, record: read_record(&mut f, offset, len)?, payload_decoded: None, disclosed_fields: None, attachment_status: None });
+        }
+        Ok((out, next))
+    }
+}
+
+fn read_record(f: &mut File, offset: u64, len: usize) -> io::Result<Record> {
+    let mut buf = vec![0u8; len];
+    f.seek(SeekFrom::Start(offset))?;
+    f.read_exact(&mut buf)?;
+    serde_json::from_slice(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
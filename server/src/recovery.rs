@@ -0,0 +1,93 @@
+//! Startup recovery: rebuild chain state by replaying the persisted record log.
+
+use std::sync::Arc;
+
+use taric_core::{AckSigner, ChainStore, DeviceTrust, Verifier, VerifyError};
+
+use crate::records::LogLine;
+
+/// A problem found while replaying the record log.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// 1-based line number in the record log.
+    pub line: usize,
+    pub entry_hash: Option<String>,
+    pub reason: String,
+    /// True when the stored log itself cannot be trusted (as opposed to the trust source having changed).
+    pub tampering: bool,
+}
+
+/// Outcome of replaying the record log.
+#[derive(Debug, Default)]
+pub struct RecoveryReport {
+    /// Number of accepted entries replayed into the chain store.
+    pub replayed: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl RecoveryReport {
+    pub fn is_tampered(&self) -> bool { self.violations.iter().any(|v| v.tampering) }
+}
+
+/// Replay every accepted record of the log through verification, in log order, updating `store`.
+///
+/// Rejected records are skipped: they never advanced the chain. An accepted record that no longer
+/// verifies is tampering, except when its device or key is no longer trusted: that is reported but
+/// not fatal, and the record still advances the chain state if its hash and previous hash check
+/// out, including the effects of its kind (key rotation, session end, firmware update), so chain
+/// heads, log positions and later entries match what the server had before the restart. A stored
+/// `log_index` that does not match the record's position among accepted records is tampering. An
+/// unparseable final line is treated as a torn write from a crash.
+pub fn recover(
+    lines: &[LogLine],
+    trust: Arc<dyn DeviceTrust>,
+    store: Arc<dyn ChainStore>,
+    ack_signer: Arc<dyn AckSigner>,
+) -> RecoveryReport {
+    let verifier = Verifier::new(trust, store, ack_signer);
+    let mut report = RecoveryReport::default();
+    let last_line = lines.last().map(|l| l.line);
+    let mut next_log_index = 0u64;
+    for l in lines {
+        let line = l.line;
+        let rec = match &l.parsed {
+            Ok(rec) => rec,
+            Err(e) => {
+                let torn = Some(line) == last_line;
+                let reason = if torn { format!("torn final record ignored: {e}") } else { format!("unparseable record: {e}") };
+                report.violations.push(Violation { line, entry_hash: None, reason, tampering: !torn });
+                continue;
+            }
+        };
+        if !rec.is_accepted() { continue; }
+        let expected = next_log_index;
+        next_log_index += 1;
+        if rec.log_index.is_some_and(|i| i != expected) {
+            report.violations.push(Violation {
+                line,
+                entry_hash: Some(rec.entry.entry_hash.clone()),
+                reason: format!("log_index {} out of sequence, expected {expected}", rec.log_index.unwrap_or_default()),
+                tampering: true,
+            });
+        }
+        match verifier.replay_entry(&rec.entry) {
+            Ok(()) => report.replayed += 1,
+            Err(err) => {
+                let (reason, tampering) = match &err {
+                    VerifyError::DeviceUnknown(_) | VerifyError::Revoked(_) => match verifier.replay_untrusted_entry(&rec.entry) {
+                        Ok(()) => (err.to_string(), false),
+                        Err(e) => (format!("{err}: {e}"), true),
+                    },
+                    _ => (err.to_string(), true),
+                };
+                report.violations.push(Violation {
+                    line,
+                    entry_hash: Some(rec.entry.entry_hash.clone()),
+                    reason,
+                    tampering,
+                });
+            }
+        }
+    }
+    report
+}
@@ -0,0 +1,79 @@
+//! Device statuses (active, quarantined, retired), persisted as one JSON object so quarantines
+//! survive restarts. Signature failure counts are kept in memory only.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::Mutex;
+
+use taric_core::{DeviceStatus, DeviceStatusStore, RollbackPolicy, DEFAULT_SIGNATURE_FAILURE_LIMIT};
+
+/// Path of the status file; override with `TARIC_DEVICE_STATUS_PATH`.
+pub fn device_status_path() -> String {
+    std::env::var("TARIC_DEVICE_STATUS_PATH").unwrap_or_else(|_| "/fixtures/device_status.json".to_string())
+}
+
+/// Consecutive invalid signatures that quarantine a device; override with `TARIC_SIGNATURE_FAILURE_LIMIT`.
+pub fn signature_failure_limit() -> u32 {
+    std::env::var("TARIC_SIGNATURE_FAILURE_LIMIT").ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_SIGNATURE_FAILURE_LIMIT)
+}
+
+/// Bearer token of the admin endpoints, from `TARIC_ADMIN_TOKEN`; without one they are disabled.
+pub fn admin_token() -> Option<String> {
+    std::env::var("TARIC_ADMIN_TOKEN").ok().filter(|t| !t.is_empty())
+}
+
+/// Handling of boot counter rollbacks: `reject` (default) or `quarantine`; set with `TARIC_BOOT_ROLLBACK`.
+pub fn rollback_policy() -> RollbackPolicy {
+    match std::env::var("TARIC_BOOT_ROLLBACK").as_deref() {
+        Ok("quarantine") => RollbackPolicy::Quarantine,
+        Ok("reject") | Err(_) => RollbackPolicy::Reject,
+        Ok(other) => {
+            eprintln!("Unknown TARIC_BOOT_ROLLBACK {other:?}; rejecting rollbacks");
+            RollbackPolicy::Reject
+        }
+    }
+}
+
+/// File-backed `DeviceStatusStore`. Devices absent from the file are active.
+pub struct FileDeviceStatus {
+    path: String,
+    status: Mutex<BTreeMap<String, DeviceStatus>>,
+    signature_failures: Mutex<HashMap<String, u32>>,
+}
+
+impl FileDeviceStatus {
+    /// Load statuses from `path`; a missing file means every device is active.
+    pub fn load(path: String) -> Result<Self, String> {
+        let status = match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| format!("{path}: {e}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("read {path}: {e}")),
+        };
+        Ok(Self { path, status: Mutex::new(status), signature_failures: Mutex::new(HashMap::new()) })
+    }
+}
+
+impl DeviceStatusStore for FileDeviceStatus {
+    fn status(&self, device_id: &str) -> DeviceStatus {
+        self.status.lock().unwrap().get(device_id).copied().unwrap_or_default()
+    }
+    fn set_status(&self, device_id: &str, status: DeviceStatus) {
+        let mut all = self.status.lock().unwrap();
+        if all.get(device_id).copied().unwrap_or_default() == status { return; }
+        if status == DeviceStatus::Active { all.remove(device_id); } else { all.insert(device_id.to_string(), status); }
+        println!("Device {device_id} is now {}", status.as_str());
+        let json = serde_json::to_string_pretty(&*all).expect("statuses serialize");
+        if let Err(e) = fs::write(&self.path, json) {
+            eprintln!("Failed to persist device status to {}: {e}", self.path);
+        }
+    }
+    fn record_signature_failure(&self, device_id: &str) -> u32 {
+        let mut failures = self.signature_failures.lock().unwrap();
+        let n = failures.entry(device_id.to_string()).or_insert(0);
+        *n += 1;
+        *n
+    }
+    fn clear_signature_failures(&self, device_id: &str) {
+        self.signature_failures.lock().unwrap().remove(device_id);
+    }
+}
@@ -0,0 +1,97 @@
+//! Live record stream shared by the SSE and WebSocket endpoints.
+//!
+//! Every appended record is broadcast under the log lock. A subscriber first catches up from
+//! the log on disk (through the index) starting at its cursor, then follows the broadcast.
+//! If it falls behind the broadcast buffer it catches up from disk again, so no record is
+//! skipped or delivered twice.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::api::AppState;
+use crate::index::{Query, MAX_LIMIT};
+use crate::records::SeqRecord;
+
+/// Capacity of the live broadcast buffer and of each subscriber's queue.
+pub const BROADCAST_CAPACITY: usize = 1024;
+const SUBSCRIBER_QUEUE: usize = 256;
+
+/// Stream parameters (query string of the stream endpoints).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StreamParams {
+    pub device_id: Option<String>,
+    pub stream_id: Option<String>,
+    pub kind: Option<String>,
+    /// Deliver records with `seq >= cursor`, then live ones. Without it, only new records are sent.
+    pub cursor: Option<u64>,
+    /// Render each payload according to its encoding (`payload_decoded`).
+    #[serde(default)]
+    pub decode: bool,
+}
+
+/// Start streaming records matching `params`; the receiver yields them in `seq` order.
+pub fn subscribe(state: AppState, params: StreamParams) -> mpsc::Receiver<SeqRecord> {
+    let (tx, rx) = mpsc::channel(SUBSCRIBER_QUEUE);
+    tokio::spawn(async move {
+        // Subscribe under the log lock so nothing appended afterwards is missed.
+        let (mut live, mut next) = {
+            let log = state.log.lock().unwrap();
+            (state.events.subscribe(), params.cursor.unwrap_or(log.len()))
+        };
+        loop {
+            if !catch_up(&state, &params, &mut next, &tx).await { return; }
+            loop {
+                match live.recv().await {
+                    Ok(rec) => {
+                        if rec.seq < next { continue; }
+                        next = rec.seq + 1;
+                        if params.device_id.as_deref().is_some_and(|d| d != rec.record.entry.device_id) { continue; }
+                        if params.stream_id.as_deref().is_some_and(|s| s != rec.record.entry.stream()) { continue; }
+                        if params.kind.as_deref().is_some_and(|k| k != rec.record.entry.kind()) { continue; }
+                        if tx.send(render(&params, (*rec).clone())).await.is_err() { return; }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => break,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Send every stored record matching `params` from `*next` to the current end of the log.
+/// Returns false once the subscriber has gone away.
+async fn catch_up(state: &AppState, params: &StreamParams, next: &mut u64, tx: &mpsc::Sender<SeqRecord>) -> bool {
+    loop {
+        let q = Query { device_id: params.device_id.clone(), stream_id: params.stream_id.clone(), kind: params.kind.clone(), cursor: Some(*next), limit: Some(MAX_LIMIT), ..Query::default() };
+        let (page, more, end) = {
+            let log = state.log.lock().unwrap();
+            match log.search(&q) {
+                Ok((page, more)) => (page, more, log.len()),
+                Err(e) => {
+                    eprintln!("Stream catch-up failed: {e}");
+                    return false;
+                }
+            }
+        };
+        for rec in page {
+            *next = rec.seq + 1;
+            if tx.send(render(params, rec)).await.is_err() { return false; }
+        }
+        if more.is_none() {
+            *next = (*next).max(end);
+            return true;
+        }
+    }
+}
+
+fn render(params: &StreamParams, rec: SeqRecord) -> SeqRecord {
+    if params.decode { rec.decoded() } else { rec }
+}
+
+/// Sender half stored in `AppState`.
+pub fn channel() -> broadcast::Sender<Arc<SeqRecord>> {
+    broadcast::channel(BROADCAST_CAPACITY).0
+}
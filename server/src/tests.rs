@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Path, Query as QueryParams, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use ed25519_dalek::{Signer as _, SigningKey};
+use taric_core::types::compute_entry_hash;
+use taric_core::{AckSigner, Attachment, ChainStore, Checkpoint, DeviceStatus, DeviceStatusStore, DeviceTrust, Ed25519AckSigner, EquivocationProof, InMemoryChainStore, LogEntry, MerkleTree, Verifier, VerifyingKey, DATA_KIND, DEFAULT_STREAM};
+
+use crate::alerts::{AlertLog, HeartbeatConfig};
+use crate::api::{self, AppState, EraseRequest, StatusBody};
+use crate::blobs::{BlobState, BlobStore};
+use crate::checkpoints::CheckpointLog;
+use crate::equivocations::EquivocationLog;
+use crate::index::Query;
+use crate::records::{self, LogLine, Record, RecordLog};
+use crate::recovery;
+use crate::status::FileDeviceStatus;
+use crate::stream;
+use crate::tombstones::TombstoneLog;
+
+/// Admin token of the test server state.
+const ADMIN_TOKEN: &str = "test-admin-token";
+
+const DEVICE: &str = "dev-1";
+
+/// Trusts `DEVICE` with one key.
+struct OneKey(VerifyingKey);
+
+/// Trusts no device, as after a device was removed from the trust source.
+struct NoKeys;
+impl DeviceTrust for NoKeys {
+    fn get_key(&self, _device_id: &str, _key_id: Option<&str>) -> Option<VerifyingKey> { None }
+}
+impl DeviceTrust for OneKey {
+    fn get_key(&self, device_id: &str, _key_id: Option<&str>) -> Option<VerifyingKey> { (device_id == DEVICE).then(|| self.0.clone()) }
+}
+
+fn verifying_key(sk: &SigningKey, key_id: &str) -> VerifyingKey {
+    VerifyingKey { algo: "ed25519".into(), key: sk.verifying_key().to_bytes().to_vec(), key_id: Some(key_id.into()) }
+}
+
+/// A version 8 entry of `DEVICE` in session `s1`, committed, hashed and signed with `sk`.
+fn entry(sk: &SigningKey, key_id: &str, prev: Option<&str>, nonce: u64, kind: &str, payload: &str) -> LogEntry {
+    let mut e = LogEntry {
+        version: 8,
+        entry_hash: String::new(),
+        device_id: DEVICE.into(),
+        timestamp: 1_700_000_000 + nonce as i64,
+        session_id: "s1".into(),
+        nonce,
+        algo: "ed25519".into(),
+        key_id: Some(key_id.into()),
+        payload: payload.into(),
+        signature: String::new(),
+        previous_entry_hash: prev.map(String::from),
+        boot_counter: Some(1),
+        entry_counter: Some(nonce),
+        stream_id: Some(DEFAULT_STREAM.into()),
+        kind: Some(kind.into()),
+        payload_encoding: Some("utf8".into()),
+        payload_salt: None,
+        payload_commitment: None,
+        payload_commitment_scheme: None,
+        attachments: None,
+        canon: None,
+        signature_format: None,
+    };
+    e.commit_payload().unwrap();
+    e.entry_hash = compute_entry_hash(&e);
+    e.signature = B64.encode(sk.sign(&e.signing_input().unwrap()).to_bytes());
+    e
+}
+
+/// A fresh, empty directory for one test's files.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("taric-server-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Server state over empty files in `dir`.
+fn state(dir: &std::path::Path) -> AppState {
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    std::fs::write(path("entries.jsonl"), "").unwrap();
+    let log = RecordLog::from_lines(path("entries.jsonl"), &[]).unwrap();
+    let server_signer = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let checkpoints = CheckpointLog::load(path("checkpoints.jsonl"), path("cosignatures.jsonl"), HashMap::new(), &server_signer.verifying_key(), log.tree()).unwrap();
+    let store: Arc<dyn ChainStore> = Arc::new(InMemoryChainStore::new());
+    let ack_signer: Arc<dyn AckSigner> = Arc::new(server_signer);
+    AppState {
+        store,
+        ack_signer,
+        log: Arc::new(Mutex::new(log)),
+        events: stream::channel(),
+        checkpoints: Arc::new(Mutex::new(checkpoints)),
+        equivocations: Arc::new(Mutex::new(EquivocationLog::load(path("equivocations.jsonl")).unwrap())),
+        device_status: Arc::new(FileDeviceStatus::load(path("status.json")).unwrap()),
+        admin_token: Some(ADMIN_TOKEN.into()),
+        alerts: Arc::new(Mutex::new(AlertLog::load(path("alerts.jsonl"), path("heartbeats.json"), &[], 1_700_000_000).unwrap())),
+        tombstones: Arc::new(Mutex::new(TombstoneLog::load(path("tombstones.jsonl")).unwrap())),
+        blobs: Arc::new(Mutex::new(BlobStore::load(path("blobs"), &[]).unwrap())),
+    }
+}
+
+fn bearer(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("authorization", format!("Bearer {token}").parse().unwrap());
+    headers
+}
+
+fn admin_headers() -> HeaderMap { bearer(ADMIN_TOKEN) }
+
+/// `state` as a server started without `TARIC_ADMIN_TOKEN`.
+fn without_admin(state: &AppState) -> AppState { AppState { admin_token: None, ..state.clone() } }
+
+#[tokio::test]
+async fn erasure_requires_the_admin_token() {
+    let dir = scratch_dir("erase-admin");
+    let state = state(&dir);
+    let erase = |state: &AppState, headers: HeaderMap| {
+        let req = EraseRequest { reason: "gdpr-1".into() };
+        api::erase_entry(State(state.clone()), Path("00".repeat(32)), headers, Json(req))
+    };
+
+    for headers in [HeaderMap::new(), bearer("nope")] {
+        assert_eq!(erase(&state, headers).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    }
+    // Without a configured token, even the right header is refused
+    assert_eq!(erase(&without_admin(&state), admin_headers()).await.unwrap_err().0, StatusCode::SERVICE_UNAVAILABLE);
+    // With the token the request reaches the log
+    assert_eq!(erase(&state, admin_headers()).await.unwrap_err().0, StatusCode::NOT_FOUND);
+    assert!(!dir.join("tombstones.jsonl").exists());
+}
+
+#[tokio::test]
+async fn erasure_reaches_equivocation_proofs() {
+    let dir = scratch_dir("erase-proofs");
+    let state = state(&dir);
+    let sk = SigningKey::from_bytes(&[42u8; 32]);
+    let device_key = verifying_key(&sk, "001-key1-1");
+    let server_key = verifying_key(&SigningKey::from_bytes(&[9u8; 32]), "server-key-1");
+    let verifier = Verifier::new(Arc::new(OneKey(device_key.clone())), state.store.clone(), state.ack_signer.clone());
+
+    let accepted = entry(&sk, "001-key1-1", None, 1, DATA_KIND, r#"{"badge_holder":"Alice"}"#);
+    let ack = verifier.process_entry(&accepted, 1_700_000_100).unwrap();
+    state.log.lock().unwrap().append(&accepted, &ack).unwrap();
+    let fork = entry(&sk, "001-key1-1", None, 2, DATA_KIND, r#"{"badge_holder":"Bob"}"#);
+    let proof = EquivocationProof::sign(state.ack_signer.as_ref(), accepted.clone(), fork.clone(), 1_700_000_200);
+    state.equivocations.lock().unwrap().append(proof).unwrap();
+
+    let req = EraseRequest { reason: "gdpr-1".into() };
+    let Json(tombstone) = api::erase_entry(State(state.clone()), Path(accepted.entry_hash.clone()), admin_headers(), Json(req)).await.unwrap();
+    assert_eq!(tombstone.entry_hash, accepted.entry_hash);
+
+    // In memory and on disk, the proof keeps the entry without its payload, and still verifies
+    let reloaded = EquivocationLog::load(dir.join("equivocations.jsonl").to_string_lossy().into_owned()).unwrap();
+    for log in [&*state.equivocations.lock().unwrap(), &reloaded] {
+        let (proofs, _) = log.page(&Default::default());
+        let [p] = proofs.as_slice() else { panic!("one proof expected") };
+        assert!(p.accepted.is_erased());
+        assert_eq!(p.accepted.entry_hash, accepted.entry_hash);
+        assert_eq!(p.conflicting, fork);
+        p.verify(&server_key, &device_key).unwrap();
+    }
+    let raw = std::fs::read_to_string(dir.join("equivocations.jsonl")).unwrap();
+    assert!(!raw.contains("Alice") && raw.contains("Bob"));
+}
+
+#[test]
+fn recovery_replays_the_record_log() {
+    let dir = scratch_dir("recovery");
+    let path = dir.join("entries.jsonl").to_string_lossy().into_owned();
+    let sk = SigningKey::from_bytes(&[42u8; 32]);
+    let signer: Arc<dyn AckSigner> = Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]));
+    let e1 = entry(&sk, "001-key1-1", None, 1, DATA_KIND, "A");
+    let e2 = entry(&sk, "001-key1-1", Some(&e1.entry_hash), 2, DATA_KIND, "B");
+    let refused = entry(&sk, "001-key1-1", None, 3, DATA_KIND, "C");
+    // Writes the records, then a torn final line, and replays them into a fresh store
+    let recover = |records: &[(&str, &LogEntry)], trust: Arc<dyn DeviceTrust>| {
+        let mut log = String::new();
+        for (status, e) in records {
+            let rec = Record { status: status.to_string(), entry: (*e).clone(), recorded_at: 1_700_000_100, log_index: None, ack: None };
+            log += &format!("{}\n", serde_json::to_string(&rec).unwrap());
+        }
+        std::fs::write(&path, log + r#"{"status":"acc"#).unwrap();
+        let store: Arc<dyn ChainStore> = Arc::new(InMemoryChainStore::new());
+        let report = recovery::recover(&records::load(&path).unwrap(), trust, store.clone(), signer.clone());
+        (report, store)
+    };
+    let trusted = || Arc::new(OneKey(verifying_key(&sk, "001-key1-1")));
+
+    // Accepted records are replayed, refused ones skipped, and the torn line only reported
+    let (report, store) = recover(&[("accepted", &e1), ("error:bad_signature", &refused), ("accepted", &e2)], trusted());
+    assert_eq!((report.replayed, report.violations.len(), report.is_tampered()), (2, 1, false));
+    assert_eq!(store.last_hash(DEVICE, DEFAULT_STREAM), Some(e2.entry_hash.clone()));
+
+    // An edited record is tampering
+    let edited = LogEntry { payload: "edited".into(), ..e2.clone() };
+    assert!(recover(&[("accepted", &e1), ("accepted", &edited)], trusted()).0.is_tampered());
+
+    // Without the device's key the records still advance the chain, as long as they link up
+    let (report, store) = recover(&[("accepted", &e1), ("accepted", &e2)], Arc::new(NoKeys));
+    assert_eq!((report.replayed, report.violations.len(), report.is_tampered()), (0, 3, false));
+    assert_eq!(store.last_hash(DEVICE, DEFAULT_STREAM), Some(e2.entry_hash.clone()));
+    assert!(recover(&[("accepted", &e2)], Arc::new(NoKeys)).0.is_tampered());
+}
+
+#[test]
+fn recovery_applies_kinds_of_untrusted_records() {
+    let sk = SigningKey::from_bytes(&[42u8; 32]);
+    let sk2 = SigningKey::from_bytes(&[43u8; 32]);
+    let signer: Arc<dyn AckSigner> = Arc::new(Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]));
+    let live: Arc<dyn ChainStore> = Arc::new(InMemoryChainStore::new());
+    let verifier = Verifier::new(Arc::new(OneKey(verifying_key(&sk, "001-key1-1"))), live.clone(), signer.clone());
+
+    // The device rotates to a second key, then signs with it
+    let rotation = format!(r#"{{"key_id":"001-key1-2","algo":"ed25519","pubkey_base64":"{}"}}"#, B64.encode(sk2.verifying_key().to_bytes()));
+    let e1 = entry(&sk, "001-key1-1", None, 1, "key_rotation", &rotation);
+    let e2 = entry(&sk2, "001-key1-2", Some(&e1.entry_hash), 2, DATA_KIND, "after rotation");
+    let lines: Vec<LogLine> = [&e1, &e2].into_iter().enumerate().map(|(i, e)| {
+        let ack = verifier.process_entry(e, 1_700_000_100).unwrap();
+        let rec = Record { status: ack.status.clone(), entry: e.clone(), recorded_at: 1_700_000_100, log_index: ack.log_index, ack: Some(ack) };
+        LogLine { line: i + 1, offset: 0, len: 0, parsed: Ok(rec) }
+    }).collect();
+
+    // The first key's device is no longer trusted: its record is reported, but the rotation still
+    // applies, so the entry signed with the announced key replays
+    let store: Arc<dyn ChainStore> = Arc::new(InMemoryChainStore::new());
+    let report = recovery::recover(&lines, Arc::new(NoKeys), store.clone(), signer);
+    assert_eq!(report.replayed, 1);
+    let [v] = report.violations.as_slice() else { panic!("one violation expected") };
+    assert_eq!((v.line, v.tampering), (1, false));
+    let rotated = store.rotated_key(DEVICE).expect("rotation applied");
+    assert_eq!((rotated.key, rotated.key_id.as_deref()), (sk2.verifying_key().to_bytes().to_vec(), Some("001-key1-2")));
+    assert_eq!(store.last_hash(DEVICE, DEFAULT_STREAM), live.last_hash(DEVICE, DEFAULT_STREAM));
+    assert_eq!(store.log_size(), live.log_size());
+}
+
+#[test]
+fn checkpoints_must_carry_the_server_signature() {
+    let dir = scratch_dir("checkpoint-keys");
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    let server = Ed25519AckSigner::from_secret_key("server-key-1", [9u8; 32]);
+    let mut tree = MerkleTree::new();
+    tree.push_entry_hash(&"ab".repeat(32)).unwrap();
+    let load = || CheckpointLog::load(path("checkpoints.jsonl"), path("cosignatures.jsonl"), HashMap::new(), &server.verifying_key(), &tree);
+
+    // An appended checkpoint is back after a restart
+    let signed = Checkpoint::sign(&server, 1, &tree.root(), 1_700_000_000);
+    load().unwrap().append(signed.clone()).unwrap();
+    assert_eq!(load().unwrap().latest(), Some(&signed));
+
+    // Same root, signed by another key; then the server's checkpoint with its timestamp changed
+    let other = Checkpoint::sign(&Ed25519AckSigner::from_secret_key("server-key-1", [8u8; 32]), 1, &tree.root(), 1_700_000_000);
+    let edited = Checkpoint { timestamp: 1_700_000_001, ..signed };
+    for cp in [other, edited] {
+        std::fs::write(path("checkpoints.jsonl"), format!("{}\n", serde_json::to_string(&cp).unwrap())).unwrap();
+        let err = load().err().expect("refused");
+        assert!(err.contains("checkpoint at tree_size 1"), "{err}");
+    }
+}
+
+#[tokio::test]
+async fn entries_are_a_plain_array_unless_paged() {
+    let dir = scratch_dir("entries-shape");
+    let state = state(&dir);
+    let sk = SigningKey::from_bytes(&[42u8; 32]);
+    let verifier = Verifier::new(Arc::new(OneKey(verifying_key(&sk, "001-key1-1"))), state.store.clone(), state.ack_signer.clone());
+    let mut prev: Option<String> = None;
+    for nonce in 1..=3 {
+        let e = entry(&sk, "001-key1-1", prev.as_deref(), nonce, DATA_KIND, "reading");
+        let ack = verifier.process_entry(&e, 1_700_000_100).unwrap();
+        state.log.lock().unwrap().append(&e, &ack).unwrap();
+        prev = Some(e.entry_hash);
+    }
+    let get = |q: Query| async {
+        let resp = api::search_entries(State(state.clone()), QueryParams(q)).await.unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+
+    // Without paging parameters, every matching record, as before pagination
+    let all = get(Query::default()).await;
+    assert_eq!(all.as_array().map(Vec::len), Some(3));
+    assert_eq!(all[2]["entry"]["entry_hash"], prev.as_deref().unwrap());
+    let none = get(Query { device_id: Some("dev-2".into()), ..Default::default() }).await;
+    assert_eq!(none, serde_json::json!([]));
+
+    // With `limit` or `cursor`, one page
+    let page = get(Query { limit: Some(2), ..Default::default() }).await;
+    assert_eq!((page["records"].as_array().map(Vec::len), &page["next_cursor"]), (Some(2), &serde_json::json!(2)));
+    let last = get(Query { cursor: Some(2), ..Default::default() }).await;
+    assert_eq!(last["records"].as_array().map(Vec::len), Some(1));
+    assert!(last.get("next_cursor").is_none());
+}
+
+#[tokio::test]
+async fn quarantine_requires_the_admin_token() {
+    let dir = scratch_dir("status-admin");
+    let state = state(&dir);
+    let set = |state: &AppState, headers: HeaderMap| {
+        let body = StatusBody { device_id: String::new(), status: DeviceStatus::Quarantined };
+        api::set_device_status(State(state.clone()), Path(DEVICE.into()), headers, Json(body))
+    };
+
+    assert_eq!(set(&without_admin(&state), admin_headers()).await.unwrap_err().0, StatusCode::SERVICE_UNAVAILABLE);
+    for headers in [HeaderMap::new(), bearer("nope")] {
+        assert_eq!(set(&state, headers).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    }
+    assert_eq!(state.device_status.status(DEVICE), DeviceStatus::Active);
+
+    let Json(set_to) = set(&state, admin_headers()).await.unwrap();
+    assert_eq!((set_to.device_id.as_str(), set_to.status), (DEVICE, DeviceStatus::Quarantined));
+    let Json(status) = api::device_status(State(state.clone()), Path(DEVICE.into())).await;
+    assert_eq!(status.status, DeviceStatus::Quarantined);
+    // The quarantine survives a restart
+    let reloaded = FileDeviceStatus::load(dir.join("status.json").to_string_lossy().into_owned()).unwrap();
+    assert_eq!(reloaded.status(DEVICE), DeviceStatus::Quarantined);
+}
+
+#[tokio::test]
+async fn heartbeat_changes_require_the_admin_token() {
+    let dir = scratch_dir("heartbeat-admin");
+    let state = state(&dir);
+    let cfg = HeartbeatConfig { interval_secs: 60, grace_secs: 0 };
+    let set = |state: &AppState, headers: HeaderMap| api::set_device_heartbeat(State(state.clone()), Path(DEVICE.into()), headers, Json(cfg));
+    let delete = |state: &AppState, headers: HeaderMap| api::delete_device_heartbeat(State(state.clone()), Path(DEVICE.into()), headers);
+
+    let disabled = without_admin(&state);
+    assert_eq!(set(&disabled, admin_headers()).await.unwrap_err().0, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(delete(&disabled, admin_headers()).await.unwrap_err().0, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(set(&state, bearer("nope")).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    assert!(state.alerts.lock().unwrap().heartbeat_status(DEVICE).config.is_none());
+
+    let Json(status) = set(&state, admin_headers()).await.unwrap();
+    assert_eq!(status.config.map(|c| c.interval_secs), Some(60));
+    assert_eq!(delete(&state, HeaderMap::new()).await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+    let Json(status) = delete(&state, admin_headers()).await.unwrap();
+    assert!(status.config.is_none());
+}
+
+#[test]
+fn one_hash_attached_with_two_sizes_keeps_both() {
+    let dir = scratch_dir("blob-sizes");
+    let blob = b"abc";
+    let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let sk = SigningKey::from_bytes(&[42u8; 32]);
+    let attaching = |size: u64| {
+        let e = entry(&sk, "001-key1-1", None, size, DATA_KIND, "x");
+        LogEntry { attachments: Some(vec![Attachment { sha256: sha256.into(), size }]), ..e }
+    };
+    let mut blobs = BlobStore::load(dir.join("blobs").to_string_lossy().into_owned(), &[]).unwrap();
+
+    // A later entry giving the hash another size does not displace the size the blob has
+    blobs.attach(&attaching(3));
+    blobs.attach(&attaching(4));
+    assert!(blobs.put(sha256, blob).unwrap());
+    assert_eq!(blobs.get(sha256).unwrap().as_deref(), Some(&blob[..]));
+    let state = |size: u64| blobs.state(&Attachment { sha256: sha256.into(), size }, true);
+    assert_eq!((state(3), state(4)), (BlobState::Stored, BlobState::Corrupt));
+    assert_eq!(blobs.missing(), [Attachment { sha256: sha256.into(), size: 4 }]);
+}
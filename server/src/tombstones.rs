@@ -0,0 +1,47 @@
+//! Erasure records: signed tombstones for entries whose payload was erased, appended to a
+//! JSONL file.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+
+use taric_core::Tombstone;
+
+/// Path of the stored tombstones; override with `TARIC_TOMBSTONES_PATH`.
+pub fn tombstones_path() -> String {
+    std::env::var("TARIC_TOMBSTONES_PATH").unwrap_or_else(|_| "/fixtures/tombstones.jsonl".to_string())
+}
+
+/// Tombstones by entry hash.
+pub struct TombstoneLog {
+    path: String,
+    items: HashMap<String, Tombstone>,
+}
+
+impl TombstoneLog {
+    /// Load stored tombstones; a missing file means none.
+    pub fn load(path: String) -> Result<Self, String> {
+        let s = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("read {path}: {e}")),
+        };
+        let mut log = Self { path, items: HashMap::new() };
+        for (i, line) in s.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let t: Tombstone = serde_json::from_str(line).map_err(|e| format!("{}:{}: unparseable tombstone: {e}", log.path, i + 1))?;
+            log.items.insert(t.entry_hash.clone(), t);
+        }
+        Ok(log)
+    }
+
+    pub fn get(&self, entry_hash: &str) -> Option<&Tombstone> { self.items.get(entry_hash) }
+
+    /// Persist a tombstone.
+    pub fn append(&mut self, tombstone: Tombstone) -> io::Result<()> {
+        let mut f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(&tombstone).expect("tombstone serializes"))?;
+        f.sync_data()?;
+        self.items.insert(tombstone.entry_hash.clone(), tombstone);
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! Fixture-backed device trust for the demo server, and the payload schemas of device classes.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use base64::Engine as _;
+use serde::Deserialize;
+use taric_core::{DeviceTrust, PayloadSchema, SchemaPolicy, VerifyingKey};
+
+/// Single device fixture written by the e2e runner (`device.json`).
+pub const DEVICE_FIXTURE_PATH: &str = "/fixtures/devices/device.json";
+/// Device fixture list written by `scripts/setup-devices.sh` (`devices.json`).
+pub const DEVICES_FIXTURE_PATH: &str = "/fixtures/devices/devices.json";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeviceFixture {
+    pub device_id: String,
+    pub algo: String,
+    pub key_id: String,
+    pub pubkey_base64: String,
+    /// Device class, whose payload schema applies to the device's `data` entries.
+    #[serde(default)]
+    pub class: Option<String>,
+    /// Schema version of the class the device is pinned to; the latest if absent.
+    #[serde(default)]
+    pub schema_version: Option<u32>,
+}
+
+impl DeviceFixture {
+    /// Decode the fixture into core key material.
+    pub fn verifying_key(&self) -> Option<VerifyingKey> {
+        let key = base64::engine::general_purpose::STANDARD.decode(self.pubkey_base64.as_bytes()).ok()?;
+        Some(VerifyingKey { algo: self.algo.clone(), key, key_id: Some(self.key_id.clone()) })
+    }
+}
+
+/// Directory of class schema files; override with `TARIC_CLASSES_DIR`.
+pub fn classes_dir() -> String {
+    std::env::var("TARIC_CLASSES_DIR").unwrap_or_else(|_| "/fixtures/classes".to_string())
+}
+
+/// Payload schema validation: `off` (default), `warn` or `reject`; set with `TARIC_SCHEMA_POLICY`.
+pub fn schema_policy() -> Option<SchemaPolicy> {
+    match std::env::var("TARIC_SCHEMA_POLICY").as_deref() {
+        Ok("reject") => Some(SchemaPolicy::Reject),
+        Ok("warn") => Some(SchemaPolicy::Warn),
+        Ok("off") | Err(_) => None,
+        Ok(other) => {
+            eprintln!("Unknown TARIC_SCHEMA_POLICY {other:?}; not validating payloads");
+            None
+        }
+    }
+}
+
+/// A class schema file, `<classes_dir>/<class>.json`: every schema version of the class.
+#[derive(Deserialize)]
+struct ClassFile { versions: BTreeMap<u32, serde_json::Value> }
+
+/// Load version `version` (the latest if `None`) of the payload schema of `class`.
+pub fn load_class_schema(class: &str, version: Option<u32>) -> Result<PayloadSchema, String> {
+    if class.is_empty() || !class.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("invalid class name {class:?}"));
+    }
+    let path = format!("{}/{class}.json", classes_dir());
+    let s = fs::read_to_string(&path).map_err(|e| format!("read {path}: {e}"))?;
+    let file: ClassFile = serde_json::from_str(&s).map_err(|e| format!("{path}: {e}"))?;
+    let (version, schema) = match version {
+        Some(v) => (v, file.versions.get(&v).ok_or_else(|| format!("{path}: no schema version {v}"))?),
+        None => file.versions.iter().next_back().map(|(v, s)| (*v, s)).ok_or_else(|| format!("{path}: no schema versions"))?,
+    };
+    PayloadSchema::new(class, version, schema.clone())
+}
+
+/// Trust source built from every known device fixture, keyed by `device_id`.
+#[derive(Default)]
+pub struct FixtureTrust { keys: HashMap<String, VerifyingKey> }
+
+impl FixtureTrust {
+    /// Load `devices.json` (if any), then `device.json`, which wins on duplicate ids.
+    pub fn load() -> Self {
+        let mut fixtures: Vec<DeviceFixture> = fs::read_to_string(DEVICES_FIXTURE_PATH)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        if let Some(f) = fs::read_to_string(DEVICE_FIXTURE_PATH).ok().and_then(|s| serde_json::from_str(&s).ok()) {
+            fixtures.push(f);
+        }
+        let mut keys = HashMap::new();
+        for f in fixtures {
+            match f.verifying_key() {
+                Some(vk) => { keys.insert(f.device_id.clone(), vk); }
+                None => eprintln!("Ignoring device fixture {} with invalid pubkey", f.device_id),
+            }
+        }
+        Self { keys }
+    }
+}
+
+impl DeviceTrust for FixtureTrust {
+    fn get_key(&self, device_id: &str, _key_id: Option<&str>) -> Option<VerifyingKey> { self.keys.get(device_id).cloned() }
+}
@@ -0,0 +1,94 @@
+//! Content negotiation for entries and ACKs: JSON, the binary CBOR envelope
+//! (`application/cbor`, see `taric_core::envelope`) for devices on constrained links, or
+//! COSE_Sign1 (`application/cose`, see `taric_core::cose`).
+
+use axum::async_trait;
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use taric_core::{Ack, LogEntry};
+
+/// Media type of the binary envelope.
+pub const CBOR: &str = "application/cbor";
+/// Media type of COSE messages; sent with `cose-type="cose-sign1"`.
+pub const COSE: &str = "application/cose";
+const COSE_SIGN1_TYPE: &str = "application/cose; cose-type=\"cose-sign1\"";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format { Json, Cbor, Cose }
+
+/// Whether the media types listed in header `name` include `mime` (parameters ignored).
+fn lists(headers: &HeaderMap, name: header::HeaderName, mime: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|m| m.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case(mime))
+}
+
+/// A posted entry: JSON, its CBOR envelope with `Content-Type: application/cbor`, or its
+/// COSE_Sign1 message with `Content-Type: application/cose`. The reply uses the format named
+/// in `Accept`, or else the request's.
+pub struct WireEntry {
+    pub entry: LogEntry,
+    pub reply: Format,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S> for WireEntry {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let headers = req.headers().clone();
+        let body = if lists(&headers, header::CONTENT_TYPE, CBOR) {
+            Format::Cbor
+        } else if lists(&headers, header::CONTENT_TYPE, COSE) {
+            Format::Cose
+        } else {
+            Format::Json
+        };
+        let reply = if lists(&headers, header::ACCEPT, COSE) {
+            Format::Cose
+        } else if lists(&headers, header::ACCEPT, CBOR) {
+            Format::Cbor
+        } else if lists(&headers, header::ACCEPT, "application/json") {
+            Format::Json
+        } else {
+            body
+        };
+        let entry = match body {
+            Format::Json => Json::<LogEntry>::from_request(req, state).await.map_err(IntoResponse::into_response)?.0,
+            Format::Cbor | Format::Cose => {
+                let bytes = Bytes::from_request(req, state).await.map_err(IntoResponse::into_response)?;
+                let parsed = if body == Format::Cbor { LogEntry::from_cbor(&bytes) } else { LogEntry::from_cose_sign1(&bytes) };
+                parsed.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?
+            }
+        };
+        Ok(WireEntry { entry, reply })
+    }
+}
+
+/// A COSE_Sign1 message response.
+pub fn cose_sign1(message: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, COSE_SIGN1_TYPE)], message).into_response()
+}
+
+/// An ACK in the negotiated format. Only COSE-signed ACKs (those of COSE-signed entries) have
+/// a COSE_Sign1 form; others asked for as COSE are sent as JSON.
+pub struct WireAck(pub Ack, pub Format);
+
+impl IntoResponse for WireAck {
+    fn into_response(self) -> Response {
+        match self.1 {
+            Format::Json => Json(self.0).into_response(),
+            Format::Cbor => ([(header::CONTENT_TYPE, CBOR)], self.0.to_cbor()).into_response(),
+            Format::Cose => match self.0.to_cose_sign1() {
+                Ok(message) => cose_sign1(message),
+                Err(_) => Json(self.0).into_response(),
+            },
+        }
+    }
+}
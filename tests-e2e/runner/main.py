@@ -26,14 +26,14 @@ def mk_entry(sk: SigningKey, device_id: str, key_id: str, payload: str, nonce: i
 		entry["version"], entry["device_id"], entry["timestamp"], entry["session_id"], entry["nonce"],
 		entry["algo"], entry["key_id"], entry["payload"], entry["previous_entry_hash"],
 	)
-	h = __import__("hashlib").sha256(cbor2.dumps(to_hash)).hexdigest()
+	h = __import__("hashlib").sha256(cbor2.dumps(to_hash, canonical=True)).hexdigest()
 	entry["entry_hash"] = h
 	# CBOR for sign
 	to_sign = (
 		entry["version"], entry["entry_hash"], entry["device_id"], entry["timestamp"], entry["session_id"], entry["nonce"],
 		entry["algo"], entry["key_id"], entry["payload"], entry["previous_entry_hash"],
 	)
-	sig = sk.sign(cbor2.dumps(to_sign), encoder=RawEncoder).signature
+	sig = sk.sign(cbor2.dumps(to_sign, canonical=True), encoder=RawEncoder).signature
 	entry["signature"] = base64.b64encode(sig).decode()
 	return entry
 
@@ -0,0 +1,161 @@
+//! taric-decrypt: verifies exported entries and decrypts the end-to-end encrypted payloads
+//! addressed to a recipient key.
+//!
+//! Reads NDJSON from the file given as argument, or stdin: `GET /entries/export` output, the
+//! server's record log, or bare entries. Each accepted entry is replayed through the core
+//! verifier (hash, device signature, chain rules, key rotations), so the input must hold every
+//! device stream from its first entry, e.g. an export filtered by device only. Records the
+//! server rejected are skipped. Verified entries are printed as NDJSON
+//! `{ device_id, stream_id, nonce, entry_hash, recipient, erased, payload }`: `payload` is the
+//! rendered plaintext (see `DecodedPayload::to_json`), or null for ciphertext addressed to another
+//! recipient and for erased payloads.
+//!
+//! Configuration (environment):
+//! - `TARIC_RECIPIENT_KEY_PATH`: recipient key JSON `{ recipient_id, algo, secret_key_base64 }`
+//!   with `algo` `x25519` (default `recipient_key.json`)
+//! - `TARIC_DEVICES_PATH`: device fixtures, a list or a single object as in `devices.json`
+//!   (default `/fixtures/devices/devices.json`)
+//!
+//! Pass `--keygen <recipient_id>` to write a new recipient key to `TARIC_RECIPIENT_KEY_PATH` and
+//! print its public key. The exit code is 2 if an entry failed verification or decryption.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use taric_core::encryption::{generate_recipient_key, recipient_public_key};
+use taric_core::{DecodedPayload, DeviceTrust, Ed25519AckSigner, InMemoryChainStore, LogEntry, Verifier, VerifyingKey};
+
+#[derive(Serialize, Deserialize)]
+struct RecipientKey { recipient_id: String, algo: String, secret_key_base64: String }
+
+#[derive(Deserialize)]
+struct DeviceFixture { device_id: String, algo: String, key_id: String, pubkey_base64: String }
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Fixtures { Many(Vec<DeviceFixture>), One(DeviceFixture) }
+
+/// An export line: a record wrapping the entry, or the entry itself.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Record { status: String, entry: LogEntry },
+    Entry(LogEntry),
+}
+
+struct FixtureTrust { keys: HashMap<String, VerifyingKey> }
+
+impl DeviceTrust for FixtureTrust {
+    fn get_key(&self, device_id: &str, _key_id: Option<&str>) -> Option<VerifyingKey> { self.keys.get(device_id).cloned() }
+}
+
+fn env_or(name: &str, default: &str) -> String { std::env::var(name).unwrap_or_else(|_| default.to_string()) }
+
+fn load_recipient(path: &str) -> Result<(String, [u8; 32]), String> {
+    let key: RecipientKey = serde_json::from_str(&fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?)
+        .map_err(|e| format!("{path}: {e}"))?;
+    if key.algo != "x25519" { return Err(format!("{path}: unsupported algo {}", key.algo)); }
+    let secret = B64.decode(key.secret_key_base64.as_bytes())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| format!("{path}: secret key must be base64 of 32 bytes"))?;
+    Ok((key.recipient_id, secret))
+}
+
+fn load_trust(path: &str) -> Result<FixtureTrust, String> {
+    let fixtures = match serde_json::from_str(&fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?).map_err(|e| format!("{path}: {e}"))? {
+        Fixtures::Many(list) => list,
+        Fixtures::One(f) => vec![f],
+    };
+    let mut keys = HashMap::new();
+    for f in fixtures {
+        let key = B64.decode(f.pubkey_base64.as_bytes()).map_err(|e| format!("{path}: {}: {e}", f.device_id))?;
+        keys.insert(f.device_id, VerifyingKey { algo: f.algo, key, key_id: Some(f.key_id) });
+    }
+    Ok(FixtureTrust { keys })
+}
+
+fn keygen(recipient_id: &str, path: &str) -> Result<(), String> {
+    if fs::metadata(path).is_ok() { return Err(format!("{path} already exists")); }
+    let (secret, public) = generate_recipient_key();
+    let key = RecipientKey { recipient_id: recipient_id.into(), algo: "x25519".into(), secret_key_base64: B64.encode(secret) };
+    fs::write(path, serde_json::to_string_pretty(&key).expect("key serializes")).map_err(|e| format!("{path}: {e}"))?;
+    println!("{}", serde_json::json!({ "recipient_id": recipient_id, "algo": "x25519", "pubkey_base64": B64.encode(public) }));
+    Ok(())
+}
+
+/// Verify and decrypt every line; returns whether all entries passed.
+fn run(input: Box<dyn BufRead>, recipient_id: &str, secret: &[u8; 32], trust: FixtureTrust) -> Result<bool, String> {
+    let signer = Ed25519AckSigner::from_secret_key("taric-decrypt", [0u8; 32]);
+    let verifier = Verifier::new(Arc::new(trust), Arc::new(InMemoryChainStore::new()), Arc::new(signer));
+    let mut ok = true;
+    for (i, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() { continue; }
+        let entry = match serde_json::from_str(&line) {
+            Ok(Line::Record { status, entry }) if status != "accepted" && status != "quarantined" => {
+                eprintln!("line {}: skipping {status} entry {}", i + 1, entry.entry_hash);
+                continue;
+            }
+            Ok(Line::Record { entry, .. } | Line::Entry(entry)) => entry,
+            Err(e) => return Err(format!("line {}: {e}", i + 1)),
+        };
+        if let Err(e) = verifier.replay_entry(&entry) {
+            eprintln!("line {}: entry {} of {} failed verification: {e}", i + 1, entry.entry_hash, entry.device_id);
+            ok = false;
+            continue;
+        }
+        let (recipient, payload) = match entry.decoded_payload() {
+            Ok(DecodedPayload::Encrypted(p)) if p.recipient != recipient_id => (Some(p.recipient), serde_json::Value::Null),
+            Ok(DecodedPayload::Encrypted(p)) => match entry.decrypt_payload(secret) {
+                Ok(plain) => (Some(p.recipient), plain.to_json()),
+                Err(e) => {
+                    eprintln!("line {}: entry {}: {e}", i + 1, entry.entry_hash);
+                    ok = false;
+                    continue;
+                }
+            },
+            Ok(plain) => (None, plain.to_json()),
+            Err(_) if entry.is_erased() => (None, serde_json::Value::Null),
+            Err(e) => return Err(format!("line {}: {e}", i + 1)),
+        };
+        println!("{}", serde_json::json!({
+            "device_id": entry.device_id,
+            "stream_id": entry.stream(),
+            "nonce": entry.nonce,
+            "entry_hash": entry.entry_hash,
+            "recipient": recipient,
+            "erased": entry.is_erased(),
+            "payload": payload,
+        }));
+    }
+    Ok(ok)
+}
+
+fn main() {
+    let key_path = env_or("TARIC_RECIPIENT_KEY_PATH", "recipient_key.json");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [flag, id] if flag == "--keygen" => keygen(id, &key_path).map(|_| true),
+        [flag] if flag == "--keygen" => Err("--keygen needs a recipient id".into()),
+        _ => load_recipient(&key_path).and_then(|(recipient_id, secret)| {
+            let trust = load_trust(&env_or("TARIC_DEVICES_PATH", "/fixtures/devices/devices.json"))?;
+            let input: Box<dyn BufRead> = match args.first() {
+                Some(path) => Box::new(BufReader::new(fs::File::open(path).map_err(|e| format!("{path}: {e}"))?)),
+                None => Box::new(BufReader::new(io::stdin())),
+            };
+            eprintln!("taric-decrypt: recipient {recipient_id} ({})", B64.encode(recipient_public_key(&secret)));
+            run(input, &recipient_id, &secret, trust)
+        }),
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(2),
+        Err(e) => { eprintln!("taric-decrypt: {e}"); std::process::exit(1); }
+    }
+}
@@ -0,0 +1,144 @@
+//! taric-witness: independently checks a TARIC server's checkpoints and co-signs them.
+//!
+//! Each round the witness fetches the latest checkpoint, verifies the server signature, and
+//! checks it is consistent with the last checkpoint it co-signed (same root at the same size, or
+//! a valid consistency proof to a larger size). Only then does it co-sign and submit the
+//! co-signature. A server presenting a different log to this witness than to others (split view),
+//! or rolling its log back, is reported with both checkpoints as evidence and never co-signed.
+//!
+//! Configuration (environment):
+//! - `TARIC_SERVER`: server base URL (default `http://127.0.0.1:8080`)
+//! - `TARIC_SERVER_PUBKEY`: base64 Ed25519 public key of the server's ACK/checkpoint key (required)
+//! - `TARIC_WITNESS_KEY_PATH`: witness key JSON `{ signer_id, algo, secret_key_base64 }`, same format as the server key
+//! - `TARIC_WITNESS_STATE_PATH`: last co-signed checkpoint (default `witness_state.json`)
+//! - `TARIC_WITNESS_INTERVAL_SECS`: seconds between rounds (default 30)
+//!
+//! Pass `--once` to run a single round; the exit code is 2 if an inconsistency was found.
+
+use std::fs;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine as _;
+use serde::Deserialize;
+use taric_core::{AckSigner, Checkpoint, ConsistencyProof, Cosignature, Ed25519AckSigner, VerifyingKey};
+
+struct Config {
+    server: String,
+    server_key: VerifyingKey,
+    signer: Ed25519AckSigner,
+    state_path: String,
+    interval: Duration,
+}
+
+#[derive(Deserialize)]
+struct KeyFixture { signer_id: String, algo: String, secret_key_base64: String }
+
+#[derive(Deserialize)]
+struct ConsistencyResponse { first: Checkpoint, second: Checkpoint, proof: ConsistencyProof }
+
+/// Outcome of a failed round.
+enum Failure {
+    /// Network or protocol problem; retry next round.
+    Transport(String),
+    /// The server's log is not consistent with what this witness saw before.
+    Inconsistent(String),
+}
+
+fn env_or(name: &str, default: &str) -> String { std::env::var(name).unwrap_or_else(|_| default.to_string()) }
+
+fn load_config() -> Result<Config, String> {
+    let pubkey = std::env::var("TARIC_SERVER_PUBKEY").map_err(|_| "TARIC_SERVER_PUBKEY is required".to_string())?;
+    let key = B64.decode(pubkey.as_bytes()).map_err(|e| format!("TARIC_SERVER_PUBKEY: {e}"))?;
+    let key_path = env_or("TARIC_WITNESS_KEY_PATH", "witness_key.json");
+    let fixture: KeyFixture = serde_json::from_str(&fs::read_to_string(&key_path).map_err(|e| format!("{key_path}: {e}"))?)
+        .map_err(|e| format!("{key_path}: {e}"))?;
+    if fixture.algo != "ed25519" { return Err(format!("{key_path}: unsupported algo {}", fixture.algo)); }
+    let seed: [u8; 32] = B64.decode(fixture.secret_key_base64.as_bytes())
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| format!("{key_path}: secret key must be a base64 32-byte seed"))?;
+    let interval = env_or("TARIC_WITNESS_INTERVAL_SECS", "30").parse().map_err(|e| format!("TARIC_WITNESS_INTERVAL_SECS: {e}"))?;
+    Ok(Config {
+        server: env_or("TARIC_SERVER", "http://127.0.0.1:8080").trim_end_matches('/').to_string(),
+        server_key: VerifyingKey { algo: "ed25519".into(), key, key_id: None },
+        signer: Ed25519AckSigner::from_secret_key(Box::leak(fixture.signer_id.into_boxed_str()), seed),
+        state_path: env_or("TARIC_WITNESS_STATE_PATH", "witness_state.json"),
+        interval: Duration::from_secs(interval),
+    })
+}
+
+fn get_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<Option<T>, Failure> {
+    match ureq::get(url).call() {
+        Ok(r) => r.into_json().map(Some).map_err(|e| Failure::Transport(format!("{url}: {e}"))),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(e) => Err(Failure::Transport(format!("{url}: {e}"))),
+    }
+}
+
+fn evidence(ours: &Checkpoint, theirs: &Checkpoint) -> String {
+    let strip = |c: &Checkpoint| Checkpoint { cosignatures: Vec::new(), ..c.clone() };
+    serde_json::json!({ "previously_cosigned": strip(ours), "now_served": strip(theirs) }).to_string()
+}
+
+/// Check `latest` against the last co-signed checkpoint `prev`.
+fn check_consistent(cfg: &Config, prev: &Checkpoint, latest: &Checkpoint) -> Result<(), Failure> {
+    let inconsistent = |why: String| Failure::Inconsistent(format!("{why}; evidence: {}", evidence(prev, latest)));
+    if latest.tree_size < prev.tree_size {
+        return Err(inconsistent(format!("log shrank from {} to {} entries", prev.tree_size, latest.tree_size)));
+    }
+    if latest.tree_size == prev.tree_size {
+        return if latest.root_hash == prev.root_hash { Ok(()) } else { Err(inconsistent("different roots at the same tree size".into())) };
+    }
+    let url = format!("{}/proofs/consistency?first={}&second={}", cfg.server, prev.tree_size, latest.tree_size);
+    let resp: ConsistencyResponse = get_json(&url)?.ok_or_else(|| Failure::Transport(format!("{url}: not found")))?;
+    if resp.first.root_hash != prev.root_hash || resp.second.root_hash != latest.root_hash {
+        return Err(inconsistent("consistency proof is for different checkpoints".into()));
+    }
+    let (first_root, second_root) = match (prev.root(), latest.root()) {
+        (Ok(a), Ok(b)) => (a, b),
+        _ => return Err(inconsistent("malformed root hash".into())),
+    };
+    resp.proof.verify(&first_root, &second_root).map_err(|e| inconsistent(format!("consistency proof rejected: {e}")))
+}
+
+/// One round: fetch, verify, co-sign, submit. Returns the checkpoint co-signed, if any.
+fn round(cfg: &Config) -> Result<Option<Checkpoint>, Failure> {
+    let Some(latest) = get_json::<Checkpoint>(&format!("{}/checkpoints/latest", cfg.server))? else { return Ok(None) };
+    latest.verify(&cfg.server_key).map_err(|e| Failure::Transport(format!("checkpoint signature: {e}")))?;
+    let prev: Option<Checkpoint> = fs::read_to_string(&cfg.state_path).ok().and_then(|s| serde_json::from_str(&s).ok());
+    // Without a previous checkpoint the first one is trusted as is.
+    if let Some(prev) = &prev { check_consistent(cfg, prev, &latest)?; }
+
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+    let cosig = Cosignature::sign(&cfg.signer, &latest, now);
+    let url = format!("{}/checkpoints/{}/cosignatures", cfg.server, latest.tree_size);
+    ureq::post(&url).send_json(&cosig).map_err(|e| Failure::Transport(format!("{url}: {e}")))?;
+
+    let state = Checkpoint { cosignatures: Vec::new(), ..latest };
+    fs::write(&cfg.state_path, serde_json::to_string(&state).expect("checkpoint serializes"))
+        .map_err(|e| Failure::Transport(format!("{}: {e}", cfg.state_path)))?;
+    Ok(Some(state))
+}
+
+fn main() {
+    let cfg = match load_config() {
+        Ok(c) => c,
+        Err(e) => { eprintln!("taric-witness: {e}"); std::process::exit(1); }
+    };
+    let once = std::env::args().any(|a| a == "--once");
+    println!("taric-witness {} watching {}", cfg.signer.signer_id(), cfg.server);
+    loop {
+        match round(&cfg) {
+            Ok(Some(cp)) => println!("co-signed checkpoint tree_size={} root={}", cp.tree_size, cp.root_hash),
+            Ok(None) => println!("no checkpoint published yet"),
+            Err(Failure::Transport(e)) => eprintln!("round failed: {e}"),
+            Err(Failure::Inconsistent(e)) => {
+                eprintln!("INCONSISTENT LOG, refusing to co-sign: {e}");
+                if once { std::process::exit(2); }
+            }
+        }
+        if once { return; }
+        std::thread::sleep(cfg.interval);
+    }
+}